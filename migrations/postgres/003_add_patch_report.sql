@@ -0,0 +1,2 @@
+ALTER TABLE discord_builds
+    ADD COLUMN IF NOT EXISTS patch_report JSONB;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS patch_profiles (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    description TEXT,
+    config JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE discord_builds ADD COLUMN IF NOT EXISTS patch_profile VARCHAR(64)
+    REFERENCES patch_profiles(name) ON UPDATE CASCADE ON DELETE SET NULL;
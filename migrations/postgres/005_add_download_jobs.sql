@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS download_jobs (
+    id SERIAL PRIMARY KEY,
+    build_hash VARCHAR(64) NOT NULL,
+    kind VARCHAR(16) NOT NULL,
+    state VARCHAR(24) NOT NULL DEFAULT 'queued',
+    assets_queued INTEGER NOT NULL DEFAULT 0,
+    assets_downloaded INTEGER NOT NULL DEFAULT 0,
+    assets_failed INTEGER NOT NULL DEFAULT 0,
+    errors JSONB NOT NULL DEFAULT '[]'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_build ON download_jobs(build_hash);
+CREATE INDEX IF NOT EXISTS idx_jobs_created ON download_jobs(created_at DESC);
@@ -0,0 +1,73 @@
+-- Same schema as migrations/postgres/001..007. JSON columns are TEXT, timestamps are
+-- CURRENT_TIMESTAMP strings, which sea-orm reads back as UTC.
+CREATE TABLE IF NOT EXISTS patch_profiles (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    description TEXT,
+    config JSON NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS discord_builds (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    build_hash VARCHAR(64) NOT NULL UNIQUE,
+    channel VARCHAR(16) NOT NULL DEFAULT 'canary',
+    build_date TIMESTAMP NOT NULL,
+    global_env JSON,
+    scripts JSON NOT NULL,
+    index_scripts JSON NOT NULL DEFAULT '[]',
+    is_patched BOOLEAN NOT NULL DEFAULT FALSE,
+    is_active BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    patch_report JSON,
+    patch_profile VARCHAR(64) REFERENCES patch_profiles(name) ON UPDATE CASCADE ON DELETE SET NULL,
+    missing_assets INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_builds_hash ON discord_builds(build_hash);
+CREATE INDEX IF NOT EXISTS idx_builds_channel ON discord_builds(channel);
+CREATE INDEX IF NOT EXISTS idx_builds_active ON discord_builds(is_active) WHERE is_active = TRUE;
+
+CREATE TABLE IF NOT EXISTS asset_cache (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    build_hash VARCHAR(64) NOT NULL REFERENCES discord_builds(build_hash),
+    asset_name VARCHAR(256) NOT NULL,
+    content_type VARCHAR(64) NOT NULL DEFAULT 'application/javascript',
+    file_size BIGINT NOT NULL DEFAULT 0,
+    is_patched BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_accessed TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(build_hash, asset_name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_assets_build ON asset_cache(build_hash);
+CREATE INDEX IF NOT EXISTS idx_assets_name ON asset_cache(asset_name);
+
+CREATE TABLE IF NOT EXISTS download_jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    build_hash VARCHAR(64) NOT NULL,
+    kind VARCHAR(16) NOT NULL,
+    state VARCHAR(24) NOT NULL DEFAULT 'queued',
+    assets_queued INTEGER NOT NULL DEFAULT 0,
+    assets_downloaded INTEGER NOT NULL DEFAULT 0,
+    assets_failed INTEGER NOT NULL DEFAULT 0,
+    errors JSON NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    started_at TIMESTAMP,
+    finished_at TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_build ON download_jobs(build_hash);
+CREATE INDEX IF NOT EXISTS idx_jobs_created ON download_jobs(created_at DESC);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    key_hash CHAR(64) NOT NULL UNIQUE,
+    scope VARCHAR(16) NOT NULL CHECK (scope IN ('read', 'admin')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TIMESTAMP
+);
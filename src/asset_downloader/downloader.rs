@@ -1,17 +1,42 @@
 use super::extractor;
+use super::manifest::{sha256_hex, AssetStatus, BuildManifest};
+use crate::cache::blob_store::BlobStore;
+use crate::cache::filesystem::{DOWNLOADING_MARKER, MANIFEST_FILE, ORIGINAL_DIR};
+use crate::cache::{precompress, FsCache};
+use crate::vencord::{BUNDLE_CSS, BUNDLE_JS};
 use anyhow::Result;
 use bytes::Bytes;
 use futures::stream::{self, StreamExt};
-use indicatif::{ProgressBar, ProgressStyle};
 use reqwest::Client;
+use serde::Serialize;
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
 
 const MAX_CONCURRENT: usize = 24;
 const MAX_FILE_IO: usize = 12;
 const MAX_RETRIES: u32 = 3;
+/// how much progress a crash can lose
+const MANIFEST_SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// running totals of a `download_build` call
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct DownloadProgress {
+    /// assets discovered but not fetched yet
+    pub queued: usize,
+    pub downloaded: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum DownloadEvent {
+    Progress(DownloadProgress),
+    AssetFailed { asset: String, error: String },
+}
 
 pub struct AssetDownloader {
     client: Client,
@@ -19,6 +44,9 @@ pub struct AssetDownloader {
     base_url: String,
     semaphore: Arc<Semaphore>,
     io_semaphore: Arc<Semaphore>,
+    store: Arc<BlobStore>,
+    events: Option<UnboundedSender<DownloadEvent>>,
+    cancel: CancellationToken,
 }
 
 impl AssetDownloader {
@@ -34,82 +62,236 @@ impl AssetDownloader {
 
         Self {
             client,
+            store: Arc::new(BlobStore::new(&cache_path)),
             cache_path,
             base_url: base_url.to_string(),
             semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT)),
             io_semaphore: Arc::new(Semaphore::new(MAX_FILE_IO)),
+            events: None,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// reports progress and per-asset failures while `download_build` runs
+    pub fn with_events(mut self, events: UnboundedSender<DownloadEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// stops `download_build` once the token is cancelled, the manifest is saved first
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    fn emit(&self, event: DownloadEvent) {
+        if let Some(ref events) = self.events {
+            let _ = events.send(event);
         }
     }
 
+    /// Downloads a build into `{hash}/original`, following asset references from the initial
+    /// scripts. Resumes from `manifest.json` when an earlier run was interrupted, and leaves the
+    /// build marked as downloading until every asset has been tried.
     pub async fn download_build(&self, build_hash: &str, initial_scripts: &[String]) -> Result<Vec<String>> {
         let build_dir = self.cache_path.join(build_hash);
-        tokio::fs::create_dir_all(&build_dir).await?;
+        let original_dir = build_dir.join(ORIGINAL_DIR);
+        tokio::fs::create_dir_all(&original_dir).await?;
+        tokio::fs::write(build_dir.join(DOWNLOADING_MARKER), b"").await?;
 
-        let mut known_assets: HashSet<String> = HashSet::new();
-        let mut queue: Vec<String> = initial_scripts.to_vec();
-        let mut downloaded: Vec<String> = Vec::new();
+        let mut manifest = BuildManifest::load(&build_dir).await?.unwrap_or_default();
+        let mut downloaded: Vec<String> = manifest.with_status(AssetStatus::Downloaded).map(str::to_string).collect();
+        let mut known_assets: HashSet<String> = downloaded.iter().cloned().collect();
+        let mut queue: Vec<String> = manifest.unfinished();
+        if !downloaded.is_empty() {
+            tracing::info!("Resuming build {}: {} assets already downloaded, {} left", build_hash, downloaded.len(), queue.len());
+        }
+        for script in initial_scripts {
+            let script = normalize_asset_name(script);
+            if manifest.track(&script, None) {
+                queue.push(script);
+            }
+        }
 
-        let pb = ProgressBar::new_spinner();
-        pb.set_style(ProgressStyle::default_spinner()
-            .template("{spinner:.green} [{elapsed}] Downloaded: {pos} | Queue: {msg}")
-            .unwrap());
+        let mut progress = DownloadProgress { downloaded: downloaded.len(), ..Default::default() };
+        let mut last_save = Instant::now();
 
         while !queue.is_empty() {
-            pb.set_message(format!("{}", queue.len()));
+            tracing::debug!("Build {}: {} downloaded, {} queued", build_hash, progress.downloaded, queue.len());
 
             let batch: Vec<String> = std::mem::take(&mut queue);
             let mut deduped: Vec<String> = Vec::new();
             for asset_name in batch {
-                let asset_name = if asset_name.contains('.') {
-                    asset_name
-                } else {
-                    format!("{}.js", asset_name)
-                };
                 if known_assets.insert(asset_name.clone()) {
                     deduped.push(asset_name);
                 }
             }
 
-            let results: Vec<(String, Result<Vec<String>>)> = stream::iter(deduped)
+            let mut remaining = deduped.len();
+            progress.queued = remaining;
+            self.emit(DownloadEvent::Progress(progress));
+
+            let mut results = stream::iter(deduped)
                 .map(|asset_name| {
                     let client = self.client.clone();
                     let base_url = self.base_url.clone();
-                    let build_dir = build_dir.clone();
+                    let original_dir = original_dir.clone();
                     let sem = self.semaphore.clone();
                     let io_sem = self.io_semaphore.clone();
+                    let store = self.store.clone();
                     async move {
                         let _permit = sem.acquire().await.unwrap();
                         let result = download_single_asset(
-                            &client, &base_url, &asset_name, &build_dir, io_sem,
+                            &client, &base_url, &asset_name, &original_dir, io_sem, &store,
                         ).await;
                         (asset_name, result)
                     }
                 })
-                .buffer_unordered(MAX_CONCURRENT)
-                .collect()
-                .await;
+                .buffer_unordered(MAX_CONCURRENT);
+
+            loop {
+                let next = tokio::select! {
+                    biased;
+                    _ = self.cancel.cancelled() => {
+                        manifest.save(&build_dir).await?;
+                        anyhow::bail!("download of build {} cancelled", build_hash);
+                    }
+                    next = results.next() => next,
+                };
+                let Some((asset_name, result)) = next else { break };
 
-            for (asset_name, result) in results {
+                remaining -= 1;
                 match result {
-                    Ok(new_refs) => {
-                        downloaded.push(asset_name);
-                        pb.inc(1);
-                        for r in new_refs {
-                            if !known_assets.contains(&r) {
+                    Ok(asset) => {
+                        for r in asset.refs {
+                            let r = normalize_asset_name(&r);
+                            if manifest.track(&r, Some(&asset_name)) {
                                 queue.push(r);
                             }
                         }
+                        manifest.record_download(&asset_name, asset.size, asset.sha256);
+                        downloaded.push(asset_name);
+                        progress.downloaded += 1;
                     }
                     Err(e) => {
                         tracing::warn!("Failed to download {}: {}", asset_name, e);
+                        manifest.set(&asset_name, AssetStatus::Failed);
+                        progress.failed += 1;
+                        self.emit(DownloadEvent::AssetFailed { asset: asset_name, error: e.to_string() });
                     }
                 }
+                progress.queued = remaining + queue.len();
+                self.emit(DownloadEvent::Progress(progress));
+
+                if last_save.elapsed() >= MANIFEST_SAVE_INTERVAL {
+                    manifest.save(&build_dir).await?;
+                    last_save = Instant::now();
+                }
             }
         }
 
-        pb.finish_with_message(format!("Done! {} assets downloaded", downloaded.len()));
+        manifest.save(&build_dir).await?;
+        tokio::fs::remove_file(build_dir.join(DOWNLOADING_MARKER)).await?;
         Ok(downloaded)
     }
+
+    /// Fetches the originals of a build cached before `original/` existed, so it can be repatched.
+    /// Such a build only has its patched files, directly in the build directory: they are moved
+    /// to `patched/` first and keep being served until the repatch replaces them. Originals come
+    /// from the blob store when another build has them. Returns how many were fetched.
+    pub async fn backfill_originals(&self, build_hash: &str) -> Result<usize> {
+        let fs_cache = FsCache::new(self.cache_path.clone());
+        if fs_cache.has_originals(build_hash) {
+            return Ok(0);
+        }
+        let build_dir = fs_cache.build_dir(build_hash);
+        let mut legacy = Vec::new();
+        let mut entries = tokio::fs::read_dir(&build_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().await?.is_file() && !name.starts_with('.') && name != MANIFEST_FILE && !name.ends_with(".tmp") {
+                legacy.push(name);
+            }
+        }
+
+        // the client mod is reinstalled by the repatch, everything else came from Discord
+        let assets: Vec<String> = legacy
+            .iter()
+            .filter(|name| !precompress::is_variant(name) && *name != BUNDLE_JS && *name != BUNDLE_CSS)
+            .cloned()
+            .collect();
+        let partial_dir = build_dir.join(format!("{}.partial", ORIGINAL_DIR));
+        tokio::fs::create_dir_all(&partial_dir).await?;
+        let results: Vec<(String, Result<FetchedAsset>)> = stream::iter(assets)
+            .map(|asset_name| {
+                let client = self.client.clone();
+                let base_url = self.base_url.clone();
+                let partial_dir = partial_dir.clone();
+                let io_sem = self.io_semaphore.clone();
+                let store = self.store.clone();
+                async move {
+                    let result = download_single_asset(
+                        &client, &base_url, &asset_name, &partial_dir, io_sem, &store,
+                    ).await;
+                    (asset_name, result)
+                }
+            })
+            .buffer_unordered(MAX_CONCURRENT)
+            .collect()
+            .await;
+        let failed: Vec<String> = results
+            .iter()
+            .filter_map(|(name, result)| result.as_ref().err().map(|e| format!("{}: {}", name, e)))
+            .collect();
+        if !failed.is_empty() {
+            // what was fetched stays in original.partial for the next attempt
+            anyhow::bail!("{} originals of build {} could not be fetched: {}", failed.len(), build_hash, failed.join(", "));
+        }
+
+        let staging = fs_cache.stage_patched(build_hash).await?;
+        for name in &legacy {
+            let from = build_dir.join(name);
+            let to = staging.join(name);
+            if tokio::fs::hard_link(&from, &to).await.is_err() {
+                tokio::fs::copy(&from, &to).await?;
+            }
+        }
+        fs_cache.publish_patched(build_hash, &staging).await?;
+        for name in &legacy {
+            let _ = tokio::fs::remove_file(build_dir.join(name)).await;
+        }
+        tokio::fs::rename(&partial_dir, fs_cache.original_dir(build_hash)).await?;
+        tracing::info!("Backfilled {} originals of build {}", results.len(), build_hash);
+        Ok(results.len())
+    }
+}
+
+/// asset references without an extension are js chunks
+fn normalize_asset_name(asset_name: &str) -> String {
+    if asset_name.contains('.') {
+        asset_name.to_string()
+    } else {
+        format!("{}.js", asset_name)
+    }
+}
+
+/// a file now on disk in `original/`
+struct FetchedAsset {
+    refs: Vec<String>,
+    size: u64,
+    sha256: String,
+}
+
+impl FetchedAsset {
+    fn new(asset_name: &str, bytes: &[u8]) -> Self {
+        let is_text = asset_name.ends_with(".js") || asset_name.ends_with(".css");
+        let refs = if is_text {
+            extractor::extract_asset_refs(&String::from_utf8_lossy(bytes)).into_iter().collect()
+        } else {
+            vec![]
+        };
+        Self { refs, size: bytes.len() as u64, sha256: sha256_hex(bytes) }
+    }
 }
 
 async fn download_single_asset(
@@ -118,21 +300,19 @@ async fn download_single_asset(
     asset_name: &str,
     build_dir: &Path,
     io_sem: Arc<Semaphore>,
-) -> Result<Vec<String>> {
+    store: &BlobStore,
+) -> Result<FetchedAsset> {
     let url = format!("{}/assets/{}", base_url, asset_name);
     let dest = build_dir.join(asset_name);
 
-    if dest.exists() {
-        if asset_name.ends_with(".js") || asset_name.ends_with(".css") {
-            let content = tokio::fs::read_to_string(&dest).await?;
-            return Ok(extractor::extract_asset_refs(&content).into_iter().collect());
-        }
-        return Ok(vec![]);
+    // Discord asset names change with their content, one another build already fetched is reused
+    if dest.exists() || store.link_by_name(asset_name, &dest).await?.is_some() {
+        let bytes = tokio::fs::read(&dest).await?;
+        return Ok(FetchedAsset::new(asset_name, &bytes));
     }
 
     let bytes = download_with_retry(client, &url, MAX_RETRIES).await?;
 
-    let is_text = asset_name.ends_with(".js") || asset_name.ends_with(".css");
     let tmp_dest = build_dir.join(format!("{}.tmp", asset_name));
 
     let _io_permit = io_sem.acquire().await.unwrap();
@@ -159,12 +339,9 @@ async fn download_single_asset(
     tokio::fs::rename(&tmp_dest, &dest).await?;
     drop(_io_permit);
 
-    if !is_text {
-        return Ok(vec![]);
-    }
-
-    let content = String::from_utf8_lossy(&bytes);
-    Ok(extractor::extract_asset_refs(&content).into_iter().collect())
+    let asset = FetchedAsset::new(asset_name, &bytes);
+    store.adopt_original(asset_name, &dest, &asset.sha256).await?;
+    Ok(asset)
 }
 
 async fn download_with_retry(client: &Client, url: &str, max_retries: u32) -> Result<Bytes> {
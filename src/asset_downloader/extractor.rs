@@ -1,5 +1,6 @@
+use crate::patcher::sourcemap::{Edit, Pass};
 use regex::Regex;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::LazyLock;
 static CHUNK_MAP_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"\d[\w]*:"([a-f0-9]{16,20})""#).unwrap()
@@ -13,6 +14,44 @@ static ASSET_URL_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"/assets/([a-zA-Z0-9]+\.[a-z0-9]{2,5})"#).unwrap()
 });
 
+/// Points asset references at renamed files. `renames` maps asset names (`{hash}.js`) to the
+/// names they are served under, chunk map entries hold the name without its `.js`/`.css`.
+pub fn rewrite_asset_refs(content: &str, renames: &HashMap<String, String>) -> String {
+    rewrite_asset_refs_with_passes(content, renames).0
+}
+
+/// `rewrite_asset_refs`, plus the replacements it made for moving a source map along.
+pub fn rewrite_asset_refs_with_passes(content: &str, renames: &HashMap<String, String>) -> (String, Vec<Pass>) {
+    let (content, chunk_maps) = replace_group(&CHUNK_MAP_RE, content, |hash| {
+        [".js", ".css"].iter().find_map(|ext| {
+            let renamed = renames.get(&format!("{}{}", hash, ext))?;
+            renamed.strip_suffix(ext).map(str::to_string)
+        })
+    });
+    let (content, exports) = replace_group(&EXPORT_RE, &content, |name| renames.get(name).cloned());
+    let (content, urls) = replace_group(&ASSET_URL_RE, &content, |name| renames.get(name).cloned());
+    let passes = [chunk_maps, exports, urls].into_iter().filter(|pass| !pass.is_empty()).collect();
+    (content, passes)
+}
+
+/// replaces the first capture group of every match `replacement` returns something for
+fn replace_group(re: &Regex, content: &str, replacement: impl Fn(&str) -> Option<String>) -> (String, Pass) {
+    let mut result = String::with_capacity(content.len());
+    let mut pass = Vec::new();
+    let mut last = 0;
+    for cap in re.captures_iter(content) {
+        let Some(group) = cap.get(1) else { continue };
+        if let Some(new) = replacement(group.as_str()) {
+            result.push_str(&content[last..group.start()]);
+            result.push_str(&new);
+            pass.push(Edit { start: group.start(), end: group.end(), len: new.len() });
+            last = group.end();
+        }
+    }
+    result.push_str(&content[last..]);
+    (result, pass)
+}
+
 pub fn extract_asset_refs(content: &str) -> HashSet<String> {
     let mut refs = HashSet::new();
 
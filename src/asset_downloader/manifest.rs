@@ -0,0 +1,140 @@
+use crate::cache::filesystem::MANIFEST_FILE;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetStatus {
+    /// referenced by a downloaded file, not fetched yet
+    Pending,
+    Downloaded,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AssetEntry {
+    pub status: AssetStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// of the file in `original/`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// of the file currently served from `patched/`, set on every (re)patch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patched_sha256: Option<String>,
+    /// first asset found referencing this one, `None` for the build's initial scripts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referenced_by: Option<String>,
+    /// name in `patched/` when `content_hashed_names` renamed it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub served_as: Option<String>,
+}
+
+impl AssetEntry {
+    fn pending(referenced_by: Option<&str>) -> Self {
+        Self {
+            status: AssetStatus::Pending,
+            size: None,
+            sha256: None,
+            patched_sha256: None,
+            referenced_by: referenced_by.map(str::to_string),
+            served_as: None,
+        }
+    }
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// Every asset of a build seen so far and whether it has been downloaded, kept in
+/// `{hash}/manifest.json` so an interrupted download picks up where it stopped.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BuildManifest {
+    pub assets: BTreeMap<String, AssetEntry>,
+}
+
+impl BuildManifest {
+    pub async fn load(build_dir: &Path) -> Result<Option<BuildManifest>> {
+        let path = build_dir.join(MANIFEST_FILE);
+        match tokio::fs::read(&path).await {
+            Ok(data) => {
+                let manifest = serde_json::from_slice(&data).with_context(|| format!("failed to parse {}", path.display()))?;
+                Ok(Some(manifest))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// written to a temp file and renamed, a crash never leaves a truncated manifest
+    pub async fn save(&self, build_dir: &Path) -> Result<()> {
+        let path = build_dir.join(MANIFEST_FILE);
+        let tmp = build_dir.join(format!("{}.tmp", MANIFEST_FILE));
+        tokio::fs::write(&tmp, serde_json::to_vec(self)?).await?;
+        tokio::fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    /// records a newly referenced asset, leaves known ones alone
+    pub fn track(&mut self, asset_name: &str, referenced_by: Option<&str>) -> bool {
+        if self.assets.contains_key(asset_name) {
+            return false;
+        }
+        self.assets.insert(asset_name.to_string(), AssetEntry::pending(referenced_by));
+        true
+    }
+
+    pub fn set(&mut self, asset_name: &str, status: AssetStatus) {
+        self.assets
+            .entry(asset_name.to_string())
+            .or_insert_with(|| AssetEntry::pending(None))
+            .status = status;
+    }
+
+    pub fn record_download(&mut self, asset_name: &str, size: u64, sha256: String) {
+        let entry = self
+            .assets
+            .entry(asset_name.to_string())
+            .or_insert_with(|| AssetEntry::pending(None));
+        entry.status = AssetStatus::Downloaded;
+        entry.size = Some(size);
+        entry.sha256 = Some(sha256);
+    }
+
+    /// assets the build references but doesn't have on disk
+    pub fn missing(&self) -> usize {
+        self.assets.values().filter(|e| e.status != AssetStatus::Downloaded).count()
+    }
+
+    pub fn status(&self, asset_name: &str) -> Option<AssetStatus> {
+        self.assets.get(asset_name).map(|e| e.status)
+    }
+
+    /// name the asset is served under, its own unless it was renamed
+    pub fn served_name<'a>(&'a self, asset_name: &'a str) -> &'a str {
+        self.assets
+            .get(asset_name)
+            .and_then(|e| e.served_as.as_deref())
+            .unwrap_or(asset_name)
+    }
+
+    pub fn with_status(&self, status: AssetStatus) -> impl Iterator<Item = &str> {
+        self.assets
+            .iter()
+            .filter(move |(_, e)| e.status == status)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// what a resumed download still has to fetch, failed assets get another try
+    pub fn unfinished(&self) -> Vec<String> {
+        self.assets
+            .iter()
+            .filter(|(_, e)| e.status != AssetStatus::Downloaded)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
@@ -1,6 +1,9 @@
 pub mod downloader;
 pub mod entry_detector;
 pub mod extractor;
+pub mod manifest;
+pub mod verify;
 
-pub use downloader::AssetDownloader;
+pub use downloader::{AssetDownloader, DownloadEvent, DownloadProgress};
 pub use entry_detector::detect_entry_scripts;
+pub use manifest::{AssetStatus, BuildManifest};
@@ -0,0 +1,79 @@
+use super::manifest::{sha256_hex, AssetStatus, BuildManifest};
+use crate::cache::FsCache;
+use anyhow::Result;
+use serde::Serialize;
+
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub build_hash: String,
+    /// assets listed in the manifest
+    pub assets: usize,
+    /// referenced by the build but never downloaded, or gone from disk since
+    pub missing: Vec<String>,
+    /// originals whose size or hash no longer matches the manifest
+    pub corrupted: Vec<String>,
+    /// served files that differ from what the last (re)patch wrote
+    pub corrupted_patched: Vec<String>,
+    /// files in `original/` the manifest doesn't know about, e.g. fetched on demand
+    pub unreferenced: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.corrupted.is_empty() && self.corrupted_patched.is_empty()
+    }
+}
+
+/// Checks a cached build against its `manifest.json`.
+pub async fn verify_build(fs_cache: &FsCache, build_hash: &str) -> Result<VerifyReport> {
+    let Some(manifest) = BuildManifest::load(&fs_cache.build_dir(build_hash)).await? else {
+        anyhow::bail!("Build {} has no manifest, download it again to record one", build_hash);
+    };
+    let original_dir = fs_cache.original_dir(build_hash);
+    let patched_dir = fs_cache.patched_dir(build_hash);
+
+    let mut report = VerifyReport {
+        build_hash: build_hash.to_string(),
+        assets: manifest.assets.len(),
+        ..Default::default()
+    };
+
+    for (name, entry) in &manifest.assets {
+        if entry.status != AssetStatus::Downloaded {
+            report.missing.push(name.clone());
+            continue;
+        }
+        let Ok(data) = tokio::fs::read(original_dir.join(name)).await else {
+            report.missing.push(name.clone());
+            continue;
+        };
+        let size_differs = entry.size.is_some_and(|size| size != data.len() as u64);
+        let hash_differs = entry.sha256.as_ref().is_some_and(|sha| *sha != sha256_hex(&data));
+        if size_differs || hash_differs {
+            report.corrupted.push(name.clone());
+        }
+
+        if let Some(ref expected) = entry.patched_sha256 {
+            match tokio::fs::read(patched_dir.join(manifest.served_name(name))).await {
+                Ok(patched) if sha256_hex(&patched) == *expected => {}
+                _ => report.corrupted_patched.push(name.clone()),
+            }
+        }
+    }
+
+    let mut entries = tokio::fs::read_dir(&original_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !entry.file_type().await?.is_file()
+            || name.ends_with(".tmp")
+            || crate::vencord::is_bundle_file(&name)
+            || manifest.assets.contains_key(&name)
+        {
+            continue;
+        }
+        report.unreferenced.push(name);
+    }
+    report.unreferenced.sort();
+
+    Ok(report)
+}
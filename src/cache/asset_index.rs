@@ -0,0 +1,211 @@
+use super::FsCache;
+use crate::asset_downloader::BuildManifest;
+use crate::db::models::asset_cache;
+use anyhow::Result;
+use sea_orm::prelude::{DateTimeWithTimeZone, Expr};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// rows per insert statement, keeps a big build under the bind parameter limit
+const INSERT_CHUNK: usize = 500;
+/// how often buffered `last_accessed` updates are written
+pub const ACCESS_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn guess_content_type(name: &str) -> &'static str {
+    if name.ends_with(".js") {
+        "application/javascript"
+    } else if name.ends_with(".css") {
+        "text/css"
+    } else if name.ends_with(".svg") {
+        "image/svg+xml"
+    } else if name.ends_with(".png") {
+        "image/png"
+    } else if name.ends_with(".woff2") {
+        "font/woff2"
+    } else if name.ends_with(".woff") {
+        "font/woff"
+    } else if name.ends_with(".wasm") {
+        "application/wasm"
+    } else if name.ends_with(".map") {
+        "application/json"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// one served file of a build
+pub struct AssetRecord {
+    pub asset_name: String,
+    pub file_size: i64,
+    pub is_patched: bool,
+}
+
+/// Upserts `asset_cache` rows, `created_at` and `last_accessed` of known assets are kept.
+/// The build must already be in `discord_builds`.
+pub async fn record_assets(db: &DatabaseConnection, build_hash: &str, assets: Vec<AssetRecord>) -> Result<()> {
+    upsert_assets(db, build_hash, &assets).await
+}
+
+/// Like `record_assets`, but the given assets become the build's whole index: rows of
+/// files that are gone (renamed away by a repatch) are deleted in the same transaction.
+pub async fn replace_assets(db: &DatabaseConnection, build_hash: &str, assets: Vec<AssetRecord>) -> Result<()> {
+    let txn = db.begin().await?;
+    upsert_assets(&txn, build_hash, &assets).await?;
+
+    let current: HashSet<&str> = assets.iter().map(|a| a.asset_name.as_str()).collect();
+    let known: Vec<String> = asset_cache::Entity::find()
+        .select_only()
+        .column(asset_cache::Column::AssetName)
+        .filter(asset_cache::Column::BuildHash.eq(build_hash))
+        .into_tuple()
+        .all(&txn)
+        .await?;
+    let stale: Vec<String> = known.into_iter().filter(|name| !current.contains(name.as_str())).collect();
+    for chunk in stale.chunks(INSERT_CHUNK) {
+        asset_cache::Entity::delete_many()
+            .filter(asset_cache::Column::BuildHash.eq(build_hash))
+            .filter(asset_cache::Column::AssetName.is_in(chunk.iter().cloned()))
+            .exec(&txn)
+            .await?;
+    }
+
+    txn.commit().await?;
+    Ok(())
+}
+
+async fn upsert_assets<C: ConnectionTrait>(conn: &C, build_hash: &str, assets: &[AssetRecord]) -> Result<()> {
+    for chunk in assets.chunks(INSERT_CHUNK) {
+        let rows = chunk.iter().map(|asset| asset_cache::ActiveModel {
+            build_hash: Set(build_hash.to_string()),
+            asset_name: Set(asset.asset_name.clone()),
+            content_type: Set(guess_content_type(&asset.asset_name).to_string()),
+            file_size: Set(asset.file_size),
+            is_patched: Set(asset.is_patched),
+            ..Default::default()
+        });
+        asset_cache::Entity::insert_many(rows)
+            .on_conflict(
+                OnConflict::columns([asset_cache::Column::BuildHash, asset_cache::Column::AssetName])
+                    .update_columns([
+                        asset_cache::Column::ContentType,
+                        asset_cache::Column::FileSize,
+                        asset_cache::Column::IsPatched,
+                    ])
+                    .to_owned(),
+            )
+            .exec_without_returning(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Records every file in a build's patched tree and forgets the ones no longer in it, run
+/// after each (re)patch. Whether a file was changed by the patches comes from the manifest,
+/// legacy builds count as unpatched.
+pub async fn index_build(db: &DatabaseConnection, fs_cache: &FsCache, build_hash: &str) -> Result<usize> {
+    let manifest = BuildManifest::load(&fs_cache.build_dir(build_hash)).await?.unwrap_or_default();
+    let by_served_name: HashMap<&str, _> = manifest
+        .assets
+        .iter()
+        .map(|(name, entry)| (manifest.served_name(name), entry))
+        .collect();
+
+    let mut assets = Vec::new();
+    let mut entries = tokio::fs::read_dir(fs_cache.patched_dir(build_hash)).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let meta = entry.metadata().await?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !meta.is_file() || name.ends_with(".tmp") || super::precompress::is_variant(&name) {
+            continue;
+        }
+        let is_patched = by_served_name
+            .get(name.as_str())
+            .is_some_and(|e| e.patched_sha256.is_some() && e.patched_sha256 != e.sha256);
+        assets.push(AssetRecord { asset_name: name, file_size: meta.len() as i64, is_patched });
+    }
+
+    let count = assets.len();
+    replace_assets(db, build_hash, assets).await?;
+    Ok(count)
+}
+
+/// Buffers asset hits so serving a file never waits on the database.
+#[derive(Default)]
+pub struct AccessTracker {
+    touched: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl AccessTracker {
+    pub fn touch(&self, build_hash: &str, asset_name: &str) {
+        let mut touched = self.touched.lock().unwrap();
+        match touched.get_mut(build_hash) {
+            Some(assets) => {
+                if !assets.contains(asset_name) {
+                    assets.insert(asset_name.to_string());
+                }
+            }
+            None => {
+                touched.insert(build_hash.to_string(), HashSet::from([asset_name.to_string()]));
+            }
+        }
+    }
+
+    /// hits recorded since the last call
+    pub fn take(&self) -> HashMap<String, HashSet<String>> {
+        std::mem::take(&mut *self.touched.lock().unwrap())
+    }
+
+    /// writes buffered hits as `last_accessed = NOW()`
+    pub async fn flush(&self, db: &DatabaseConnection) -> Result<()> {
+        for (build_hash, assets) in self.take() {
+            let assets: Vec<String> = assets.into_iter().collect();
+            for chunk in assets.chunks(INSERT_CHUNK) {
+                asset_cache::Entity::update_many()
+                    .col_expr(asset_cache::Column::LastAccessed, Expr::current_timestamp().into())
+                    .filter(asset_cache::Column::BuildHash.eq(&build_hash))
+                    .filter(asset_cache::Column::AssetName.is_in(chunk.iter().cloned()))
+                    .exec(db)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, FromQueryResult)]
+pub struct BuildUsage {
+    pub build_hash: String,
+    pub assets: i64,
+    pub patched_assets: i64,
+    pub total_size: i64,
+    pub last_accessed: Option<DateTimeWithTimeZone>,
+}
+
+/// disk usage of every indexed build, largest first
+pub async fn usage_by_build(db: &DatabaseConnection) -> Result<Vec<BuildUsage>> {
+    Ok(asset_cache::Entity::find()
+        .select_only()
+        .column(asset_cache::Column::BuildHash)
+        .column_as(Expr::cust("COUNT(*)"), "assets")
+        .column_as(Expr::cust("CAST(SUM(CASE WHEN is_patched THEN 1 ELSE 0 END) AS BIGINT)"), "patched_assets")
+        .column_as(Expr::cust("CAST(COALESCE(SUM(file_size), 0) AS BIGINT)"), "total_size")
+        .column_as(Expr::cust("MAX(last_accessed)"), "last_accessed")
+        .group_by(asset_cache::Column::BuildHash)
+        .order_by_desc(Expr::cust("total_size"))
+        .into_model::<BuildUsage>()
+        .all(db)
+        .await?)
+}
+
+/// assets nobody has requested for the longest time
+pub async fn least_recently_used(db: &DatabaseConnection, limit: u64) -> Result<Vec<asset_cache::Model>> {
+    Ok(asset_cache::Entity::find()
+        .order_by_asc(asset_cache::Column::LastAccessed)
+        .limit(limit)
+        .all(db)
+        .await?)
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// lives in the cache directory, skipped when listing builds
+pub const STORE_DIR: &str = ".store";
+
+/// What patching one original with one pipeline produced, kept next to the patched blob
+/// so a later build sharing the file can skip patching it and still report accurately.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PatchedEntry {
+    /// SHA-256 of the patched output, `None` when no patch changed the file and the original is served as is
+    pub sha256: Option<String>,
+    /// replacements per patch that applied to the file
+    pub matches: BTreeMap<String, usize>,
+}
+
+/// Content-addressed storage shared by every build. Originals are keyed by their SHA-256,
+/// patched output by the original's hash plus the pipeline fingerprint. Build directories
+/// hold hard links into the store, so a chunk shared by many builds is on disk once.
+///
+/// ```text
+/// .store/original/{sha[..2]}/{sha}
+/// .store/patched/{key[..2]}/{key}       only when the patches changed the file
+/// .store/patched/{key[..2]}/{key}.json  PatchedEntry
+/// .store/compressed/{sha[..2]}/{sha}.{br,gz}  precompressed variants of served files
+/// .store/names/{asset_name}             sha of the original, Discord asset names are content hashed
+/// ```
+#[derive(Clone)]
+pub struct BlobStore {
+    root: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new(cache_path: &Path) -> Self {
+        Self { root: cache_path.join(STORE_DIR) }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn original_path(&self, sha: &str) -> PathBuf {
+        self.root.join("original").join(&sha[..2]).join(sha)
+    }
+
+    fn patched_path(&self, key: &str) -> PathBuf {
+        self.root.join("patched").join(&key[..2]).join(key)
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.patched_path(key).with_extension("json")
+    }
+
+    fn compressed_path(&self, sha: &str, extension: &str) -> PathBuf {
+        self.root.join("compressed").join(&sha[..2]).join(format!("{}.{}", sha, extension))
+    }
+
+    fn name_path(&self, asset_name: &str) -> PathBuf {
+        self.root.join("names").join(asset_name)
+    }
+
+    /// Links the stored original of `asset_name` to `dest`, `None` if the store doesn't have it.
+    pub async fn link_by_name(&self, asset_name: &str, dest: &Path) -> Result<Option<String>> {
+        let Ok(sha) = tokio::fs::read_to_string(self.name_path(asset_name)).await else {
+            return Ok(None);
+        };
+        let blob = self.original_path(sha.trim());
+        if !blob.exists() {
+            return Ok(None);
+        }
+        link(&blob, dest).await?;
+        Ok(Some(sha.trim().to_string()))
+    }
+
+    /// Moves a freshly downloaded file into the store and leaves a link in its place.
+    /// A file the store already has is replaced by a link to the stored copy.
+    pub async fn adopt_original(&self, asset_name: &str, path: &Path, sha: &str) -> Result<()> {
+        let blob = self.original_path(sha);
+        if blob.exists() {
+            link(&blob, path).await?;
+        } else {
+            tokio::fs::create_dir_all(blob.parent().unwrap()).await?;
+            if tokio::fs::hard_link(path, &blob).await.is_err() {
+                tokio::fs::copy(path, &blob).await?;
+            }
+        }
+        let name_path = self.name_path(asset_name);
+        tokio::fs::create_dir_all(name_path.parent().unwrap()).await?;
+        tokio::fs::write(name_path, sha).await?;
+        Ok(())
+    }
+
+    pub async fn patched_entry(&self, key: &str) -> Option<PatchedEntry> {
+        let data = tokio::fs::read(self.entry_path(key)).await.ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    /// links the stored patched output to `dest`, false if it is gone
+    pub async fn link_patched(&self, key: &str, dest: &Path) -> Result<bool> {
+        let blob = self.patched_path(key);
+        if !blob.exists() {
+            return Ok(false);
+        }
+        link(&blob, dest).await?;
+        Ok(true)
+    }
+
+    /// Records a patch result. Changed output is written to the store and linked to `dest`.
+    pub async fn put_patched(&self, key: &str, entry: &PatchedEntry, data: Option<&[u8]>, dest: &Path) -> Result<()> {
+        let blob = self.patched_path(key);
+        tokio::fs::create_dir_all(blob.parent().unwrap()).await?;
+        if let Some(data) = data {
+            write_atomic(&blob, data).await?;
+            link(&blob, dest).await?;
+        }
+        write_atomic(&self.entry_path(key), &serde_json::to_vec(entry)?).await?;
+        Ok(())
+    }
+
+    /// links the stored `extension` variant of the content `sha` to `dest`, false if there is none
+    pub async fn link_compressed(&self, sha: &str, extension: &str, dest: &Path) -> Result<bool> {
+        let blob = self.compressed_path(sha, extension);
+        if !blob.exists() {
+            return Ok(false);
+        }
+        link(&blob, dest).await?;
+        Ok(true)
+    }
+
+    pub async fn put_compressed(&self, sha: &str, extension: &str, data: &[u8], dest: &Path) -> Result<()> {
+        let blob = self.compressed_path(sha, extension);
+        tokio::fs::create_dir_all(blob.parent().unwrap()).await?;
+        write_atomic(&blob, data).await?;
+        link(&blob, dest).await
+    }
+
+    /// Removes blobs no build links to anymore, run after builds were evicted.
+    /// Returns the number of bytes freed.
+    #[cfg(unix)]
+    pub async fn prune(&self) -> Result<u64> {
+        let root = self.root.clone();
+        tokio::task::spawn_blocking(move || prune_blocking(&root)).await?
+    }
+
+    /// hard link counts aren't available here, so the store is left alone
+    #[cfg(not(unix))]
+    pub async fn prune(&self) -> Result<u64> {
+        Ok(0)
+    }
+}
+
+/// patched blob key of an original under a given pipeline and set of applicable patches
+pub fn patched_key(original_sha: &str, fingerprint: &str, patches: &[&str]) -> String {
+    crate::asset_downloader::manifest::sha256_hex(
+        format!("{}\n{}\n{}", original_sha, fingerprint, patches.join(",")).as_bytes(),
+    )
+}
+
+/// replaces `dest` with a hard link to `blob`, copying where links aren't possible
+async fn link(blob: &Path, dest: &Path) -> Result<()> {
+    let _ = tokio::fs::remove_file(dest).await;
+    if tokio::fs::hard_link(blob, dest).await.is_err() {
+        tokio::fs::copy(blob, dest).await?;
+    }
+    Ok(())
+}
+
+async fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    tokio::fs::write(&tmp, data).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn prune_blocking(root: &Path) -> Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut freed = 0;
+    for kind in ["original", "patched", "compressed"] {
+        let Ok(shards) = std::fs::read_dir(root.join(kind)) else { continue };
+        for shard in shards.flatten() {
+            for blob in std::fs::read_dir(shard.path())?.flatten() {
+                let path = blob.path();
+                let meta = blob.metadata()?;
+                // entries of unchanged results have no blob of their own and are a few bytes, they stay
+                if path.extension().is_some_and(|e| e == "json" || e == "tmp") {
+                    continue;
+                }
+                if meta.nlink() == 1 {
+                    std::fs::remove_file(&path)?;
+                    let _ = std::fs::remove_file(path.with_extension("json"));
+                    freed += meta.len();
+                }
+            }
+        }
+    }
+
+    if let Ok(names) = std::fs::read_dir(root.join("names")) {
+        for name in names.flatten() {
+            let sha = std::fs::read_to_string(name.path()).unwrap_or_default();
+            let sha = sha.trim();
+            if sha.len() < 2 || !root.join("original").join(&sha[..2]).join(sha).exists() {
+                std::fs::remove_file(name.path())?;
+            }
+        }
+    }
+    Ok(freed)
+}
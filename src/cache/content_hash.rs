@@ -0,0 +1,109 @@
+use super::filesystem::{MANIFEST_FILE, PATCHED_DIR};
+use super::FsCache;
+use crate::asset_downloader::manifest::sha256_hex;
+use crate::asset_downloader::BuildManifest;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// SHA-256 of what a build serves, per asset. Reloaded whenever the manifest or the published
+/// `patched/` tree changes, so a stale entry never outlives the tree it described.
+#[derive(Default)]
+pub struct ContentHashes {
+    builds: Mutex<HashMap<String, Arc<BuildHashes>>>,
+}
+
+struct BuildHashes {
+    /// what the hashes were loaded from
+    version: TreeVersion,
+    /// by served name
+    hashes: Mutex<HashMap<String, String>>,
+    /// asset name -> served name, for assets `content_hashed_names` renamed
+    renames: Arc<HashMap<String, String>>,
+    /// served names of renamed assets
+    renamed: HashSet<String>,
+}
+
+/// `manifest.json` mtime and `patched` symlink target, either is `None` when missing. Builds
+/// without a manifest (backfilled, legacy) still get a new target on every (re)patch.
+#[derive(PartialEq)]
+struct TreeVersion {
+    manifest_modified: Option<SystemTime>,
+    patched_target: Option<PathBuf>,
+}
+
+/// what `/assets` needs to know about a served file
+#[derive(Debug)]
+pub struct ServedAsset {
+    pub sha256: String,
+    /// the name changes with the content, so it can be cached forever
+    pub immutable: bool,
+}
+
+impl ContentHashes {
+    /// Hash of the served `asset_name`. Assets the manifest doesn't cover, e.g. fetched on
+    /// demand or from legacy builds, are hashed from disk once.
+    pub async fn get(&self, fs_cache: &FsCache, build_hash: &str, asset_name: &str) -> Result<ServedAsset> {
+        let build = self.build(fs_cache, build_hash).await?;
+        let immutable = build.renamed.contains(asset_name);
+
+        if let Some(sha) = build.hashes.lock().unwrap().get(asset_name) {
+            return Ok(ServedAsset { sha256: sha.clone(), immutable });
+        }
+        let data = tokio::fs::read(fs_cache.patched_dir(build_hash).join(asset_name)).await?;
+        let sha = tokio::task::spawn_blocking(move || sha256_hex(&data)).await?;
+        build.hashes.lock().unwrap().insert(asset_name.to_string(), sha.clone());
+        Ok(ServedAsset { sha256: sha, immutable })
+    }
+
+    /// asset name -> served name of every renamed asset of a build
+    pub async fn renames(&self, fs_cache: &FsCache, build_hash: &str) -> Result<Arc<HashMap<String, String>>> {
+        Ok(self.build(fs_cache, build_hash).await?.renames.clone())
+    }
+
+    async fn build(&self, fs_cache: &FsCache, build_hash: &str) -> Result<Arc<BuildHashes>> {
+        let build_dir = fs_cache.build_dir(build_hash);
+        let version = TreeVersion {
+            manifest_modified: tokio::fs::metadata(build_dir.join(MANIFEST_FILE))
+                .await
+                .and_then(|m| m.modified())
+                .ok(),
+            patched_target: tokio::fs::read_link(build_dir.join(PATCHED_DIR)).await.ok(),
+        };
+
+        let cached = self
+            .builds
+            .lock()
+            .unwrap()
+            .get(build_hash)
+            .filter(|b| b.version == version)
+            .cloned();
+        if let Some(build) = cached {
+            return Ok(build);
+        }
+
+        let mut hashes = HashMap::new();
+        let mut renames = HashMap::new();
+        if let Some(manifest) = BuildManifest::load(&build_dir).await? {
+            for (name, entry) in manifest.assets {
+                let served = entry.served_as.clone().unwrap_or_else(|| name.clone());
+                if let Some(sha) = entry.patched_sha256 {
+                    hashes.insert(served.clone(), sha);
+                }
+                if entry.served_as.is_some() {
+                    renames.insert(name, served);
+                }
+            }
+        }
+        let build = Arc::new(BuildHashes {
+            version,
+            hashes: Mutex::new(hashes),
+            renamed: renames.values().cloned().collect(),
+            renames: Arc::new(renames),
+        });
+        self.builds.lock().unwrap().insert(build_hash.to_string(), build.clone());
+        Ok(build)
+    }
+}
@@ -1,5 +1,16 @@
+use super::blob_store::BlobStore;
+use super::precompress::Encoding;
 use anyhow::Result;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// untouched assets as downloaded from Discord
+pub const ORIGINAL_DIR: &str = "original";
+/// what gets served, always rebuilt from `original/`
+pub const PATCHED_DIR: &str = "patched";
+/// downloaded and pending assets of a build, see `BuildManifest`
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// present while a build is still being downloaded
+pub const DOWNLOADING_MARKER: &str = ".downloading";
 
 pub struct FsCache {
     base_path: PathBuf,
@@ -14,8 +25,32 @@ impl FsCache {
         self.base_path.join(build_hash)
     }
 
+    pub fn original_dir(&self, build_hash: &str) -> PathBuf {
+        self.build_dir(build_hash).join(ORIGINAL_DIR)
+    }
+
+    /// Directory assets are served from. Builds cached before originals were kept
+    /// have their (already patched) assets directly in the build directory.
+    pub fn patched_dir(&self, build_hash: &str) -> PathBuf {
+        let build_dir = self.build_dir(build_hash);
+        let patched = build_dir.join(PATCHED_DIR);
+        if !patched.exists() && !build_dir.join(ORIGINAL_DIR).exists() {
+            return build_dir;
+        }
+        patched
+    }
+
+    /// content-addressed store the build directories link into
+    pub fn blob_store(&self) -> BlobStore {
+        BlobStore::new(&self.base_path)
+    }
+
+    pub fn has_originals(&self, build_hash: &str) -> bool {
+        self.original_dir(build_hash).exists()
+    }
+
     pub async fn get_asset(&self, build_hash: &str, asset_name: &str) -> Result<Option<Vec<u8>>> {
-        let path = self.base_path.join(build_hash).join(asset_name);
+        let path = self.patched_dir(build_hash).join(asset_name);
         if path.exists() {
             let data = tokio::fs::read(&path).await?;
             Ok(Some(data))
@@ -24,16 +59,100 @@ impl FsCache {
         }
     }
 
+    /// writes into the currently served patched tree, with fresh `.br`/`.gz` siblings
     pub async fn put_asset(&self, build_hash: &str, asset_name: &str, data: &[u8]) -> Result<()> {
-        let dir = self.base_path.join(build_hash);
+        let dir = self.patched_dir(build_hash);
         tokio::fs::create_dir_all(&dir).await?;
         let path = dir.join(asset_name);
+        // unpatched files are hard links to the originals, never write through them
+        let _ = tokio::fs::remove_file(&path).await;
+        for encoding in Encoding::ALL {
+            let _ = tokio::fs::remove_file(encoding.sibling(&path)).await;
+        }
         tokio::fs::write(&path, data).await?;
+        super::precompress::precompress_asset(&path, Some(&self.blob_store())).await?;
+        Ok(())
+    }
+
+    pub async fn put_original(&self, build_hash: &str, asset_name: &str, data: &[u8]) -> Result<()> {
+        let dir = self.original_dir(build_hash);
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join(asset_name);
+        // originals are hard links into the blob store, shared with other builds
+        let _ = tokio::fs::remove_file(&path).await;
+        tokio::fs::write(path, data).await?;
         Ok(())
     }
 
+    /// false for builds that are still downloading or whose download was cancelled
     pub fn build_exists(&self, build_hash: &str) -> bool {
-        self.base_path.join(build_hash).exists()
+        let build_dir = self.build_dir(build_hash);
+        build_dir.exists() && !build_dir.join(DOWNLOADING_MARKER).exists()
+    }
+
+    /// fresh, empty directory for a new patched tree, published with `publish_patched`
+    pub async fn stage_patched(&self, build_hash: &str) -> Result<PathBuf> {
+        let generation = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let staging = self
+            .build_dir(build_hash)
+            .join(format!("{}-{}", PATCHED_DIR, generation));
+        tokio::fs::create_dir_all(&staging).await?;
+        Ok(staging)
+    }
+
+    /// Swaps a staged tree in as the served `patched/` directory and removes the previous one.
+    /// On unix `patched` is a symlink replaced with a single rename, so readers never see a
+    /// half-written or missing tree.
+    #[cfg(unix)]
+    pub async fn publish_patched(&self, build_hash: &str, staging: &Path) -> Result<()> {
+        let build_dir = self.build_dir(build_hash);
+        let link = build_dir.join(PATCHED_DIR);
+        let staging_name = staging
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("invalid staging directory {:?}", staging))?;
+
+        let mut previous = None;
+        match tokio::fs::symlink_metadata(&link).await {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                previous = tokio::fs::read_link(&link).await.ok().map(|target| build_dir.join(target));
+            }
+            Ok(_) => {
+                // a plain directory, e.g. published on another platform
+                let moved = build_dir.join(format!("{}-replaced", PATCHED_DIR));
+                let _ = tokio::fs::remove_dir_all(&moved).await;
+                tokio::fs::rename(&link, &moved).await?;
+                previous = Some(moved);
+            }
+            Err(_) => {}
+        }
+
+        let tmp_link = build_dir.join(format!(".{}-link", staging_name.to_string_lossy()));
+        let _ = tokio::fs::remove_file(&tmp_link).await;
+        tokio::fs::symlink(staging_name, &tmp_link).await?;
+        tokio::fs::rename(&tmp_link, &link).await?;
+
+        if let Some(previous) = previous {
+            if previous != staging {
+                // open file handles keep streaming from the old tree until they are done
+                let _ = tokio::fs::remove_dir_all(&previous).await;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub async fn publish_patched(&self, build_hash: &str, staging: &Path) -> Result<()> {
+        let build_dir = self.build_dir(build_hash);
+        let target = build_dir.join(PATCHED_DIR);
+        let old = build_dir.join(format!("{}-replaced", PATCHED_DIR));
+
+        if target.exists() {
+            let _ = tokio::fs::remove_dir_all(&old).await;
+            tokio::fs::rename(&target, &old).await?;
+        }
+        tokio::fs::rename(staging, &target).await?;
+        let _ = tokio::fs::remove_dir_all(&old).await;
+        Ok(())
     }
 
     pub async fn list_builds(&self) -> Result<Vec<String>> {
@@ -43,7 +162,10 @@ impl FsCache {
             while let Some(entry) = entries.next_entry().await? {
                 if entry.file_type().await?.is_dir() {
                     if let Some(name) = entry.file_name().to_str() {
-                        builds.push(name.to_string());
+                        // `.store` and other bookkeeping directories aren't builds
+                        if !name.starts_with('.') {
+                            builds.push(name.to_string());
+                        }
                     }
                 }
             }
@@ -0,0 +1,251 @@
+use super::filesystem::DOWNLOADING_MARKER;
+use super::{asset_index, FsCache};
+use crate::config::GcConfig;
+use crate::db::models::{asset_cache, discord_build};
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use sea_orm::prelude::Expr;
+use sea_orm::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// a build directory as the collector sees it
+#[derive(Debug, Clone)]
+pub struct CachedBuild {
+    pub build_hash: String,
+    pub size: u64,
+    /// newest `asset_cache.last_accessed`, `None` if nothing was ever served
+    pub last_accessed: Option<DateTime<FixedOffset>>,
+    /// `None` for directories with no `discord_builds` row
+    pub build_date: Option<DateTime<FixedOffset>>,
+    /// when the build was downloaded, stands in for `last_accessed` until it is served
+    pub downloaded_at: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvictedBuild {
+    pub build_hash: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub builds: usize,
+    pub total_bytes: u64,
+    pub evicted: Vec<EvictedBuild>,
+    pub total_bytes_after: u64,
+}
+
+/// Builds to evict, least recently served first, until the cache fits in `max_total_bytes`.
+/// Protected builds and the `keep_recent` newest builds are never picked.
+pub fn plan(builds: &[CachedBuild], protected: &HashSet<String>, config: &GcConfig) -> Vec<String> {
+    let total: u64 = builds.iter().map(|b| b.size).sum();
+    plan_by(builds, protected, config, total, |b| b.size)
+        .into_iter()
+        .map(|(build_hash, _)| build_hash)
+        .collect()
+}
+
+/// Like `plan` for a cache of `total` bytes, where `freed` tells how much evicting a build
+/// gives back after the builds picked before it. Returns the picked builds with those sizes.
+pub fn plan_by(
+    builds: &[CachedBuild],
+    protected: &HashSet<String>,
+    config: &GcConfig,
+    total: u64,
+    mut freed: impl FnMut(&CachedBuild) -> u64,
+) -> Vec<(String, u64)> {
+    if config.max_total_bytes == 0 || total <= config.max_total_bytes {
+        return Vec::new();
+    }
+
+    let mut by_date: Vec<&CachedBuild> = builds.iter().filter(|b| b.build_date.is_some()).collect();
+    by_date.sort_by_key(|b| std::cmp::Reverse(b.build_date));
+    let recent: HashSet<&str> = by_date.iter().take(config.keep_recent).map(|b| b.build_hash.as_str()).collect();
+
+    let mut candidates: Vec<&CachedBuild> = builds
+        .iter()
+        .filter(|b| !protected.contains(&b.build_hash) && !recent.contains(b.build_hash.as_str()))
+        .collect();
+    // least recently used first, a build never served was last used when it was downloaded
+    candidates.sort_by_key(|b| (b.last_accessed.or(b.downloaded_at), b.build_date));
+
+    let mut remaining = total;
+    let mut evict = Vec::new();
+    for build in candidates {
+        if remaining <= config.max_total_bytes {
+            break;
+        }
+        let size = freed(build);
+        remaining = remaining.saturating_sub(size);
+        evict.push((build.build_hash.clone(), size));
+    }
+    evict
+}
+
+/// Evicts builds per `config`, or only reports what it would evict with `dry_run`.
+/// The active build, pinned builds, `in_use` builds (being patched) and builds still
+/// downloading or not yet recorded in the database are kept.
+pub async fn collect(
+    db: &DatabaseConnection,
+    fs_cache: &FsCache,
+    config: &GcConfig,
+    in_use: &HashSet<String>,
+    dry_run: bool,
+) -> Result<GcReport> {
+    let rows = discord_build::Entity::find().all(db).await?;
+    let build_rows: HashMap<&str, &discord_build::Model> = rows.iter().map(|b| (b.build_hash.as_str(), b)).collect();
+    let last_accessed: HashMap<String, DateTime<FixedOffset>> = asset_index::usage_by_build(db)
+        .await?
+        .into_iter()
+        .filter_map(|u| u.last_accessed.map(|at| (u.build_hash, at)))
+        .collect();
+
+    let mut protected: HashSet<String> = config.pinned.iter().cloned().collect();
+    protected.extend(in_use.iter().cloned());
+    protected.extend(rows.iter().filter(|b| b.is_active).map(|b| b.build_hash.clone()));
+
+    let store_root = fs_cache.blob_store().root().to_path_buf();
+    let store_links = tokio::task::spawn_blocking(move || scan_links(&store_root)).await??;
+    let mut links = HashMap::new();
+    let mut builds = Vec::new();
+    for build_hash in fs_cache.list_builds().await? {
+        let build_dir = fs_cache.build_dir(&build_hash);
+        let row = build_rows.get(build_hash.as_str());
+        // a finished download is only recorded once its patch is done
+        if build_dir.join(DOWNLOADING_MARKER).exists() || row.is_none() {
+            protected.insert(build_hash.clone());
+        }
+        let build_links = tokio::task::spawn_blocking(move || scan_links(&build_dir)).await??;
+        builds.push(CachedBuild {
+            last_accessed: last_accessed.get(&build_hash).copied(),
+            build_date: row.map(|b| b.build_date),
+            downloaded_at: row.map(|b| b.created_at),
+            size: freed_bytes(&build_links, &HashMap::new(), &store_links),
+            build_hash: build_hash.clone(),
+        });
+        links.insert(build_hash, build_links);
+    }
+
+    // shared blobs count once, wherever they are linked from
+    let mut files: HashMap<&FileId, u64> = store_links.iter().map(|(id, l)| (id, l.len)).collect();
+    files.extend(links.values().flatten().map(|(id, l)| (id, l.len)));
+    let total_bytes = files.values().sum();
+
+    // links already dropped by the builds picked so far, a blob two of them share is freed by the second
+    let mut removed: HashMap<FileId, u64> = HashMap::new();
+    let evict = plan_by(&builds, &protected, config, total_bytes, |build| {
+        let build_links = &links[&build.build_hash];
+        let size = freed_bytes(build_links, &removed, &store_links);
+        for (id, l) in build_links {
+            *removed.entry(id.to_owned()).or_default() += l.count;
+        }
+        size
+    });
+    let mut report = GcReport {
+        dry_run,
+        builds: builds.len(),
+        total_bytes,
+        total_bytes_after: total_bytes,
+        ..Default::default()
+    };
+
+    for (build_hash, size) in evict {
+        if !dry_run {
+            if let Err(e) = evict_build(db, fs_cache, &build_hash).await {
+                tracing::error!("Failed to evict build {}: {:#}", build_hash, e);
+                continue;
+            }
+            tracing::info!("Evicted build {} ({} bytes)", build_hash, size);
+        }
+        report.total_bytes_after -= size;
+        report.evicted.push(EvictedBuild { build_hash, size });
+    }
+
+    // evicted builds held the last links to some shared blobs
+    if !dry_run && !report.evicted.is_empty() {
+        let freed = fs_cache.blob_store().prune().await?;
+        tracing::info!("Pruned {} bytes of unreferenced blobs", freed);
+    }
+    Ok(report)
+}
+
+async fn evict_build(db: &DatabaseConnection, fs_cache: &FsCache, build_hash: &str) -> Result<()> {
+    tokio::fs::remove_dir_all(fs_cache.build_dir(build_hash)).await?;
+    asset_cache::Entity::delete_many()
+        .filter(asset_cache::Column::BuildHash.eq(build_hash))
+        .exec(db)
+        .await?;
+    discord_build::Entity::update_many()
+        .col_expr(discord_build::Column::IsPatched, Expr::value(false))
+        .col_expr(discord_build::Column::UpdatedAt, Expr::current_timestamp().into())
+        .filter(discord_build::Column::BuildHash.eq(build_hash))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// bytes on disk under `path`, files hard-linked between `original/` and `patched/` count once
+pub fn dir_size(path: &Path) -> Result<u64> {
+    Ok(scan_links(path)?.values().map(|l| l.len).sum())
+}
+
+#[cfg(unix)]
+type FileId = (u64, u64);
+#[cfg(not(unix))]
+type FileId = std::path::PathBuf;
+
+/// one file of a tree and how many of its hard links are in the tree
+struct Links {
+    len: u64,
+    nlink: u64,
+    count: u64,
+}
+
+fn scan_links(path: &Path) -> Result<HashMap<FileId, Links>> {
+    let mut files: HashMap<FileId, Links> = HashMap::new();
+    if !path.exists() {
+        return Ok(files);
+    }
+    let mut stack = vec![path.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let meta = entry.path().symlink_metadata()?;
+            if meta.is_dir() {
+                stack.push(entry.path());
+            } else if meta.is_file() {
+                let (id, nlink) = file_id(&meta, &entry.path());
+                files.entry(id).or_insert(Links { len: meta.len(), nlink, count: 0 }).count += 1;
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Bytes that go away with a build: files left with no links outside it once `removed`
+/// links are gone, other than the store's own, which the prune after eviction drops.
+fn freed_bytes(build: &HashMap<FileId, Links>, removed: &HashMap<FileId, u64>, store: &HashMap<FileId, Links>) -> u64 {
+    build
+        .iter()
+        .filter(|(id, l)| {
+            let elsewhere = l.nlink.saturating_sub(l.count + removed.get(*id).copied().unwrap_or(0));
+            elsewhere <= store.get(*id).map_or(0, |s| s.count)
+        })
+        .map(|(_, l)| l.len)
+        .sum()
+}
+
+#[cfg(unix)]
+fn file_id(meta: &std::fs::Metadata, _path: &Path) -> (FileId, u64) {
+    use std::os::unix::fs::MetadataExt;
+    ((meta.dev(), meta.ino()), meta.nlink())
+}
+
+/// without link counts every path is its own file
+#[cfg(not(unix))]
+fn file_id(_meta: &std::fs::Metadata, path: &Path) -> (FileId, u64) {
+    (path.to_path_buf(), 1)
+}
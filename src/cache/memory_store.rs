@@ -0,0 +1,87 @@
+use super::store::CacheStore;
+use crate::config::BucketLimit;
+use crate::server::rate_limit::{gcra, Decision};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// writes between sweeps of expired entries, reads skip them either way
+const SWEEP_EVERY: u32 = 1024;
+
+/// In-process `CacheStore`: a TTL map and GCRA buckets, for a single instance.
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<Entries>,
+    /// theoretical arrival time per rate limit key
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Entries {
+    values: HashMap<String, (String, Instant)>,
+    writes: u32,
+}
+
+#[derive(Default)]
+struct Buckets {
+    tats: HashMap<String, u64>,
+    writes: u32,
+}
+
+impl MemoryStore {
+    /// like `rate_limit`, at a given time
+    pub fn rate_limit_at(&self, key: &str, limit: BucketLimit, now_ms: u64) -> Decision {
+        let mut buckets = self.buckets.lock().unwrap();
+        let (decision, tat) = gcra(buckets.tats.get(key).copied(), now_ms, limit);
+        if let Some(tat) = tat {
+            buckets.tats.insert(key.to_string(), tat);
+            buckets.writes += 1;
+            if buckets.writes >= SWEEP_EVERY {
+                buckets.writes = 0;
+                // a tat in the past is a full bucket, same as no entry
+                buckets.tats.retain(|_, tat| *tat > now_ms);
+            }
+        }
+        decision
+    }
+}
+
+#[async_trait]
+impl CacheStore for MemoryStore {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let entries = self.entries.lock().unwrap();
+        Ok(entries
+            .values
+            .get(key)
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(value, _)| value.clone()))
+    }
+
+    async fn set_ex(&self, key: &str, value: &str, ttl_secs: u64) -> Result<()> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.values.insert(key.to_string(), (value.to_string(), now + Duration::from_secs(ttl_secs)));
+        entries.writes += 1;
+        if entries.writes >= SWEEP_EVERY {
+            entries.writes = 0;
+            entries.values.retain(|_, (_, expires)| *expires > now);
+        }
+        Ok(())
+    }
+
+    async fn del(&self, key: &str) -> Result<()> {
+        self.entries.lock().unwrap().values.remove(key);
+        Ok(())
+    }
+
+    async fn rate_limit(&self, key: &str, limit: BucketLimit) -> Result<Decision> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+        Ok(self.rate_limit_at(key, limit, now))
+    }
+}
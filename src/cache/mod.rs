@@ -1,4 +1,11 @@
 pub mod redis_cache;
+pub mod memory_store;
+pub mod store;
 pub mod filesystem;
+pub mod asset_index;
+pub mod gc;
+pub mod blob_store;
+pub mod precompress;
+pub mod content_hash;
 
 pub use filesystem::FsCache;
@@ -1,31 +1,101 @@
+use super::store::CacheStore;
+use crate::config::BucketLimit;
+use crate::server::rate_limit::{window_and_interval, Decision};
 use anyhow::Result;
-use redis::aio::ConnectionManager;
+use async_trait::async_trait;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
 use redis::AsyncCommands;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::sync::OnceCell;
 
+/// Same accounting as `rate_limit::gcra`, atomically in Redis. Uses the Redis clock so every
+/// instance agrees. Returns allowed, remaining, reset after and retry after (ms).
+static GCRA_SCRIPT: LazyLock<redis::Script> = LazyLock::new(|| {
+    redis::Script::new(
+        r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local window = tonumber(ARGV[1])
+local interval = tonumber(ARGV[2])
+local tat = tonumber(redis.call('GET', KEYS[1])) or now
+if tat < now then tat = now end
+local new_tat = tat + interval
+if new_tat - now > window then
+    return {0, 0, tat - now, new_tat - window - now}
+end
+redis.call('SET', KEYS[1], new_tat, 'PX', new_tat - now)
+return {1, math.floor((window - (new_tat - now)) / interval), new_tat - now, 0}
+"#,
+    )
+});
+
+/// Short timeouts: a Redis outage should fall back or fail quickly, not hang requests.
 pub async fn connect(redis_url: &str) -> Result<ConnectionManager> {
     let client = redis::Client::open(redis_url)?;
-    let cm = ConnectionManager::new(client).await?;
+    let config = ConnectionManagerConfig::new()
+        .set_connection_timeout(Duration::from_secs(2))
+        .set_response_timeout(Duration::from_secs(1))
+        .set_number_of_retries(2)
+        // a multiplier on a 1s first delay, the default of 100 waits minutes before giving up
+        .set_factor(2)
+        .set_max_delay(1000);
+    let cm = ConnectionManager::new_with_config(client, config).await?;
     tracing::info!("Connected to Redis");
     Ok(cm)
 }
 
-const BUILDS_LIST_KEY: &str = "cache:builds_list";
-
-pub fn builds_list_key() -> &'static str {
-    BUILDS_LIST_KEY
+pub struct RedisStore {
+    redis_url: String,
+    conn: OnceCell<ConnectionManager>,
 }
 
-pub async fn get_cached_json(conn: &mut ConnectionManager, key: &str) -> Result<Option<String>> {
-    let result: Option<String> = conn.get(key).await?;
-    Ok(result)
-}
+impl RedisStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { redis_url: String::new(), conn: OnceCell::new_with(Some(conn)) }
+    }
+
+    /// Connects on first use, and again on the next call after a failed attempt.
+    pub fn lazy(redis_url: &str) -> Self {
+        Self { redis_url: redis_url.to_string(), conn: OnceCell::new() }
+    }
 
-pub async fn cache_json(conn: &mut ConnectionManager, key: &str, json: &str, ttl_secs: u64) -> Result<()> {
-    conn.set_ex::<_, _, ()>(key, json, ttl_secs).await?;
-    Ok(())
+    /// the manager reconnects by itself once it has connected
+    async fn conn(&self) -> Result<ConnectionManager> {
+        Ok(self.conn.get_or_try_init(|| connect(&self.redis_url)).await?.clone())
+    }
 }
 
-pub async fn invalidate_builds_cache(conn: &mut ConnectionManager) -> Result<()> {
-    conn.del::<_, ()>(BUILDS_LIST_KEY).await?;
-    Ok(())
+#[async_trait]
+impl CacheStore for RedisStore {
+    fn backend(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.conn().await?.get(key).await?)
+    }
+
+    async fn set_ex(&self, key: &str, value: &str, ttl_secs: u64) -> Result<()> {
+        self.conn().await?.set_ex::<_, _, ()>(key, value, ttl_secs).await?;
+        Ok(())
+    }
+
+    async fn del(&self, key: &str) -> Result<()> {
+        self.conn().await?.del::<_, ()>(key).await?;
+        Ok(())
+    }
+
+    async fn rate_limit(&self, key: &str, limit: BucketLimit) -> Result<Decision> {
+        let (window, interval) = window_and_interval(limit);
+        let (allowed, remaining, reset_after_ms, retry_after_ms): (u8, u32, u64, u64) =
+            GCRA_SCRIPT.key(key).arg(window).arg(interval).invoke_async(&mut self.conn().await?).await?;
+        Ok(Decision {
+            allowed: allowed == 1,
+            limit: limit.requests,
+            remaining,
+            reset_after_ms,
+            retry_after_ms,
+        })
+    }
 }
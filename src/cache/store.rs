@@ -0,0 +1,170 @@
+use super::memory_store::MemoryStore;
+use super::redis_cache::{self, RedisStore};
+use crate::config::{BucketLimit, CacheBackend};
+use crate::server::rate_limit::Decision;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const BUILDS_LIST_KEY: &str = "cache:builds_list";
+
+/// Short-lived shared state: the builds list cache and rate limit buckets.
+#[async_trait]
+pub trait CacheStore: Send + Sync {
+    fn backend(&self) -> &'static str;
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+    async fn set_ex(&self, key: &str, value: &str, ttl_secs: u64) -> Result<()>;
+    async fn del(&self, key: &str) -> Result<()>;
+    /// counts one request against `key`, see `rate_limit::gcra`
+    async fn rate_limit(&self, key: &str, limit: BucketLimit) -> Result<Decision>;
+}
+
+pub async fn invalidate_builds_cache(store: &dyn CacheStore) -> Result<()> {
+    store.del(BUILDS_LIST_KEY).await
+}
+
+/// Store for `[server] cache_backend`. `auto` starts on the in-process store when Redis
+/// doesn't answer at startup, and moves to Redis once it does.
+pub async fn connect(backend: CacheBackend, redis_url: &str) -> Result<Arc<dyn CacheStore>> {
+    match backend {
+        CacheBackend::Memory => {
+            tracing::info!("Using the in-process cache store");
+            Ok(Arc::new(MemoryStore::default()))
+        }
+        CacheBackend::Redis => Ok(Arc::new(RedisStore::new(redis_cache::connect(redis_url).await?))),
+        CacheBackend::Auto => match redis_cache::connect(redis_url).await {
+            Ok(conn) => Ok(Arc::new(FallbackStore::new(Box::new(RedisStore::new(conn))))),
+            Err(e) => {
+                let store = FallbackStore::new(Box::new(RedisStore::lazy(redis_url)));
+                store.degrade(&e);
+                Ok(Arc::new(store))
+            }
+        },
+    }
+}
+
+/// first wait before checking on a failed primary, doubled after each failed check
+const RETRY_MIN: Duration = Duration::from_secs(1);
+const RETRY_MAX: Duration = Duration::from_secs(30);
+/// read to check whether the primary is back
+const PROBE_KEY: &str = "cache:probe";
+
+/// Goes to `primary`, and to an in-process store once `primary` fails. Requests don't wait on
+/// a failed primary: a background task checks on it with backoff and switches back when it
+/// answers. Rate limits are per instance during an outage, and the builds list may be up to
+/// its TTL stale afterwards.
+pub struct FallbackStore {
+    primary: Arc<dyn CacheStore>,
+    fallback: MemoryStore,
+    degraded: Arc<AtomicBool>,
+    retry: (Duration, Duration),
+}
+
+impl FallbackStore {
+    pub fn new(primary: Box<dyn CacheStore>) -> Self {
+        Self::with_retry(primary, RETRY_MIN, RETRY_MAX)
+    }
+
+    /// checks on a failed primary after `min`, backing off up to `max`
+    pub fn with_retry(primary: Box<dyn CacheStore>, min: Duration, max: Duration) -> Self {
+        Self {
+            primary: Arc::from(primary),
+            fallback: MemoryStore::default(),
+            degraded: Arc::new(AtomicBool::new(false)),
+            retry: (min, max),
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    /// Switches to the in-process store until the primary answers again.
+    /// Only the first failure logs and starts checking on the primary.
+    pub fn degrade(&self, error: &anyhow::Error) {
+        if self.degraded.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        tracing::warn!("{} failed ({}), using the in-process cache store", self.primary.backend(), error);
+
+        let primary = self.primary.clone();
+        let degraded = self.degraded.clone();
+        let (mut wait, max) = self.retry;
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(wait).await;
+                if primary.get(PROBE_KEY).await.is_ok() {
+                    tracing::info!("{} is back, leaving the in-process cache store", primary.backend());
+                    degraded.store(false, Ordering::Relaxed);
+                    return;
+                }
+                wait = (wait * 2).min(max);
+            }
+        });
+    }
+
+    fn record<T>(&self, result: &Result<T>) {
+        if let Err(e) = result {
+            self.degrade(e);
+        }
+    }
+}
+
+#[async_trait]
+impl CacheStore for FallbackStore {
+    fn backend(&self) -> &'static str {
+        if self.is_degraded() {
+            self.fallback.backend()
+        } else {
+            self.primary.backend()
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        if self.is_degraded() {
+            return self.fallback.get(key).await;
+        }
+        let result = self.primary.get(key).await;
+        self.record(&result);
+        match result {
+            Ok(value) => Ok(value),
+            Err(_) => self.fallback.get(key).await,
+        }
+    }
+
+    async fn set_ex(&self, key: &str, value: &str, ttl_secs: u64) -> Result<()> {
+        if self.is_degraded() {
+            return self.fallback.set_ex(key, value, ttl_secs).await;
+        }
+        let result = self.primary.set_ex(key, value, ttl_secs).await;
+        self.record(&result);
+        match result {
+            Ok(()) => Ok(()),
+            Err(_) => self.fallback.set_ex(key, value, ttl_secs).await,
+        }
+    }
+
+    /// deletes from both, so nothing written during an outage outlives it
+    async fn del(&self, key: &str) -> Result<()> {
+        self.fallback.del(key).await?;
+        if !self.is_degraded() {
+            let result = self.primary.del(key).await;
+            self.record(&result);
+        }
+        Ok(())
+    }
+
+    async fn rate_limit(&self, key: &str, limit: BucketLimit) -> Result<Decision> {
+        if self.is_degraded() {
+            return self.fallback.rate_limit(key, limit).await;
+        }
+        let result = self.primary.rate_limit(key, limit).await;
+        self.record(&result);
+        match result {
+            Ok(decision) => Ok(decision),
+            Err(_) => self.fallback.rate_limit(key, limit).await,
+        }
+    }
+}
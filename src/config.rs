@@ -1,35 +1,116 @@
 use anyhow::Result;
-use serde::Deserialize;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// read at startup and again whenever the server reloads its patch config
+pub const PATCH_CONFIG_PATH: &str = "patch_config.toml";
 
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     pub database_url: String,
     pub redis_url: String,
     pub bind_addr: String,
+    /// serve the selector and admin API on their own listener instead of `bind_addr`
+    pub admin_bind_addr: Option<String>,
     pub api_base_url: String,
     pub discord_base_url: String,
     pub asset_base_url: String,
     pub github_builds_repo: String,
     pub cache_path: PathBuf,
+    /// bearer token with admin scope, on top of the keys in `api_keys`
+    pub admin_token: Option<String>,
     pub patch_config: PatchConfig,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PatchConfig {
+    /// directory of declarative find/replace patches loaded next to the built-in ones
+    #[serde(default = "default_patches_dir")]
+    pub patches_dir: PathBuf,
     pub patches: PatchToggles,
     pub branding: BrandingConfig,
     #[serde(default)]
     pub server: ServerConfig,
+    #[serde(default)]
+    pub vencord: VencordConfig,
+    #[serde(default)]
+    pub modals: ModalsConfig,
+    #[serde(default)]
+    pub gc: GcConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+}
+
+fn default_patches_dir() -> PathBuf {
+    PathBuf::from("patches.d")
 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// top-level keys a patch profile may override, everything else is instance-wide
+pub const PROFILE_KEYS: &[&str] = &["patches_dir", "patches", "branding", "modals"];
+
+impl PatchConfig {
+    pub fn load(path: &Path) -> Result<PatchConfig> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+        toml::from_str(&source).map_err(|e| anyhow::anyhow!("Invalid {}: {}", path.display(), e))
+    }
+
+    /// Layers a patch profile over this config. Objects are merged key by key,
+    /// any other value (including arrays) replaces the one underneath.
+    pub fn with_overlay(&self, overlay: &serde_json::Value) -> Result<PatchConfig> {
+        let Some(fields) = overlay.as_object() else {
+            anyhow::bail!("profile config must be a JSON object");
+        };
+        if let Some(key) = fields.keys().find(|k| !PROFILE_KEYS.contains(&k.as_str())) {
+            anyhow::bail!("profile cannot override {:?}, allowed keys are {:?}", key, PROFILE_KEYS);
+        }
+
+        let mut merged = serde_json::to_value(self)?;
+        merge_json(&mut merged, overlay);
+        Ok(serde_json::from_value(merged)?)
+    }
+}
+
+fn merge_json(base: &mut serde_json::Value, overlay: &serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ServerConfig {
     pub trust_proxy_headers: bool,
     pub rate_limit_enabled: bool,
+    /// limit of the `proxy` bucket, i.e. `/api`
     pub rate_limit_requests: u32,
     pub rate_limit_window_secs: u32,
+    /// let requests through when Redis can't be reached, otherwise answer 503
+    pub rate_limit_fail_open: bool,
+    /// the other buckets
+    pub rate_limits: RateLimits,
+    /// reload `patch_config.toml` and `patches_dir` when they change on disk (SIGHUP always works on unix)
+    pub watch_config: bool,
+    /// repatch the active build after a successful reload
+    pub repatch_active_on_reload: bool,
+    /// where the builds list cache and rate limits live
+    pub cache_backend: CacheBackend,
+    /// never contact Discord or GitHub, assets that aren't cached are answered with 404
+    pub offline: bool,
+    /// where the build management API is mounted, `/api` belongs to the Discord API proxy
+    pub admin_prefix: String,
+    /// redirect the management routes that used to live under `/api` to `admin_prefix`
+    pub legacy_api_redirects: bool,
 }
 
 impl Default for ServerConfig {
@@ -39,11 +120,162 @@ impl Default for ServerConfig {
             rate_limit_enabled: false,
             rate_limit_requests: 60,
             rate_limit_window_secs: 60,
+            rate_limit_fail_open: true,
+            rate_limits: RateLimits::default(),
+            watch_config: true,
+            repatch_active_on_reload: false,
+            cache_backend: CacheBackend::Auto,
+            offline: false,
+            admin_prefix: "/_ug2/api".into(),
+            legacy_api_redirects: true,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Refuses an outbound request in offline mode, `what` says which one.
+    pub fn ensure_online(&self, what: &str) -> Result<()> {
+        if self.offline {
+            anyhow::bail!("offline mode is on, refusing to {}", what);
         }
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheBackend {
+    /// Redis while it is reachable, in-process otherwise
+    #[default]
+    Auto,
+    /// Redis only, errors are errors (see `rate_limit_fail_open`)
+    Redis,
+    /// in-process only, for single instance deployments without Redis
+    Memory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BucketLimit {
+    pub requests: u32,
+    pub window_secs: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimits {
+    /// the whole management API
+    pub admin: BucketLimit,
+    /// starting downloads, on top of `admin`
+    pub downloads: BucketLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            admin: BucketLimit { requests: 120, window_secs: 60 },
+            downloads: BucketLimit { requests: 10, window_secs: 3600 },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// require an API key or `ADMIN_TOKEN` for `/api`, the Discord API proxy is never affected
+    pub enabled: bool,
+    /// let anyone call `GET /_ug2/api/builds`
+    pub public_builds_list: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            public_builds_list: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GcConfig {
+    /// collect in the background every `interval_mins`, `ug2-client gc` works either way
+    pub enabled: bool,
+    pub interval_mins: u64,
+    /// evict builds until the cache fits, 0 means no limit
+    pub max_total_bytes: u64,
+    /// the most recent builds by build date are never evicted
+    pub keep_recent: usize,
+    /// builds that are never evicted, on top of the active one
+    pub pinned: Vec<String>,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_mins: 60,
+            max_total_bytes: 0,
+            keep_recent: 3,
+            pinned: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VencordConfig {
+    /// URL or local path of the browser bundle (Vencord's or Equicord's `browser.js`)
+    pub bundle: String,
+    /// expected SHA-256 of the bundle, required when it is a URL
+    pub bundle_sha256: Option<String>,
+    /// stylesheet shipped next to the bundle, skipped when unset
+    pub stylesheet: Option<String>,
+    /// expected SHA-256 of the stylesheet, required when it is a URL
+    pub stylesheet_sha256: Option<String>,
+    /// localStorage key the client mod reads its settings from
+    pub settings_key: String,
+    /// plugins enabled for users who haven't configured them yet
+    pub plugins: Vec<String>,
+}
+
+impl Default for VencordConfig {
+    fn default() -> Self {
+        Self {
+            bundle: "https://github.com/Vendicated/Vencord/releases/download/devbuild/browser.js".into(),
+            bundle_sha256: None,
+            stylesheet: Some("https://github.com/Vendicated/Vencord/releases/download/devbuild/browser.css".into()),
+            stylesheet_sha256: None,
+            settings_key: "VencordSettings".into(),
+            plugins: Vec::new(),
+        }
+    }
+}
+
+/// modal families `remove_modals` can suppress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModalFamily {
+    NitroUpsell,
+    NewFeature,
+    AgeGate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModalsConfig {
+    pub remove: Vec<ModalFamily>,
+}
+
+impl Default for ModalsConfig {
+    fn default() -> Self {
+        Self {
+            remove: vec![ModalFamily::NitroUpsell, ModalFamily::NewFeature, ModalFamily::AgeGate],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PatchToggles {
     pub nitro_rebranding: bool,
     pub discord_rebranding: bool,
@@ -64,9 +296,27 @@ pub struct PatchToggles {
     pub cdn_redirect: bool,
     #[serde(default)]
     pub cdn_bypass: bool,
+    /// serve patched JS/CSS under content-derived names so browsers can cache them forever
+    #[serde(default)]
+    pub content_hashed_names: bool,
+    #[serde(default)]
+    pub source_maps: SourceMapMode,
+}
+
+/// what happens to source maps once the code they describe was patched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceMapMode {
+    /// Discord's maps as they are, slightly off wherever a patch changed the code
+    #[default]
+    Serve,
+    /// drop `sourceMappingURL` comments so devtools don't load mismatched maps
+    Strip,
+    /// move the mappings to where the patched code ended up
+    Rewrite,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrandingConfig {
     pub instance_name: String,
     pub instance_url: String,
@@ -111,8 +361,7 @@ pub fn extract_host(url: &str) -> Option<&str> {
 
 impl AppConfig {
     pub fn load() -> Result<Self> {
-        let patch_config_str = std::fs::read_to_string("patch_config.toml")?;
-        let patch_config: PatchConfig = toml::from_str(&patch_config_str)?;
+        let patch_config = PatchConfig::load(Path::new(PATCH_CONFIG_PATH))?;
         let resolved = ResolvedUrls::from_config(
             &patch_config.branding,
             std::env::var("DISCORD_BASE_URL").ok(),
@@ -124,11 +373,13 @@ impl AppConfig {
             database_url: std::env::var("DATABASE_URL")?,
             redis_url: std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".into()),
             bind_addr: std::env::var("BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:3000".into()),
+            admin_bind_addr: std::env::var("ADMIN_BIND_ADDR").ok().filter(|a| !a.is_empty()),
             api_base_url: resolved.api_base_url,
             discord_base_url: resolved.discord_base_url,
             asset_base_url: resolved.asset_base_url,
             github_builds_repo: std::env::var("GITHUB_BUILDS_REPO").unwrap_or_else(|_| "Discord-Build-Logger/Builds".into()),
             cache_path: PathBuf::from(std::env::var("CACHE_PATH").unwrap_or_else(|_| "./assets/cache".into())),
+            admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
             patch_config,
         })
     }
@@ -216,6 +467,47 @@ mod tests {
         assert_eq!(urls.asset_base_url, "https://assets.example.com");
     }
 
+    fn patch_config() -> PatchConfig {
+        toml::from_str(include_str!("../patch_config.toml")).unwrap()
+    }
+
+    #[test]
+    fn load_reports_the_broken_file() {
+        let path = std::env::temp_dir().join(format!("ug2-broken-config-{}.toml", std::process::id()));
+        std::fs::write(&path, "[patches\n").unwrap();
+        let err = PatchConfig::load(&path).unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.contains(&path.display().to_string()));
+
+        let config = PatchConfig::load(Path::new(PATCH_CONFIG_PATH)).unwrap();
+        assert!(config.server.watch_config);
+        assert!(!config.server.repatch_active_on_reload);
+    }
+
+    #[test]
+    fn profile_overlay_merges_nested_keys() {
+        let base = patch_config();
+        let merged = base
+            .with_overlay(&serde_json::json!({
+                "patches": { "remove_qr_login": !base.patches.remove_qr_login },
+                "modals": { "remove": ["age_gate"] },
+            }))
+            .unwrap();
+
+        assert_eq!(merged.patches.remove_qr_login, !base.patches.remove_qr_login);
+        assert_eq!(merged.patches.fast_identify, base.patches.fast_identify);
+        assert_eq!(merged.modals.remove, vec![ModalFamily::AgeGate]);
+        assert_eq!(merged.branding.instance_name, base.branding.instance_name);
+    }
+
+    #[test]
+    fn profile_overlay_rejects_instance_settings_and_bad_values() {
+        let base = patch_config();
+        assert!(base.with_overlay(&serde_json::json!({ "server": { "rate_limit_enabled": true } })).is_err());
+        assert!(base.with_overlay(&serde_json::json!({ "patches": { "fast_identify": "yes" } })).is_err());
+        assert!(base.with_overlay(&serde_json::json!(["patches"])).is_err());
+    }
+
     #[test]
     fn cdn_url_does_not_affect_asset_base_url() {
         // cdn_url is only used for GLOBAL_ENV CDN_HOST injection, not for asset fetching
@@ -1,21 +1,104 @@
 pub mod models;
 
 use anyhow::Result;
-use sea_orm::{ConnectOptions, Database, DatabaseConnection, ConnectionTrait};
+use sea_orm::{
+    ConnectOptions, ConnectionTrait, Database, DatabaseBackend, DatabaseConnection, Statement, TransactionTrait,
+};
+use std::collections::HashSet;
+use std::time::Duration;
 
+/// `(name, postgres, sqlite)`, applied in order and recorded in `schema_migrations`.
+/// SQLite starts from the current schema, so it has no file for most of the history.
+const MIGRATIONS: &[(&str, Option<&str>, Option<&str>)] = &[
+    (
+        "001_initial",
+        Some(include_str!("../../migrations/postgres/001_initial.sql")),
+        Some(include_str!("../../migrations/sqlite/001_initial.sql")),
+    ),
+    ("002_add_index_scripts", Some(include_str!("../../migrations/postgres/002_add_index_scripts.sql")), None),
+    ("003_add_patch_report", Some(include_str!("../../migrations/postgres/003_add_patch_report.sql")), None),
+    ("004_add_patch_profiles", Some(include_str!("../../migrations/postgres/004_add_patch_profiles.sql")), None),
+    ("005_add_download_jobs", Some(include_str!("../../migrations/postgres/005_add_download_jobs.sql")), None),
+    ("006_add_missing_assets", Some(include_str!("../../migrations/postgres/006_add_missing_assets.sql")), None),
+    ("007_add_api_keys", Some(include_str!("../../migrations/postgres/007_add_api_keys.sql")), None),
+];
+
+/// `postgres://...` or `sqlite://path/to/ug2.db` (created if missing), `sqlite::memory:` for tests
 pub async fn connect(database_url: &str) -> Result<DatabaseConnection> {
-    let mut opts = ConnectOptions::new(database_url);
-    opts.max_connections(10)
-        .min_connections(1)
-        .idle_timeout(std::time::Duration::from_secs(300));
+    let database_url = sqlite_url(database_url);
+    let mut opts = ConnectOptions::new(&database_url);
+    if database_url.contains(":memory:") || database_url.contains("mode=memory") {
+        // every connection would get its own empty database
+        opts.max_connections(1)
+            .min_connections(1)
+            .idle_timeout(Duration::from_secs(u32::MAX as u64))
+            .max_lifetime(Duration::from_secs(u32::MAX as u64));
+    } else if database_url.starts_with("sqlite:") {
+        // SQLite takes one writer at a time anyway
+        opts.max_connections(4)
+            .min_connections(1)
+            .idle_timeout(Duration::from_secs(300));
+    } else {
+        opts.max_connections(10)
+            .min_connections(1)
+            .idle_timeout(Duration::from_secs(300));
+    }
     let db = Database::connect(opts).await?;
-    tracing::info!("Connected to database");
+    tracing::info!("Connected to database ({:?})", db.get_database_backend());
     Ok(db)
 }
 
+/// adds `mode=rwc` to SQLite file URLs without a mode, so a missing database file is created
+pub fn sqlite_url(database_url: &str) -> String {
+    if !database_url.starts_with("sqlite:") || database_url.contains(":memory:") || database_url.contains("mode=") {
+        return database_url.to_string();
+    }
+    let separator = if database_url.contains('?') { '&' } else { '?' };
+    format!("{}{}mode=rwc", database_url, separator)
+}
+
 pub async fn run_migrations(db: &DatabaseConnection) -> Result<()> {
-    db.execute_unprepared(include_str!("../../migrations/001_initial.sql")).await?;
-    db.execute_unprepared(include_str!("../../migrations/002_add_index_scripts.sql")).await?;
-    tracing::info!("Migrations applied");
+    let backend = db.get_database_backend();
+    db.execute_unprepared(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            name VARCHAR(128) PRIMARY KEY,
+            applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .await?;
+    let applied: HashSet<String> = db
+        .query_all(Statement::from_string(backend, "SELECT name FROM schema_migrations"))
+        .await?
+        .iter()
+        .map(|row| row.try_get::<String>("", "name"))
+        .collect::<Result<_, _>>()?;
+
+    let mut count = 0;
+    for (name, postgres, sqlite) in MIGRATIONS {
+        if applied.contains(*name) {
+            continue;
+        }
+        let sql = match backend {
+            DatabaseBackend::Postgres => *postgres,
+            DatabaseBackend::Sqlite => *sqlite,
+            DatabaseBackend::MySql => anyhow::bail!("MySQL is not supported, use Postgres or SQLite"),
+        };
+        let txn = db.begin().await?;
+        if let Some(sql) = sql {
+            txn.execute_unprepared(sql).await?;
+        }
+        txn.execute(Statement::from_sql_and_values(
+            backend,
+            match backend {
+                DatabaseBackend::Postgres => "INSERT INTO schema_migrations (name) VALUES ($1)",
+                _ => "INSERT INTO schema_migrations (name) VALUES (?)",
+            },
+            [(*name).into()],
+        ))
+        .await?;
+        txn.commit().await?;
+        count += 1;
+    }
+    tracing::info!("Migrations applied ({} new)", count);
     Ok(())
 }
@@ -18,6 +18,10 @@ pub mod discord_build {
         pub index_scripts: Json,
         pub is_patched: bool,
         pub is_active: bool,
+        pub patch_report: Option<Json>,
+        pub patch_profile: Option<String>,
+        /// assets the build references that couldn't be downloaded
+        pub missing_assets: i32,
         pub created_at: DateTimeWithTimeZone,
         pub updated_at: DateTimeWithTimeZone,
     }
@@ -73,3 +77,81 @@ pub mod asset_cache {
 
     impl ActiveModelBehavior for ActiveModel {}
 }
+
+pub mod patch_profile {
+    use super::*;
+
+    /// a named overlay over the global patch config, attached to builds through `discord_builds.patch_profile`
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+    #[sea_orm(table_name = "patch_profiles")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+        #[sea_orm(unique)]
+        pub name: String,
+        pub description: Option<String>,
+        pub config: Json,
+        pub created_at: DateTimeWithTimeZone,
+        pub updated_at: DateTimeWithTimeZone,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+pub mod download_job {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+    #[sea_orm(table_name = "download_jobs")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+        pub build_hash: String,
+        /// `download` or `fetch_current`
+        pub kind: String,
+        /// see `server::jobs::JobState`
+        pub state: String,
+        pub assets_queued: i32,
+        pub assets_downloaded: i32,
+        pub assets_failed: i32,
+        pub errors: Json,
+        pub created_at: DateTimeWithTimeZone,
+        pub started_at: Option<DateTimeWithTimeZone>,
+        pub finished_at: Option<DateTimeWithTimeZone>,
+        pub updated_at: DateTimeWithTimeZone,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+pub mod api_key {
+    use super::*;
+
+    /// a key for the management API, only its SHA-256 is stored
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
+    #[sea_orm(table_name = "api_keys")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+        #[sea_orm(unique)]
+        pub name: String,
+        #[sea_orm(unique)]
+        #[serde(skip_serializing)]
+        pub key_hash: String,
+        /// `read` or `admin`, see `server::auth::Scope`
+        pub scope: String,
+        pub created_at: DateTimeWithTimeZone,
+        pub last_used_at: Option<DateTimeWithTimeZone>,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
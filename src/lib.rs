@@ -5,3 +5,4 @@ pub mod asset_downloader;
 pub mod patcher;
 pub mod cache;
 pub mod server;
+pub mod vencord;
@@ -23,12 +23,20 @@ async fn main() -> Result<()> {
         match args[1].as_str() {
             "clone" => return run_clone(&config).await,
             "import" => return run_import(&config, args.get(2).map(|s| s.as_str())).await,
+            "verify" => return run_verify(&config, args.get(2).map(|s| s.as_str())).await,
+            "gc" => return run_gc(&config, args.iter().any(|a| a == "--dry-run")).await,
+            "create-key" => return run_create_key(&config, args.get(2), args.get(3)).await,
+            "revoke-key" => return run_revoke_key(&config, args.get(2)).await,
             other => {
                 eprintln!("Unknown command: {}", other);
                 eprintln!("Usage:");
                 eprintln!("  ug2-client              Start the HTTP server");
                 eprintln!("  ug2-client clone        Clone the Discord Build Logger repo (you should already have it, look at data/builds-repo), use this if you didn't download ug2-client from the repo.");
                 eprintln!("  ug2-client import [dir] Import builds from cloned repo into DB");
+                eprintln!("  ug2-client verify <hash> Check a cached build against its manifest");
+                eprintln!("  ug2-client gc [--dry-run] Evict builds per the [gc] settings in patch_config.toml");
+                eprintln!("  ug2-client create-key <name> [read|admin] Create an API key (read-only by default)");
+                eprintln!("  ug2-client revoke-key <name> Delete an API key");
                 std::process::exit(1);
             }
         }
@@ -37,15 +45,16 @@ async fn main() -> Result<()> {
     let db = db::connect(&config.database_url).await?;
     tracing::info!("Database connected");
 
-    let redis = cache::redis_cache::connect(&config.redis_url).await?;
-    tracing::info!("Redis connected");
+    let cache_store = cache::store::connect(config.patch_config.server.cache_backend, &config.redis_url).await?;
+    tracing::info!("Cache store: {}", cache_store.backend());
 
-    server::run(config, db, redis).await?;
+    server::run(config, db, cache_store).await?;
 
     Ok(())
 }
 
 async fn run_clone(config: &config::AppConfig) -> Result<()> {
+    config.patch_config.server.ensure_online("clone the builds repo")?;
     let repo_url = format!("https://github.com/{}.git", config.github_builds_repo);
     let target_dir = std::path::Path::new("./data/builds-repo");
 
@@ -191,3 +200,90 @@ async fn run_import(config: &config::AppConfig, data_dir: Option<&str>) -> Resul
     );
     Ok(())
 }
+
+async fn run_verify(config: &config::AppConfig, build_hash: Option<&str>) -> Result<()> {
+    let Some(build_hash) = build_hash else {
+        anyhow::bail!("Usage: ug2-client verify <hash>");
+    };
+    let fs_cache = cache::FsCache::new(config.cache_path.clone());
+    if !fs_cache.build_dir(build_hash).exists() {
+        anyhow::bail!("Build {} is not in the cache", build_hash);
+    }
+
+    let report = asset_downloader::verify::verify_build(&fs_cache, build_hash).await?;
+    let sections = [
+        ("missing", &report.missing),
+        ("corrupted", &report.corrupted),
+        ("corrupted (patched)", &report.corrupted_patched),
+        ("unreferenced", &report.unreferenced),
+    ];
+    for (label, assets) in sections {
+        for asset in assets {
+            println!("{:<20} {}", label, asset);
+        }
+    }
+    println!(
+        "{}: {} assets, {} missing, {} corrupted, {} corrupted after patching, {} unreferenced",
+        build_hash,
+        report.assets,
+        report.missing.len(),
+        report.corrupted.len(),
+        report.corrupted_patched.len(),
+        report.unreferenced.len(),
+    );
+
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+async fn run_gc(config: &config::AppConfig, dry_run: bool) -> Result<()> {
+    let gc = &config.patch_config.gc;
+    if gc.max_total_bytes == 0 {
+        tracing::warn!("[gc] max_total_bytes is 0, nothing will be evicted");
+    }
+
+    let db = db::connect(&config.database_url).await?;
+    db::run_migrations(&db).await?;
+    let fs_cache = cache::FsCache::new(config.cache_path.clone());
+
+    // a running server's jobs aren't visible from here, its download markers and missing rows still are
+    let report = cache::gc::collect(&db, &fs_cache, gc, &std::collections::HashSet::new(), dry_run).await?;
+    for build in &report.evicted {
+        println!("{} {} ({} MB)", if dry_run { "would evict" } else { "evicted" }, build.build_hash, build.size / 1_000_000);
+    }
+    println!(
+        "{} builds, {} MB before, {} MB after",
+        report.builds,
+        report.total_bytes / 1_000_000,
+        report.total_bytes_after / 1_000_000,
+    );
+    Ok(())
+}
+
+async fn run_create_key(config: &config::AppConfig, name: Option<&String>, scope: Option<&String>) -> Result<()> {
+    let name = name.context("Usage: ug2-client create-key <name> [read|admin]")?;
+    let scope = match scope {
+        Some(scope) => server::auth::Scope::parse(scope).context("scope must be read or admin")?,
+        None => server::auth::Scope::Read,
+    };
+
+    let db = db::connect(&config.database_url).await?;
+    db::run_migrations(&db).await?;
+    let key = server::auth::create_key(&db, name, scope).await?;
+    println!("{} key {}: {}", scope.as_str(), name, key);
+    println!("It is only shown once, send it as `Authorization: Bearer <key>`");
+    Ok(())
+}
+
+async fn run_revoke_key(config: &config::AppConfig, name: Option<&String>) -> Result<()> {
+    let name = name.context("Usage: ug2-client revoke-key <name>")?;
+    let db = db::connect(&config.database_url).await?;
+    db::run_migrations(&db).await?;
+    if !server::auth::revoke_key(&db, name).await? {
+        anyhow::bail!("no API key named {}", name);
+    }
+    println!("Revoked API key {}", name);
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use crate::patcher::{Patch, PatchContext};
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use serde::Deserialize;
+use std::path::Path;
+
+/// one find/replace rule as written in a `patches.d/*.toml` or `patches.d/*.json` file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PatchDefinition {
+    pub name: String,
+    /// literal text to look for, mutually exclusive with `find_regex`
+    pub find: Option<String>,
+    /// regex to look for, `replace` can use its capture groups (`$1`, `${name}`)
+    pub find_regex: Option<String>,
+    pub replace: String,
+    /// glob matched against the asset file name, e.g. `web.*.js`
+    pub files: Option<String>,
+    /// a required patch that matches nothing in a build is reported as an error
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub min_build_date: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "deserialize_date")]
+    pub max_build_date: Option<NaiveDate>,
+}
+
+/// accepts TOML's native dates (`2022-01-01`) as well as quoted `"2022-01-01"` strings
+fn deserialize_date<'de, D>(deserializer: D) -> std::result::Result<Option<NaiveDate>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum DateInput {
+        Toml(toml::value::Datetime),
+        Text(String),
+    }
+
+    let raw = match Option::<DateInput>::deserialize(deserializer)? {
+        Some(DateInput::Toml(dt)) => dt.to_string(),
+        Some(DateInput::Text(s)) => s,
+        None => return Ok(None),
+    };
+    NaiveDate::parse_from_str(&raw, "%Y-%m-%d")
+        .map(Some)
+        .map_err(|_| serde::de::Error::custom(format!("invalid date {:?}, expected YYYY-MM-DD", raw)))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DefinitionFile {
+    #[serde(default)]
+    patches: Vec<PatchDefinition>,
+}
+
+enum Matcher {
+    Literal(String),
+    Regex(Regex),
+}
+
+pub struct DeclarativePatch {
+    name: String,
+    matcher: Matcher,
+    replace: String,
+    files: Option<GlobMatcher>,
+    required: bool,
+    min_build_date: Option<NaiveDate>,
+    max_build_date: Option<NaiveDate>,
+}
+
+impl DeclarativePatch {
+    pub fn from_definition(def: PatchDefinition) -> Result<Self> {
+        if def.name.trim().is_empty() {
+            bail!("patch name must not be empty");
+        }
+
+        let matcher = match (def.find, def.find_regex) {
+            (Some(literal), None) if !literal.is_empty() => Matcher::Literal(literal),
+            (None, Some(pattern)) => Matcher::Regex(
+                Regex::new(&pattern).context(format!("invalid find_regex in patch {}", def.name))?,
+            ),
+            (Some(_), Some(_)) => bail!("patch {} sets both find and find_regex", def.name),
+            _ => bail!("patch {} needs a non-empty find or find_regex", def.name),
+        };
+
+        let files = match def.files {
+            Some(pattern) => Some(
+                Glob::new(&pattern)
+                    .context(format!("invalid files glob in patch {}", def.name))?
+                    .compile_matcher(),
+            ),
+            None => None,
+        };
+
+        if let (Some(min), Some(max)) = (def.min_build_date, def.max_build_date) {
+            if min > max {
+                bail!("patch {} has min_build_date after max_build_date", def.name);
+            }
+        }
+
+        Ok(Self {
+            name: def.name,
+            matcher,
+            replace: def.replace,
+            files,
+            required: def.required,
+            min_build_date: def.min_build_date,
+            max_build_date: def.max_build_date,
+        })
+    }
+}
+
+impl Patch for DeclarativePatch {
+    fn name(&self) -> &str { &self.name }
+
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
+        match &self.matcher {
+            Matcher::Literal(find) => ctx.replace(content, find, &self.replace),
+            Matcher::Regex(re) => ctx.replace_regex(content, re, &self.replace),
+        }
+    }
+
+    fn applies_to_file(&self, file_name: &str) -> bool {
+        self.files.as_ref().is_none_or(|glob| glob.is_match(file_name))
+    }
+
+    fn applies_to_build(&self, build_date: Option<NaiveDate>) -> bool {
+        let Some(date) = build_date else {
+            return true;
+        };
+        self.min_build_date.is_none_or(|min| date >= min)
+            && self.max_build_date.is_none_or(|max| date <= max)
+    }
+
+    fn is_required(&self) -> bool { self.required }
+
+    fn fingerprint(&self) -> String {
+        let matcher = match &self.matcher {
+            Matcher::Literal(find) => format!("find={:?}", find),
+            Matcher::Regex(re) => format!("find_regex={:?}", re.as_str()),
+        };
+        format!(
+            "{} {} replace={:?} files={:?} required={} dates={:?}..{:?}",
+            self.name,
+            matcher,
+            self.replace,
+            self.files.as_ref().map(|glob| glob.glob().glob()),
+            self.required,
+            self.min_build_date,
+            self.max_build_date,
+        )
+    }
+}
+
+/// parses one definition file, the format is picked from the extension
+pub fn parse_definitions(file_name: &str, source: &str) -> Result<Vec<PatchDefinition>> {
+    let file: DefinitionFile = if file_name.ends_with(".json") {
+        serde_json::from_str(source)?
+    } else {
+        toml::from_str(source)?
+    };
+    Ok(file.patches)
+}
+
+/// loads every `*.toml` / `*.json` file in `dir`, in file name order.
+/// A missing directory just means there are no declarative patches.
+pub fn load_dir(dir: &Path) -> Result<Vec<DeclarativePatch>> {
+    if !dir.exists() {
+        tracing::debug!("No declarative patch directory at {:?}", dir);
+        return Ok(Vec::new());
+    }
+
+    let mut paths: Vec<_> = std::fs::read_dir(dir)
+        .context(format!("Failed to read patch directory {:?}", dir))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.is_file()
+                && matches!(path.extension().and_then(|e| e.to_str()), Some("toml") | Some("json"))
+        })
+        .collect();
+    paths.sort();
+
+    let mut patches = Vec::new();
+    let mut names = std::collections::HashSet::new();
+
+    for path in paths {
+        let source = std::fs::read_to_string(&path).context(format!("Failed to read {:?}", path))?;
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let definitions = parse_definitions(&file_name, &source)
+            .context(format!("Malformed patch definition file {:?}", path))?;
+
+        for def in definitions {
+            if !names.insert(def.name.clone()) {
+                bail!("Duplicate declarative patch name {} in {:?}", def.name, path);
+            }
+            patches.push(DeclarativePatch::from_definition(def).context(format!("In {:?}", path))?);
+        }
+    }
+
+    tracing::info!("Loaded {} declarative patches from {:?}", patches.len(), dir);
+    Ok(patches)
+}
@@ -1,4 +1,10 @@
 pub mod pipeline;
 pub mod patches;
+pub mod declarative;
+pub mod report;
+pub mod profile;
+pub mod rename;
+pub mod sourcemap;
 
-pub use pipeline::{Patch, PatchPipeline};
+pub use pipeline::{Patch, PatchContext, PatchPipeline};
+pub use report::PatchReport;
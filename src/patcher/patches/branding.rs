@@ -1,4 +1,4 @@
-use crate::patcher::Patch;
+use crate::patcher::{Patch, PatchContext};
 
 pub struct NitroRebranding {
     instance_name: String,
@@ -13,19 +13,18 @@ impl NitroRebranding {
 impl Patch for NitroRebranding {
     fn name(&self) -> &str { "nitro_rebranding" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !content.contains("Nitro") {
             return content;
         }
         let name = &self.instance_name;
-        content
-            .replace("Discord Nitro", &format!("{} Premium", name))
-            .replace("\"Nitro\"", "\"Premium\"")
-            .replace("Nitro ", "Premium ")
-            .replace(" Nitro", " Premium")
-            .replace("[Nitro]", "[Premium]")
-            .replace("*Nitro*", "*Premium*")
-            .replace("\"Nitro. ", "\"Premium. ")
+        let content = ctx.replace(content, "Discord Nitro", &format!("{} Premium", name));
+        let content = ctx.replace(content, "\"Nitro\"", "\"Premium\"");
+        let content = ctx.replace(content, "Nitro ", "Premium ");
+        let content = ctx.replace(content, " Nitro", " Premium");
+        let content = ctx.replace(content, "[Nitro]", "[Premium]");
+        let content = ctx.replace(content, "*Nitro*", "*Premium*");
+        ctx.replace(content, "\"Nitro. ", "\"Premium. ")
     }
 }
 
@@ -42,17 +41,16 @@ impl DiscordRebranding {
 impl Patch for DiscordRebranding {
     fn name(&self) -> &str { "discord_rebranding" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !content.contains("Discord") {
             return content;
         }
         let name = &self.instance_name;
-        content
-            .replace(" Discord ", &format!(" {} ", name))
-            .replace("Discord ", &format!("{} ", name))
-            .replace(" Discord", &format!(" {}", name))
-            .replace("Discord's", &format!("{}'s", name))
-            .replace("*Discord*", &format!("*{}*", name))
+        let content = ctx.replace(content, " Discord ", &format!(" {} ", name));
+        let content = ctx.replace(content, "Discord ", &format!("{} ", name));
+        let content = ctx.replace(content, " Discord", &format!(" {}", name));
+        let content = ctx.replace(content, "Discord's", &format!("{}'s", name));
+        ctx.replace(content, "*Discord*", &format!("*{}*", name))
     }
 }
 
@@ -69,11 +67,12 @@ impl TitleRebranding {
 impl Patch for TitleRebranding {
     fn name(&self) -> &str { "title_rebranding" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !content.contains("isPlatformEmbedded") {
             return content;
         }
-        content.replace(
+        ctx.replace(
+            content,
             r#"isPlatformEmbedded?void 0:"Discord""#,
             &format!(r#"isPlatformEmbedded?void 0:"{}""#, self.instance_name),
         )
@@ -85,7 +84,7 @@ pub struct ServerToGuild;
 impl Patch for ServerToGuild {
     fn name(&self) -> &str { "server_to_guild" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !content.contains("Server") && !content.contains("server") {
             return content;
         }
@@ -114,8 +113,8 @@ impl Patch for ServerToGuild {
 
         let mut result = content;
         for (from, to) in replacements {
-            result = result.replace(from, to);
-            result = result.replace(&from.to_lowercase(), &to.to_lowercase());
+            result = ctx.replace(result, from, to);
+            result = ctx.replace(result, &from.to_lowercase(), &to.to_lowercase());
         }
         result
     }
@@ -1,15 +1,16 @@
-use crate::patcher::Patch;
+use crate::patcher::{Patch, PatchContext};
 
 pub struct EnableDevExperiments;
 
 impl Patch for EnableDevExperiments {
     fn name(&self) -> &str { "enable_dev_experiments" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !content.contains("DeveloperExperimentStore") {
             return content;
         }
-        content.replace(
+        ctx.replace(
+            content,
             "DeveloperExperimentStore\";isDeveloper=!1",
             "DeveloperExperimentStore\";isDeveloper=!0",
         )
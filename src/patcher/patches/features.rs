@@ -1,4 +1,4 @@
-use crate::patcher::Patch;
+use crate::patcher::{Patch, PatchContext};
 use regex::Regex;
 use std::sync::LazyLock;
 
@@ -11,13 +11,11 @@ static LS_DELETE_RE: LazyLock<Regex> = LazyLock::new(|| {
 impl Patch for PreventLocalStorageDeletion {
     fn name(&self) -> &str { "prevent_localstorage_deletion" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !LS_DELETE_RE.is_match(&content) {
             return content;
         }
-        LS_DELETE_RE
-            .replace_all(&content, "void 0")
-            .into_owned()
+        ctx.replace_regex(content, &LS_DELETE_RE, "void 0")
     }
 }
 
@@ -26,11 +24,12 @@ pub struct FastIdentifyFix;
 impl Patch for FastIdentifyFix {
     fn name(&self) -> &str { "fast_identify" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !content.contains("_doFastConnectIdentify") {
             return content;
         }
-        content.replace(
+        ctx.replace(
+            content,
             "?this._doFastConnectIdentify():this._doResumeOrIdentify()",
             "?this._doResumeOrIdentify():this._doResumeOrIdentify()",
         )
@@ -46,13 +45,11 @@ static RECONNECT_RE: LazyLock<Regex> = LazyLock::new(|| {
 impl Patch for GatewayReconnectPatch {
     fn name(&self) -> &str { "gateway_reconnect" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !RECONNECT_RE.is_match(&content) {
             return content;
         }
-        RECONNECT_RE
-            .replace_all(&content, "${1}isFastConnect=!0")
-            .into_owned()
+        ctx.replace_regex(content, &RECONNECT_RE, "${1}isFastConnect=!0")
     }
 }
 
@@ -65,11 +62,11 @@ static QR_CODE_RE: LazyLock<Regex> = LazyLock::new(|| {
 impl Patch for RemoveQrCodeLogin {
     fn name(&self) -> &str { "remove_qr_login" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !QR_CODE_RE.is_match(&content) {
             return content;
         }
-        QR_CODE_RE.replace_all(&content, "null").into_owned()
+        ctx.replace_regex(content, &QR_CODE_RE, "null")
     }
 }
 
@@ -82,12 +79,10 @@ static SELF_XSS_RE: LazyLock<Regex> = LazyLock::new(|| {
 impl Patch for NoXssWarning {
     fn name(&self) -> &str { "no_xss_warning" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !SELF_XSS_RE.is_match(&content) {
             return content;
         }
-        SELF_XSS_RE
-            .replace_all(&content, "false")
-            .into_owned()
+        ctx.replace_regex(content, &SELF_XSS_RE, "false")
     }
 }
@@ -1,4 +1,4 @@
-use crate::patcher::Patch;
+use crate::patcher::{Patch, PatchContext};
 
 pub struct SentryRedirect {
     target_url: String,
@@ -13,11 +13,12 @@ impl SentryRedirect {
 impl Patch for SentryRedirect {
     fn name(&self) -> &str { "sentry_redirect" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !content.contains("fa97a90475514c03") {
             return content;
         }
-        content.replace(
+        ctx.replace(
+            content,
             // TODO: dynamically retrieve this, it probably changed by now
             "https://fa97a90475514c03a42f80cd36d147c4@sentry.io/140984",
             &self.target_url,
@@ -38,13 +39,12 @@ impl StatusPageRedirect {
 impl Patch for StatusPageRedirect {
     fn name(&self) -> &str { "status_page_redirect" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         if !content.contains("status.discord.com") && !content.contains("discordstatus.com") {
             return content;
         }
-        content
-            .replace("status.discord.com", &self.target_url)
-            .replace("discordstatus.com", &self.target_url)
+        let content = ctx.replace(content, "status.discord.com", &self.target_url);
+        ctx.replace(content, "discordstatus.com", &self.target_url)
     }
 }
 
@@ -67,35 +67,28 @@ impl CdnRedirect {
 impl Patch for CdnRedirect {
     fn name(&self) -> &str { "cdn_redirect" }
 
-    fn apply(&self, content: String) -> String {
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
         let cdn_custom = self.cdn_host != "cdn.discordapp.com";
         let media_custom = self.media_host != "media.discordapp.net";
         if !cdn_custom && !media_custom {
             return content;
         }
+        // only the host rewrites count, a bypass path put back is not a match
         let mut result = content;
         if cdn_custom {
-            if result.contains("cdn.discordapp.com") {
-                result = result.replace("cdn.discordapp.com", &self.cdn_host);
-            }
+            result = ctx.replace(result, "cdn.discordapp.com", &self.cdn_host);
             for path in &self.bypass_paths {
                 let custom = format!("{}{}", self.cdn_host, path);
                 let original = format!("cdn.discordapp.com{}", path);
-                if result.contains(&custom) {
-                    result = result.replace(&custom, &original);
-                }
+                result = ctx.replace_uncounted(result, &custom, &original);
             }
         }
         if media_custom {
-            if result.contains("media.discordapp.net") {
-                result = result.replace("media.discordapp.net", &self.media_host);
-            }
+            result = ctx.replace(result, "media.discordapp.net", &self.media_host);
             for path in &self.bypass_paths {
                 let custom = format!("{}{}", self.media_host, path);
                 let original = format!("media.discordapp.net{}", path);
-                if result.contains(&custom) {
-                    result = result.replace(&custom, &original);
-                }
+                result = ctx.replace_uncounted(result, &custom, &original);
             }
         }
         result
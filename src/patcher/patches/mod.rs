@@ -2,3 +2,5 @@ pub mod branding;
 pub mod infrastructure;
 pub mod features;
 pub mod experiments;
+pub mod modals;
+pub mod source_maps;
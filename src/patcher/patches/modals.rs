@@ -0,0 +1,68 @@
+use crate::config::ModalFamily;
+use crate::patcher::{Patch, PatchContext};
+use regex::Regex;
+use std::sync::LazyLock;
+
+// Modals are opened by dispatching a Flux action, and the stores only react to the exact action type.
+// Renaming the type at the dispatch site (`type:"..."`) leaves the handlers in place but nothing ever reaches them.
+const SUPPRESSED_TYPE: &str = r#"type:"UG2_SUPPRESSED_${1}""#;
+
+pub fn for_family(family: ModalFamily) -> Box<dyn Patch> {
+    match family {
+        ModalFamily::NitroUpsell => Box::new(RemoveNitroUpsellModals),
+        ModalFamily::NewFeature => Box::new(RemoveNewFeatureModals),
+        ModalFamily::AgeGate => Box::new(RemoveAgeGateModals),
+    }
+}
+
+pub struct RemoveNitroUpsellModals;
+
+static NITRO_UPSELL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    // upsell/promotion actions that open something, either word order; fetch/dismiss actions feed stores
+    Regex::new(r#"type:"((?:PREMIUM|NITRO)_(?:[A-Z_]*(?:UPSELL|PROMOTION)[A-Z_]*(?:MODAL|OPEN|SHOW)|[A-Z_]*(?:MODAL|OPEN|SHOW)[A-Z_]*(?:UPSELL|PROMOTION))[A-Z_]*)""#).unwrap()
+});
+
+impl Patch for RemoveNitroUpsellModals {
+    fn name(&self) -> &str { "remove_modals.nitro_upsell" }
+
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
+        if !NITRO_UPSELL_RE.is_match(&content) {
+            return content;
+        }
+        ctx.replace_regex(content, &NITRO_UPSELL_RE, SUPPRESSED_TYPE)
+    }
+}
+
+pub struct RemoveNewFeatureModals;
+
+static NEW_FEATURE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"type:"((?:NEW_FEATURE|WHATS_NEW|CHANGE_LOG)_[A-Z_]*(?:OPEN|SHOW)[A-Z_]*)""#).unwrap()
+});
+
+impl Patch for RemoveNewFeatureModals {
+    fn name(&self) -> &str { "remove_modals.new_feature" }
+
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
+        if !NEW_FEATURE_RE.is_match(&content) {
+            return content;
+        }
+        ctx.replace_regex(content, &NEW_FEATURE_RE, SUPPRESSED_TYPE)
+    }
+}
+
+pub struct RemoveAgeGateModals;
+
+static AGE_GATE_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"type:"((?:AGE_GATE|EMAIL_VERIFICATION|PHONE_VERIFICATION)_[A-Z_]*(?:MODAL|OPEN|SHOW)[A-Z_]*)""#).unwrap()
+});
+
+impl Patch for RemoveAgeGateModals {
+    fn name(&self) -> &str { "remove_modals.age_gate" }
+
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
+        if !AGE_GATE_RE.is_match(&content) {
+            return content;
+        }
+        ctx.replace_regex(content, &AGE_GATE_RE, SUPPRESSED_TYPE)
+    }
+}
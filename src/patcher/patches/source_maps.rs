@@ -0,0 +1,20 @@
+use crate::patcher::sourcemap::SOURCE_MAPPING_URL_RE;
+use crate::patcher::{Patch, PatchContext};
+
+/// `source_maps = "strip"`
+pub struct StripSourceMaps;
+
+impl Patch for StripSourceMaps {
+    fn name(&self) -> &str { "strip_source_maps" }
+
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String {
+        if !content.contains("sourceMappingURL=") {
+            return content;
+        }
+        ctx.replace_regex(content, &SOURCE_MAPPING_URL_RE, "")
+    }
+
+    fn applies_to_file(&self, file_name: &str) -> bool {
+        file_name.ends_with(".js") || file_name.ends_with(".css")
+    }
+}
@@ -1,20 +1,142 @@
-use crate::config::PatchConfig;
-use anyhow::Result;
+use super::report::{PatchReport, PatchStats};
+use super::sourcemap::{self, Edit, Pass};
+use crate::asset_downloader::manifest::{sha256_hex, BuildManifest};
+use crate::cache::blob_store::{patched_key, BlobStore, PatchedEntry};
+use crate::cache::FsCache;
+use crate::config::{PatchConfig, SourceMapMode};
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
 
+/// Bookkeeping handed to a patch while it runs. Patches go through its replace helpers
+/// so the pipeline knows how many replacements each one made.
+#[derive(Debug, Default)]
+pub struct PatchContext {
+    matches: usize,
+    /// every replace call that changed something, what source maps are rewritten from
+    passes: Vec<Pass>,
+}
+
+impl PatchContext {
+    pub fn matches(&self) -> usize {
+        self.matches
+    }
+
+    pub fn into_passes(self) -> Vec<Pass> {
+        self.passes
+    }
+
+    pub fn replace(&mut self, content: String, from: &str, to: &str) -> String {
+        let before = self.passes.len();
+        let result = self.replace_uncounted(content, from, to);
+        if let Some(pass) = self.passes.get(before) {
+            self.matches += pass.len();
+        }
+        result
+    }
+
+    /// like `replace`, without adding to the match count, for edits that undo part of
+    /// the patch's own work
+    pub fn replace_uncounted(&mut self, content: String, from: &str, to: &str) -> String {
+        if from.is_empty() {
+            return content;
+        }
+        let mut result = String::with_capacity(content.len());
+        let mut last = 0;
+        let mut pass = Vec::new();
+        for (start, _) in content.match_indices(from) {
+            result.push_str(&content[last..start]);
+            result.push_str(to);
+            last = start + from.len();
+            pass.push(Edit { start, end: last, len: to.len() });
+        }
+        if pass.is_empty() {
+            return content;
+        }
+        self.passes.push(pass);
+        result.push_str(&content[last..]);
+        result
+    }
+
+    /// `replacement` may reference capture groups (`$1`, `${name}`)
+    pub fn replace_regex(&mut self, content: String, re: &Regex, replacement: &str) -> String {
+        let mut result = String::with_capacity(content.len());
+        let mut last = 0;
+        let mut pass = Vec::new();
+        for caps in re.captures_iter(&content) {
+            let m = caps.get(0).unwrap();
+            result.push_str(&content[last..m.start()]);
+            let expanded_at = result.len();
+            caps.expand(replacement, &mut result);
+            last = m.end();
+            pass.push(Edit { start: m.start(), end: last, len: result.len() - expanded_at });
+        }
+        if pass.is_empty() {
+            return content;
+        }
+        self.matches += pass.len();
+        self.passes.push(pass);
+        result.push_str(&content[last..]);
+        result
+    }
+}
+
 pub trait Patch: Send + Sync {
     fn name(&self) -> &str;
-    fn apply(&self, content: String) -> String;
+    fn patch(&self, content: String, ctx: &mut PatchContext) -> String;
+
+    fn apply(&self, content: String) -> String {
+        self.patch(content, &mut PatchContext::default())
+    }
+
+    /// patched content along with the number of replacements made
+    fn apply_counted(&self, content: String) -> (String, usize) {
+        let mut ctx = PatchContext::default();
+        let result = self.patch(content, &mut ctx);
+        (result, ctx.matches())
+    }
+
+    fn applies_to_file(&self, _file_name: &str) -> bool { true }
+
+    /// `None` when the build date is unknown, patches should then assume they apply
+    fn applies_to_build(&self, _build_date: Option<NaiveDate>) -> bool { true }
+
+    fn is_required(&self) -> bool { false }
+
+    /// Identifies what the patch does. Built-in patches are covered by the config they are
+    /// created from, patches defined elsewhere have to describe their rules here.
+    fn fingerprint(&self) -> String { self.name().to_string() }
 }
 
 pub struct PatchPipeline {
     patches: Vec<Box<dyn Patch>>,
+    /// changes whenever the pipeline could produce different output, keys patched blobs
+    fingerprint: String,
+    /// `content_hashed_names`
+    rename_assets: bool,
+    source_maps: SourceMapMode,
+}
+
+/// what `patch_tree` wrote
+struct PatchedTree {
+    report: PatchReport,
+    /// SHA-256 of every file whose served content differs from the original
+    changed: HashMap<String, String>,
+    /// old name -> served name, empty unless `content_hashed_names` is on
+    renames: HashMap<String, String>,
 }
 
 impl PatchPipeline {
-    pub fn new(config: &PatchConfig) -> Self {
+    pub fn new(config: &PatchConfig) -> Result<Self> {
         use super::patches;
-        let mut pipeline = Self { patches: Vec::new() };
+        let mut pipeline = Self {
+            patches: Vec::new(),
+            fingerprint: String::new(),
+            rename_assets: config.patches.content_hashed_names,
+            source_maps: config.patches.source_maps,
+        };
         let name = &config.branding.instance_name;
 
         if config.patches.nitro_rebranding {
@@ -68,49 +190,282 @@ impl PatchPipeline {
         if config.patches.no_xss_warning {
             pipeline.patches.push(Box::new(patches::features::NoXssWarning));
         }
+        if config.patches.remove_modals {
+            for family in &config.modals.remove {
+                pipeline.patches.push(patches::modals::for_family(*family));
+            }
+        }
         if config.patches.enable_dev_experiments {
             pipeline.patches.push(Box::new(patches::experiments::EnableDevExperiments));
         }
 
+        let declarative = super::declarative::load_dir(&config.patches_dir)
+            .context("Failed to load declarative patches")?;
+        for patch in declarative {
+            pipeline.patches.push(Box::new(patch));
+        }
+        if config.patches.source_maps == SourceMapMode::Strip {
+            pipeline.patches.push(Box::new(patches::source_maps::StripSourceMaps));
+        }
+
+        let mut fingerprint = format!(
+            "{}\n{}\n",
+            env!("CARGO_PKG_VERSION"),
+            serde_json::to_string(&(&config.patches, &config.branding, &config.modals))?
+        );
+        for patch in &pipeline.patches {
+            fingerprint.push_str(&patch.fingerprint());
+            fingerprint.push('\n');
+        }
+        pipeline.fingerprint = sha256_hex(fingerprint.as_bytes());
+
         tracing::info!("Patch pipeline initialized with {} patches", pipeline.patches.len());
-        pipeline
+        Ok(pipeline)
+    }
+
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    pub fn patch_content(&self, file_name: &str, content: &str, build_date: Option<NaiveDate>) -> String {
+        self.apply_all(file_name, content, build_date).0
     }
 
-    pub fn patch_content(&self, content: &str) -> String {
+    fn applicable<'a>(&'a self, file_name: &'a str, build_date: Option<NaiveDate>) -> impl Iterator<Item = &'a dyn Patch> {
+        self.patches
+            .iter()
+            .map(|patch| patch.as_ref())
+            .filter(move |patch| patch.applies_to_file(file_name) && patch.applies_to_build(build_date))
+    }
+
+    /// patched content, the replacements made by every patch that applied and where they were made
+    fn apply_all(
+        &self,
+        file_name: &str,
+        content: &str,
+        build_date: Option<NaiveDate>,
+    ) -> (String, BTreeMap<String, usize>, Vec<Pass>) {
         let mut result = content.to_string();
-        for patch in &self.patches {
-            result = patch.apply(result);
+        let mut matches = BTreeMap::new();
+        let mut passes = Vec::new();
+        for patch in self.applicable(file_name, build_date) {
+            let mut ctx = PatchContext::default();
+            result = patch.patch(result, &mut ctx);
+            matches.insert(patch.name().to_string(), ctx.matches());
+            passes.extend(ctx.into_passes());
         }
-        result
+        (result, matches, passes)
     }
 
-    pub async fn patch_build(&self, build_dir: &Path) -> Result<u32> {
-        let mut count = 0u32;
-        let mut entries = tokio::fs::read_dir(build_dir).await?;
-        let mut file_count = 0u32;
+    /// Patches a build from its pristine originals into a freshly staged tree and swaps it in.
+    /// Running it again with another config always starts from the originals, so repatching is idempotent.
+    pub async fn patch_cached_build(
+        &self,
+        fs_cache: &FsCache,
+        build_hash: &str,
+        build_date: Option<NaiveDate>,
+    ) -> Result<PatchReport> {
+        if !fs_cache.has_originals(build_hash) {
+            anyhow::bail!("Build {} has no original assets, download it again to patch it", build_hash);
+        }
+
+        let staging = fs_cache.stage_patched(build_hash).await?;
+        let store = fs_cache.blob_store();
+        let original_dir = fs_cache.original_dir(build_hash);
+        let tree = match self.patch_tree(&original_dir, &staging, build_date, Some(&store)).await {
+            Ok(result) => result,
+            Err(e) => {
+                let _ = tokio::fs::remove_dir_all(&staging).await;
+                return Err(e);
+            }
+        };
+        fs_cache.publish_patched(build_hash, &staging).await?;
+
+        // builds downloaded before manifests existed have nothing to update
+        let build_dir = fs_cache.build_dir(build_hash);
+        if let Some(mut manifest) = BuildManifest::load(&build_dir).await? {
+            for (name, entry) in manifest.assets.iter_mut() {
+                entry.patched_sha256 = tree.changed.get(name).cloned().or_else(|| entry.sha256.clone());
+                entry.served_as = tree.renames.get(name).cloned();
+            }
+            manifest.save(&build_dir).await?;
+        }
+        Ok(tree.report)
+    }
+
+    /// Writes a patched copy of every asset in `source_dir` to `dest_dir`, `source_dir` is never modified.
+    pub async fn patch_build(
+        &self,
+        source_dir: &Path,
+        dest_dir: &Path,
+        build_date: Option<NaiveDate>,
+    ) -> Result<PatchReport> {
+        Ok(self.patch_tree(source_dir, dest_dir, build_date, None).await?.report)
+    }
+
+    /// `patch_build`, plus what the manifest needs to know. With a store, files another build
+    /// already had patched the same way are linked from it instead of patched again.
+    async fn patch_tree(
+        &self,
+        source_dir: &Path,
+        dest_dir: &Path,
+        build_date: Option<NaiveDate>,
+        store: Option<&BlobStore>,
+    ) -> Result<PatchedTree> {
+        let mut changed = HashMap::new();
+        // patched hash of every patchable file, what renaming goes by
+        let mut patched_hashes = HashMap::new();
+        let mut rewritten_maps = HashSet::new();
+        let mut report = PatchReport::new();
+        for patch in &self.patches {
+            if patch.applies_to_build(build_date) {
+                report.patches.insert(
+                    patch.name().to_string(),
+                    PatchStats { required: patch.is_required(), ..Default::default() },
+                );
+            }
+        }
+
+        tokio::fs::create_dir_all(dest_dir).await?;
+        let mut entries = tokio::fs::read_dir(source_dir).await?;
 
         while let Some(entry) = entries.next_entry().await? {
             let path = entry.path();
             let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let dest = dest_dir.join(name.as_ref());
+
+            if !entry.file_type().await?.is_file() || name.ends_with(".tmp") {
+                continue;
+            }
+
+            let is_patchable = (name.ends_with(".js") || name.ends_with(".css"))
+                && !crate::vencord::is_bundle_file(&name);
+            if !is_patchable {
+                // a map rewritten for its patched file earlier
+                if !rewritten_maps.contains(name.as_ref()) {
+                    link_or_copy(&path, &dest).await?;
+                }
+                continue;
+            }
+
+            let content = tokio::fs::read_to_string(&path).await?;
+            report.files_scanned += 1;
+            let original_sha = sha256_hex(content.as_bytes());
+            let map_name = match self.source_maps {
+                SourceMapMode::Rewrite => sourcemap::map_file_name(&content).filter(|m| source_dir.join(m).exists()),
+                _ => None,
+            };
 
-            if name.ends_with(".js") || name.ends_with(".css") {
-                let content = tokio::fs::read_to_string(&path).await?;
-                let patched = self.patch_content(&content);
-                if patched != content {
-                    tokio::fs::write(&path, patched).await?;
-                    count += 1;
-                    tracing::debug!("Patched: {}", name);
+            let key = store.map(|_| {
+                let patches: Vec<&str> = self.applicable(&name, build_date).map(|p| p.name()).collect();
+                patched_key(&original_sha, &self.fingerprint, &patches)
+            });
+            // a stored result doesn't say where the replacements were, the map needs them
+            if let (Some(store), Some(key), None) = (store, key.as_deref(), &map_name) {
+                if let Some(entry) = store.patched_entry(key).await {
+                    let linked = match entry.sha256 {
+                        Some(_) => store.link_patched(key, &dest).await?,
+                        None => {
+                            link_or_copy(&path, &dest).await?;
+                            true
+                        }
+                    };
+                    if linked {
+                        for (patch, matches) in &entry.matches {
+                            report.record(patch, *matches);
+                        }
+                        if let Some(ref sha) = entry.sha256 {
+                            changed.insert(name.to_string(), sha.clone());
+                            report.files_changed += 1;
+                        }
+                        patched_hashes.insert(name.to_string(), entry.sha256.unwrap_or(original_sha));
+                        continue;
+                    }
                 }
+            }
 
-                file_count += 1;
-                // Yield to runtime every 10 files to allow allocator to reclaim memory
-                if file_count.is_multiple_of(10) {
-                    tokio::task::yield_now().await;
+            let (patched, matches, passes) = self.apply_all(&name, &content, build_date);
+            for (patch, count) in &matches {
+                report.record(patch, *count);
+            }
+            let mut entry = PatchedEntry { sha256: None, matches };
+            if patched != content {
+                let sha = sha256_hex(patched.as_bytes());
+                changed.insert(name.to_string(), sha.clone());
+                entry.sha256 = Some(sha);
+                report.files_changed += 1;
+                tracing::debug!("Patched: {}", name);
+            } else {
+                link_or_copy(&path, &dest).await?;
+            }
+            patched_hashes.insert(name.to_string(), entry.sha256.clone().unwrap_or(original_sha));
+            match (store, key) {
+                (Some(store), Some(key)) => {
+                    let data = entry.sha256.is_some().then_some(patched.as_bytes());
+                    store.put_patched(&key, &entry, data, &dest).await?;
+                }
+                _ if entry.sha256.is_some() => tokio::fs::write(&dest, &patched).await?,
+                _ => {}
+            }
+
+            if let Some(map_name) = map_name.filter(|_| entry.sha256.is_some()) {
+                let map = tokio::fs::read(source_dir.join(&map_name)).await?;
+                match sourcemap::rewrite_map(&map, &content, &patched, &passes) {
+                    Ok(rewritten) => {
+                        let map_dest = dest_dir.join(&map_name);
+                        let _ = tokio::fs::remove_file(&map_dest).await;
+                        tokio::fs::write(&map_dest, &rewritten).await?;
+                        changed.insert(map_name.clone(), sha256_hex(&rewritten));
+                        rewritten_maps.insert(map_name);
+                    }
+                    Err(e) => tracing::warn!("Serving the original source map of {}: {}", name, e),
+                }
+            }
+
+            // Yield to runtime every 10 files to allow allocator to reclaim memory
+            if report.files_scanned.is_multiple_of(10) {
+                tokio::task::yield_now().await;
+            }
+        }
+
+        let renames = if self.rename_assets {
+            let renames = super::rename::rename_tree(
+                dest_dir,
+                &self.fingerprint,
+                &patched_hashes,
+                &mut changed,
+                self.source_maps == SourceMapMode::Rewrite,
+            )
+            .await?;
+            tracing::debug!("Renamed {} assets in {:?}", renames.len(), dest_dir);
+            renames
+        } else {
+            HashMap::new()
+        };
+
+        let compressed = crate::cache::precompress::precompress_dir(dest_dir, store).await?;
+        tracing::debug!("Precompressed {} files in {:?}", compressed, dest_dir);
+
+        report.finish();
+        for (name, stats) in &report.patches {
+            if stats.replacements == 0 {
+                if stats.required {
+                    tracing::error!("Required patch {} did not match anything in {:?}", name, source_dir);
+                } else {
+                    tracing::warn!("Patch {} did not match anything in {:?}", name, source_dir);
                 }
             }
         }
 
-        tracing::info!("Patched {} files in {:?}", count, build_dir);
-        Ok(count)
+        tracing::info!("Patched {} files from {:?} into {:?}", report.files_changed, source_dir, dest_dir);
+        Ok(PatchedTree { report, changed, renames })
+    }
+}
+
+/// unchanged files are hard-linked from the originals instead of duplicated
+async fn link_or_copy(source: &Path, dest: &Path) -> Result<()> {
+    if tokio::fs::hard_link(source, dest).await.is_err() {
+        tokio::fs::copy(source, dest).await?;
     }
+    Ok(())
 }
@@ -0,0 +1,118 @@
+use super::PatchPipeline;
+use crate::config::PatchConfig;
+use crate::db::models::{discord_build, patch_profile};
+use anyhow::{Context, Result};
+use sea_orm::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A profile's config layered over the global one, and the pipeline built from it.
+#[derive(Clone)]
+pub struct CachedProfile {
+    pub config: Arc<PatchConfig>,
+    pub pipeline: Arc<PatchPipeline>,
+}
+
+/// Profiles built on first use. Lives next to the global pipeline in `LivePatching`, so a
+/// reload starts from scratch, and saving a profile drops its entry.
+#[derive(Default)]
+pub struct ProfileCache {
+    profiles: Mutex<HashMap<String, CachedProfile>>,
+}
+
+impl ProfileCache {
+    pub async fn get(&self, db: &DatabaseConnection, base: &PatchConfig, name: &str) -> Result<CachedProfile> {
+        if let Some(profile) = self.profiles.lock().unwrap().get(name) {
+            return Ok(profile.clone());
+        }
+        let config = profile_config(db, base, name).await?;
+        let profile = CachedProfile { pipeline: Arc::new(PatchPipeline::new(&config)?), config: Arc::new(config) };
+        self.profiles.lock().unwrap().insert(name.to_string(), profile.clone());
+        Ok(profile)
+    }
+
+    pub fn invalidate(&self, name: &str) {
+        self.profiles.lock().unwrap().remove(name);
+    }
+}
+
+/// Pipeline to patch a build with. Builds without a profile share the global pipeline,
+/// the others get the one built from their profile layered over the global config.
+pub async fn pipeline_for_build(
+    db: &DatabaseConnection,
+    profiles: &ProfileCache,
+    base: &PatchConfig,
+    default: &Arc<PatchPipeline>,
+    build_hash: &str,
+) -> Result<Arc<PatchPipeline>> {
+    let build = find_build(db, build_hash).await?;
+    pipeline_for_profile(db, profiles, base, default, build_hash, build.and_then(|b| b.patch_profile)).await
+}
+
+/// Patches an asset fetched on demand like a full patch of its build would: with the build's
+/// profile, and only the patches meant for its build date.
+pub async fn patch_fetched_asset(
+    db: &DatabaseConnection,
+    profiles: &ProfileCache,
+    base: &PatchConfig,
+    default: &Arc<PatchPipeline>,
+    build_hash: &str,
+    asset_name: &str,
+    content: &str,
+) -> String {
+    let build = find_build(db, build_hash).await.unwrap_or_else(|e| {
+        tracing::warn!("Failed to look up build {}: {}", build_hash, e);
+        None
+    });
+    // builds only served through a pin aren't in the database, every patch applies to them
+    let build_date = build.as_ref().map(|b| b.build_date.date_naive());
+    let profile_name = build.and_then(|b| b.patch_profile);
+    let pipeline = pipeline_for_profile(db, profiles, base, default, build_hash, profile_name)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("Falling back to the global pipeline for {}: {}", build_hash, e);
+            default.clone()
+        });
+    pipeline.patch_content(asset_name, content, build_date)
+}
+
+async fn find_build(db: &DatabaseConnection, build_hash: &str) -> Result<Option<discord_build::Model>> {
+    Ok(discord_build::Entity::find()
+        .filter(discord_build::Column::BuildHash.eq(build_hash))
+        .one(db)
+        .await?)
+}
+
+async fn pipeline_for_profile(
+    db: &DatabaseConnection,
+    profiles: &ProfileCache,
+    base: &PatchConfig,
+    default: &Arc<PatchPipeline>,
+    build_hash: &str,
+    profile_name: Option<String>,
+) -> Result<Arc<PatchPipeline>> {
+    let Some(profile_name) = profile_name else {
+        return Ok(default.clone());
+    };
+
+    tracing::debug!("Using patch profile {} for build {}", profile_name, build_hash);
+    Ok(profiles.get(db, base, &profile_name).await?.pipeline)
+}
+
+pub async fn profile_config(db: &DatabaseConnection, base: &PatchConfig, name: &str) -> Result<PatchConfig> {
+    let profile = patch_profile::Entity::find()
+        .filter(patch_profile::Column::Name.eq(name))
+        .one(db)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Patch profile {} does not exist", name))?;
+
+    base.with_overlay(&profile.config)
+        .context(format!("Invalid config in patch profile {}", name))
+}
+
+/// profile names end up in URLs and logs, keep them boring
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
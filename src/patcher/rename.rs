@@ -0,0 +1,181 @@
+use crate::asset_downloader::extractor;
+use crate::asset_downloader::manifest::sha256_hex;
+use super::sourcemap;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+/// hex digits of the suffix, `{hash}.{suffix}.js`
+const SUFFIX_LEN: usize = 8;
+
+/// `abc.js` -> `abc.{suffix}.js`
+pub fn renamed(asset_name: &str, suffix: &str) -> String {
+    match asset_name.split_once('.') {
+        Some((stem, rest)) => format!("{}.{}.{}", stem, suffix, rest),
+        None => format!("{}.{}", asset_name, suffix),
+    }
+}
+
+/// Renames patched assets in `dir` after their content and rewrites the references between them.
+/// `files` holds the SHA-256 of each asset as patched. Files sharing a stem, like a chunk's `.js`
+/// and `.css`, get the same suffix because webpack's chunk maps only hold the stem. The suffix also
+/// covers `fingerprint`, so a config change renames everything and no stale reference survives.
+///
+/// A file's final content depends on the new names of the files it references, so suffixes are
+/// derived leaves first and each one covers the suffixes of what the file references. Files that
+/// reference each other share one suffix over all of them.
+///
+/// With `rewrite_maps`, the source map next to a rewritten file is moved along with the new references.
+///
+/// Returns old name -> new name. `changed` gets the new hash of every file whose references were
+/// rewritten, and of every map moved along.
+pub async fn rename_tree(
+    dir: &Path,
+    fingerprint: &str,
+    files: &HashMap<String, String>,
+    changed: &mut HashMap<String, String>,
+    rewrite_maps: bool,
+) -> Result<HashMap<String, String>> {
+    let mut stems: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+    for (name, sha) in files {
+        stems.entry(stem(name)).or_default().push((name, sha));
+    }
+    let groups: Vec<&str> = stems.keys().copied().collect();
+    let index: HashMap<&str, usize> = groups.iter().enumerate().map(|(i, stem)| (*stem, i)).collect();
+
+    let mut references: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); groups.len()];
+    for name in files.keys() {
+        let content = tokio::fs::read_to_string(dir.join(name)).await?;
+        let from = index[stem(name)];
+        for asset_ref in extractor::extract_asset_refs(&content) {
+            if let Some(&to) = index.get(stem(&asset_ref)) {
+                references[from].insert(to);
+            }
+        }
+    }
+
+    let mut suffixes: Vec<Option<String>> = vec![None; groups.len()];
+    for component in strongly_connected(&references) {
+        let mut input = fingerprint.to_string();
+        for &group in &component {
+            let mut members = stems[groups[group]].clone();
+            members.sort();
+            for (name, sha) in members {
+                input.push_str(&format!("\n{} {}", name, sha));
+            }
+        }
+        let mut referenced = BTreeSet::new();
+        for &group in &component {
+            // components come after everything they reference, only their own members are unset
+            referenced.extend(references[group].iter().filter_map(|&to| suffixes[to].as_deref().map(|s| (groups[to], s))));
+        }
+        for (stem, suffix) in referenced {
+            input.push_str(&format!("\n-> {} {}", stem, suffix));
+        }
+        let suffix = sha256_hex(input.as_bytes())[..SUFFIX_LEN].to_string();
+        for group in component {
+            suffixes[group] = Some(suffix.clone());
+        }
+    }
+
+    let mut renames = HashMap::new();
+    for (i, stem) in groups.iter().enumerate() {
+        let suffix = suffixes[i].as_deref().unwrap_or_default();
+        for (name, _) in &stems[stem] {
+            renames.insert(name.to_string(), renamed(name, suffix));
+        }
+    }
+
+    for (name, new_name) in &renames {
+        let path = dir.join(name);
+        let content = tokio::fs::read_to_string(&path).await?;
+        let (rewritten, passes) = extractor::rewrite_asset_refs_with_passes(&content, &renames);
+        if rewritten == content {
+            // keeps the hard link into the store
+            tokio::fs::rename(&path, dir.join(new_name)).await?;
+            continue;
+        }
+        tokio::fs::write(dir.join(new_name), &rewritten).await?;
+        tokio::fs::remove_file(&path).await?;
+        changed.insert(name.clone(), sha256_hex(rewritten.as_bytes()));
+
+        let Some(map_name) = sourcemap::map_file_name(&content).filter(|_| rewrite_maps) else { continue };
+        let map_path = dir.join(&map_name);
+        let Ok(map) = tokio::fs::read(&map_path).await else { continue };
+        match sourcemap::rewrite_map(&map, &content, &rewritten, &passes) {
+            Ok(moved) => {
+                // may still be a link to the original map
+                tokio::fs::remove_file(&map_path).await?;
+                tokio::fs::write(&map_path, &moved).await?;
+                changed.insert(map_name, sha256_hex(&moved));
+            }
+            Err(e) => tracing::warn!("Source map of {} doesn't follow its renamed references: {}", name, e),
+        }
+    }
+    Ok(renames)
+}
+
+fn stem(name: &str) -> &str {
+    name.split('.').next().unwrap_or(name)
+}
+
+/// Tarjan's algorithm without recursion, a long chain of chunks would overflow the stack.
+/// Components come out after every component they reference.
+fn strongly_connected(edges: &[BTreeSet<usize>]) -> Vec<Vec<usize>> {
+    let mut next_index = 0;
+    let mut indices: Vec<Option<usize>> = vec![None; edges.len()];
+    let mut lowlinks = vec![0; edges.len()];
+    let mut on_stack = vec![false; edges.len()];
+    let mut stack = Vec::new();
+    let mut components = Vec::new();
+
+    for root in 0..edges.len() {
+        if indices[root].is_some() {
+            continue;
+        }
+        // node and the edges still to visit from it
+        let mut work = vec![(root, edges[root].iter())];
+        indices[root] = Some(next_index);
+        lowlinks[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some((node, children)) = work.last_mut() {
+            let node = *node;
+            if let Some(&child) = children.next() {
+                match indices[child] {
+                    None => {
+                        indices[child] = Some(next_index);
+                        lowlinks[child] = next_index;
+                        next_index += 1;
+                        stack.push(child);
+                        on_stack[child] = true;
+                        work.push((child, edges[child].iter()));
+                    }
+                    Some(index) if on_stack[child] => lowlinks[node] = lowlinks[node].min(index),
+                    Some(_) => {}
+                }
+                continue;
+            }
+
+            work.pop();
+            if let Some((parent, _)) = work.last() {
+                lowlinks[*parent] = lowlinks[*parent].min(lowlinks[node]);
+            }
+            if Some(lowlinks[node]) == indices[node] {
+                let mut component = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort();
+                components.push(component);
+            }
+        }
+    }
+    components
+}
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// what a `patch_build` run did, stored per build and served at `/_ug2/api/builds/{hash}/patch-report`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchReport {
+    pub generated_at: DateTime<Utc>,
+    pub files_scanned: u32,
+    pub files_changed: u32,
+    pub patches: BTreeMap<String, PatchStats>,
+    /// patches that did not match anything, usually because Discord changed the code they target
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PatchStats {
+    pub files_touched: u32,
+    pub replacements: u64,
+    pub required: bool,
+}
+
+impl PatchReport {
+    pub fn new() -> Self {
+        Self {
+            generated_at: Utc::now(),
+            files_scanned: 0,
+            files_changed: 0,
+            patches: BTreeMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, patch_name: &str, matches: usize) {
+        let stats = self.patches.entry(patch_name.to_string()).or_default();
+        if matches > 0 {
+            stats.files_touched += 1;
+            stats.replacements += matches as u64;
+        }
+    }
+
+    /// fills `warnings` once every file went through the pipeline
+    pub fn finish(&mut self) {
+        self.warnings = self
+            .patches
+            .iter()
+            .filter(|(_, stats)| stats.replacements == 0)
+            .map(|(name, stats)| {
+                if stats.required {
+                    format!("required patch {} matched nothing", name)
+                } else {
+                    format!("patch {} matched nothing", name)
+                }
+            })
+            .collect();
+    }
+}
+
+impl Default for PatchReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// `//# sourceMappingURL=...` in JS, `/*# sourceMappingURL=... */` in CSS
+pub static SOURCE_MAPPING_URL_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?m)(?://[#@] ?sourceMappingURL=(\S+)[ \t]*$|/\*[#@] ?sourceMappingURL=(\S+?)\s*\*/)"#).unwrap()
+});
+
+/// One replacement, in byte offsets of the text it was made in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    /// length of what replaced `start..end`
+    pub len: usize,
+}
+
+/// The replacements of one `replace` call, in order. Each pass works on the previous one's output.
+pub type Pass = Vec<Edit>;
+
+/// a pass with the size change of its edits summed up, `deltas[i]` covers `edits[..i]`
+struct ShiftTable<'a> {
+    edits: &'a [Edit],
+    deltas: Vec<isize>,
+}
+
+impl<'a> ShiftTable<'a> {
+    fn new(edits: &'a [Edit]) -> Self {
+        let mut deltas = Vec::with_capacity(edits.len() + 1);
+        let mut delta = 0;
+        deltas.push(0);
+        for edit in edits {
+            delta += edit.len as isize - (edit.end - edit.start) as isize;
+            deltas.push(delta);
+        }
+        Self { edits, deltas }
+    }
+
+    /// Where `offset` of the text before the pass ended up after it.
+    /// `None` inside a replaced range, except for its first byte.
+    fn shift(&self, offset: usize) -> Option<usize> {
+        let before = self.edits.partition_point(|e| e.end <= offset);
+        if let Some(edit) = self.edits.get(before) {
+            if offset > edit.start && offset < edit.end {
+                return None;
+            }
+        }
+        Some((offset as isize + self.deltas[before]) as usize)
+    }
+}
+
+/// name of the map an asset points at, when it is a sibling file
+pub fn map_file_name(content: &str) -> Option<String> {
+    let url = SOURCE_MAPPING_URL_RE
+        .captures_iter(content)
+        .last()
+        .and_then(|cap| cap.get(1).or(cap.get(2)))?
+        .as_str();
+    if url.contains('/') || url.contains(':') {
+        return None;
+    }
+    Some(url.to_string())
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_value(c: u8) -> Option<i64> {
+    BASE64.iter().position(|&b| b == c).map(|v| v as i64)
+}
+
+/// decodes one segment of base64 VLQ values
+pub fn decode_vlq(segment: &str) -> Result<Vec<i64>> {
+    let mut values = Vec::new();
+    let mut value = 0i64;
+    let mut shift = 0;
+    for c in segment.bytes() {
+        let digit = base64_value(c).with_context(|| format!("invalid VLQ character {:?}", c as char))?;
+        value += (digit & 31) << shift;
+        if digit & 32 != 0 {
+            shift += 5;
+            if shift > 60 {
+                bail!("VLQ value out of range");
+            }
+            continue;
+        }
+        values.push(if value & 1 == 1 { -(value >> 1) } else { value >> 1 });
+        value = 0;
+        shift = 0;
+    }
+    if shift != 0 {
+        bail!("truncated VLQ value");
+    }
+    Ok(values)
+}
+
+pub fn encode_vlq(out: &mut String, value: i64) {
+    let mut vlq = if value < 0 { ((-value) << 1) | 1 } else { value << 1 };
+    loop {
+        let mut digit = vlq & 31;
+        vlq >>= 5;
+        if vlq > 0 {
+            digit |= 32;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if vlq == 0 {
+            break;
+        }
+    }
+}
+
+/// one mapping with absolute values, `source` holds source index, line, column and name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub line: usize,
+    pub column: usize,
+    pub source: Option<(i64, i64, i64, Option<i64>)>,
+}
+
+pub fn decode_mappings(mappings: &str) -> Result<Vec<Mapping>> {
+    let mut decoded = Vec::new();
+    let (mut source, mut source_line, mut source_column, mut name) = (0i64, 0i64, 0i64, 0i64);
+    for (line, segments) in mappings.split(';').enumerate() {
+        let mut column = 0i64;
+        for segment in segments.split(',').filter(|s| !s.is_empty()) {
+            let fields = decode_vlq(segment)?;
+            column += fields[0];
+            let mapped = match fields.len() {
+                1 => None,
+                4 | 5 => {
+                    source += fields[1];
+                    source_line += fields[2];
+                    source_column += fields[3];
+                    let named = (fields.len() == 5).then(|| {
+                        name += fields[4];
+                        name
+                    });
+                    Some((source, source_line, source_column, named))
+                }
+                n => bail!("mapping segment with {} fields", n),
+            };
+            if column < 0 {
+                bail!("negative generated column");
+            }
+            decoded.push(Mapping { line, column: column as usize, source: mapped });
+        }
+    }
+    Ok(decoded)
+}
+
+/// `mappings` must be sorted by line and column
+pub fn encode_mappings(mappings: &[Mapping]) -> String {
+    let mut out = String::new();
+    let (mut source, mut source_line, mut source_column, mut name) = (0i64, 0i64, 0i64, 0i64);
+    let mut line = 0;
+    let mut column = 0i64;
+    let mut first_on_line = true;
+    for mapping in mappings {
+        while line < mapping.line {
+            out.push(';');
+            line += 1;
+            column = 0;
+            first_on_line = true;
+        }
+        if !first_on_line {
+            out.push(',');
+        }
+        first_on_line = false;
+        encode_vlq(&mut out, mapping.column as i64 - column);
+        column = mapping.column as i64;
+        if let Some((s, sl, sc, n)) = mapping.source {
+            encode_vlq(&mut out, s - source);
+            encode_vlq(&mut out, sl - source_line);
+            encode_vlq(&mut out, sc - source_column);
+            (source, source_line, source_column) = (s, sl, sc);
+            if let Some(n) = n {
+                encode_vlq(&mut out, n - name);
+                name = n;
+            }
+        }
+    }
+    out
+}
+
+/// Line starts of a text, and whether each line is ASCII so columns are byte offsets.
+/// Minified bundles are a few huge lines, so walks over non-ASCII lines continue from
+/// the last position asked for, mappings come in order.
+struct Lines<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+    ascii: Vec<bool>,
+    /// line, byte offset and UTF-16 column of the last lookup
+    cursor: (usize, usize, usize),
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        let ascii = (0..starts.len())
+            .map(|line| {
+                let end = starts.get(line + 1).map(|e| e - 1).unwrap_or(text.len());
+                text[starts[line]..end].is_ascii()
+            })
+            .collect();
+        Self { text, starts, ascii, cursor: (0, 0, 0) }
+    }
+
+    fn end(&self, line: usize) -> usize {
+        self.starts.get(line + 1).map(|e| e - 1).unwrap_or(self.text.len())
+    }
+
+    /// where to start walking `line` from to reach `byte` / `units`
+    fn walk_from(&self, line: usize, byte: usize, units: usize) -> (usize, usize) {
+        let (cursor_line, cursor_byte, cursor_units) = self.cursor;
+        if cursor_line == line && cursor_byte <= byte && cursor_units <= units {
+            (cursor_byte, cursor_units)
+        } else {
+            (self.starts[line], 0)
+        }
+    }
+
+    /// source map columns count UTF-16 code units
+    fn offset(&mut self, line: usize, column: usize) -> Option<usize> {
+        let start = *self.starts.get(line)?;
+        let end = self.end(line);
+        if self.ascii[line] {
+            return (column <= end - start).then_some(start + column);
+        }
+        let (mut byte, mut units) = self.walk_from(line, end, column);
+        for c in self.text[byte..end].chars() {
+            if units >= column {
+                break;
+            }
+            units += c.len_utf16();
+            byte += c.len_utf8();
+        }
+        if units < column {
+            return None;
+        }
+        self.cursor = (line, byte, units);
+        Some(byte)
+    }
+
+    fn position(&mut self, offset: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|&s| s <= offset) - 1;
+        if self.ascii[line] {
+            return (line, offset - self.starts[line]);
+        }
+        let (byte, units) = self.walk_from(line, offset, usize::MAX);
+        let units = units + self.text[byte..offset].encode_utf16().count();
+        self.cursor = (line, offset, units);
+        (line, units)
+    }
+}
+
+/// Moves the generated positions of `mappings` from `original` to `patched`, given the passes that
+/// turned one into the other. Mappings inside replaced text are dropped.
+pub fn rewrite_mappings(original: &str, patched: &str, passes: &[Pass], mappings: &str) -> Result<String> {
+    let mut before = Lines::new(original);
+    let mut after = Lines::new(patched);
+    let tables: Vec<ShiftTable> = passes.iter().map(|pass| ShiftTable::new(pass)).collect();
+    let mut rewritten = Vec::new();
+    for mapping in decode_mappings(mappings)? {
+        let Some(mut offset) = before.offset(mapping.line, mapping.column) else {
+            continue;
+        };
+        let mut kept = true;
+        for table in &tables {
+            match table.shift(offset) {
+                Some(shifted) => offset = shifted,
+                None => {
+                    kept = false;
+                    break;
+                }
+            }
+        }
+        if kept && offset <= patched.len() {
+            let (line, column) = after.position(offset);
+            rewritten.push(Mapping { line, column, ..mapping });
+        }
+    }
+    rewritten.sort_by_key(|m| (m.line, m.column));
+    Ok(encode_mappings(&rewritten))
+}
+
+/// A source map (`version: 3`, not an index map) with its mappings moved to the patched file.
+pub fn rewrite_map(map: &[u8], original: &str, patched: &str, passes: &[Pass]) -> Result<Vec<u8>> {
+    let mut map: serde_json::Value = serde_json::from_slice(map).context("invalid source map")?;
+    if map.get("sections").is_some() {
+        bail!("index maps are not supported");
+    }
+    let mappings = map
+        .get("mappings")
+        .and_then(|m| m.as_str())
+        .context("source map has no mappings")?;
+    let rewritten = rewrite_mappings(original, patched, passes, mappings)?;
+    map["mappings"] = serde_json::Value::String(rewritten);
+    Ok(serde_json::to_vec(&map)?)
+}
@@ -0,0 +1,190 @@
+use crate::asset_downloader::manifest::sha256_hex;
+use crate::db::models::api_key;
+use crate::server::handlers::api::StatusResponse;
+use crate::server::state::AppState;
+use anyhow::Result;
+use axum::extract::{Query, Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use sea_orm::prelude::Expr;
+use sea_orm::*;
+use std::collections::HashMap;
+
+/// generated keys start with this, so they are easy to spot in configs and logs
+pub const KEY_PREFIX: &str = "ug2_";
+
+/// What a token may do. `Admin` includes `Read`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Read,
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Scope> {
+        match s {
+            "read" => Some(Scope::Read),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+
+    /// GET and HEAD only read, every other method changes something
+    pub fn required_for(method: &Method) -> Scope {
+        if method == Method::GET || method == Method::HEAD {
+            Scope::Read
+        } else {
+            Scope::Admin
+        }
+    }
+}
+
+pub fn hash_key(key: &str) -> String {
+    sha256_hex(key.as_bytes())
+}
+
+/// a new random key and the hash to store, the key itself is only shown once
+pub fn generate_key() -> (String, String) {
+    let key = format!("{}{}", KEY_PREFIX, hex::encode(rand::random::<[u8; 32]>()));
+    let hash = hash_key(&key);
+    (key, hash)
+}
+
+/// `last_used_at` is only written when it is older than this, not on every request
+pub const LAST_USED_RESOLUTION: chrono::TimeDelta = chrono::TimeDelta::minutes(1);
+
+/// Only job event streams take `?access_token=`, `EventSource` can't send headers.
+/// Anywhere else a key in the URL would end up in logs and browser history.
+pub fn accepts_query_token(method: &Method, path: &str) -> bool {
+    method == Method::GET && path.ends_with("/events")
+}
+
+/// `Authorization: Bearer ...`, or `?access_token=` where `accepts_query_token` allows it
+pub fn presented_token(headers: &HeaderMap, query: &HashMap<String, String>) -> Option<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| t.trim().to_string());
+    bearer
+        .or_else(|| query.get("access_token").cloned())
+        .filter(|t| !t.is_empty())
+}
+
+pub async fn create_key(db: &DatabaseConnection, name: &str, scope: Scope) -> Result<String> {
+    let (key, key_hash) = generate_key();
+    api_key::ActiveModel {
+        name: Set(name.to_string()),
+        key_hash: Set(key_hash),
+        scope: Set(scope.as_str().to_string()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(key)
+}
+
+/// false when there is no key with that name
+pub async fn revoke_key(db: &DatabaseConnection, name: &str) -> Result<bool> {
+    let result = api_key::Entity::delete_many()
+        .filter(api_key::Column::Name.eq(name))
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// Scope of a presented token, `None` if it is neither `ADMIN_TOKEN` nor a stored key.
+/// Only hashes are compared, so the comparison leaks nothing about the secrets.
+pub async fn resolve_scope(db: &DatabaseConnection, admin_token: Option<&str>, token: &str) -> Result<Option<Scope>> {
+    let token_hash = hash_key(token);
+    if admin_token.is_some_and(|admin| hash_key(admin) == token_hash) {
+        return Ok(Some(Scope::Admin));
+    }
+
+    let Some(key) = api_key::Entity::find()
+        .filter(api_key::Column::KeyHash.eq(&token_hash))
+        .one(db)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let scope = Scope::parse(&key.scope);
+    let db = db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = touch_key(&db, &key).await {
+            tracing::debug!("Failed to record use of API key {}: {}", key.name, e);
+        }
+    });
+    Ok(scope)
+}
+
+/// Records that `key` was used, false if it already was within `LAST_USED_RESOLUTION`.
+pub async fn touch_key(db: &DatabaseConnection, key: &api_key::Model) -> Result<bool> {
+    let stale = chrono::Utc::now() - LAST_USED_RESOLUTION;
+    if key.last_used_at.is_some_and(|at| at > stale) {
+        return Ok(false);
+    }
+    // a concurrent request may have written it since the key was read
+    let result = api_key::Entity::update_many()
+        .col_expr(api_key::Column::LastUsedAt, Expr::value(chrono::Utc::now().fixed_offset()))
+        .filter(api_key::Column::Id.eq(key.id))
+        .filter(
+            Condition::any()
+                .add(api_key::Column::LastUsedAt.is_null())
+                .add(api_key::Column::LastUsedAt.lt(stale.fixed_offset())),
+        )
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+fn auth_error(status: StatusCode, message: &str) -> Response {
+    let body = Json(StatusResponse { status: "error".into(), message: message.into() });
+    if status == StatusCode::UNAUTHORIZED {
+        (status, [(header::WWW_AUTHENTICATE, "Bearer")], body).into_response()
+    } else {
+        (status, body).into_response()
+    }
+}
+
+/// Guards the management routes of `/api` with the `[auth]` settings.
+pub async fn auth_middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !state.config.patch_config.auth.enabled {
+        return next.run(request).await;
+    }
+
+    let query = if accepts_query_token(request.method(), request.uri().path()) {
+        Query::<HashMap<String, String>>::try_from_uri(request.uri())
+            .map(|q| q.0)
+            .unwrap_or_default()
+    } else {
+        HashMap::new()
+    };
+    let Some(token) = presented_token(request.headers(), &query) else {
+        return auth_error(StatusCode::UNAUTHORIZED, "Missing API key");
+    };
+
+    let scope = match resolve_scope(&state.db, state.config.admin_token.as_deref(), &token).await {
+        Ok(Some(scope)) => scope,
+        Ok(None) => return auth_error(StatusCode::UNAUTHORIZED, "Invalid API key"),
+        Err(e) => {
+            tracing::error!("Failed to look up API key: {}", e);
+            return auth_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to check API key");
+        }
+    };
+    if scope < Scope::required_for(request.method()) {
+        return auth_error(StatusCode::FORBIDDEN, "This API key is read-only");
+    }
+
+    next.run(request).await
+}
@@ -1,15 +1,22 @@
 use crate::asset_downloader::AssetDownloader;
-use crate::cache::redis_cache;
-use crate::db::models::discord_build;
+use crate::cache::{asset_index, store};
+use crate::db::models::{asset_cache, discord_build, download_job, patch_profile};
 use crate::discord_scraper::{build_parser, GitHubClient};
-use crate::server::state::AppState;
+use crate::server::handlers::assets::is_valid_build_hash;
+use crate::server::jobs::{self, BuildBusy, DownloadSpec, Enqueued, JobEvent, JobKind};
+use crate::cache::FsCache;
+use crate::server::state::{AppState, LivePatching};
 use axum::extract::State;
 use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use sea_orm::prelude::Expr;
 use sea_orm::*;
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use tokio::sync::broadcast;
 
 #[derive(Debug, FromQueryResult)]
 struct BuildSummary {
@@ -18,6 +25,8 @@ struct BuildSummary {
     is_patched: bool,
     is_active: bool,
     build_date: chrono::DateTime<chrono::FixedOffset>,
+    patch_profile: Option<String>,
+    missing_assets: i32,
 }
 
 #[derive(Deserialize)]
@@ -32,6 +41,8 @@ pub struct BuildResponse {
     pub is_patched: bool,
     pub is_active: bool,
     pub build_date: String,
+    pub patch_profile: Option<String>,
+    pub missing_assets: i32,
 }
 
 #[derive(Serialize)]
@@ -40,6 +51,26 @@ pub struct StatusResponse {
     pub message: String,
 }
 
+#[derive(Serialize)]
+pub struct JobAcceptedResponse {
+    pub status: String,
+    pub message: String,
+    /// poll `GET /_ug2/api/jobs/{id}` for progress
+    pub job_id: i32,
+}
+
+#[derive(Serialize)]
+pub struct BuildAssetsResponse {
+    pub build_hash: String,
+    pub total_size: i64,
+    pub assets: Vec<asset_cache::Model>,
+}
+
+#[derive(Deserialize)]
+pub struct LruQuery {
+    pub limit: Option<u64>,
+}
+
 struct DownloadInfo {
     build_hash: String,
     channel: String,
@@ -48,11 +79,14 @@ struct DownloadInfo {
     timestamp: i64,
 }
 
-// POST /api/builds/download
+// POST /_ug2/api/builds/download
 pub async fn download_build(
     State(state): State<AppState>,
     Json(req): Json<DownloadRequest>,
 ) -> Response {
+    if let Err(e) = state.patching().await.config.server.ensure_online("download builds") {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string());
+    }
     let info = if let Some(hash) = &req.build_hash {
         match discord_build::Entity::find()
             .filter(discord_build::Column::BuildHash.eq(hash))
@@ -108,104 +142,28 @@ pub async fn download_build(
         }
     };
 
-    let config = state.config.clone();
-    let db = state.db.clone();
-    let pipeline = state.pipeline.clone();
-    let fs_cache = state.fs_cache.clone();
-    let mut redis = state.redis.clone();
     let build_hash = info.build_hash.clone();
-    let resp_hash = build_hash.clone();
-
-    state.task_tracker.spawn(async move {
-        tracing::info!("Starting download for build {} ({} scripts)", build_hash, info.scripts.len());
-
-        let downloader =
-            AssetDownloader::new(config.cache_path.clone(), &config.asset_base_url);
-        match downloader
-            .download_build(&build_hash, &info.scripts)
-            .await
-        {
-            Ok(assets) => {
-                tracing::info!(
-                    "Downloaded {} assets for build {}",
-                    assets.len(),
-                    build_hash
-                );
-
-                let build_dir = fs_cache.build_dir(&build_hash);
-
-                let index_scripts = crate::asset_downloader::detect_entry_scripts(
-                    &build_dir,
-                    &info.scripts,
-                );
-
-                match pipeline.patch_build(&build_dir).await {
-                    Ok(count) => {
-                        tracing::info!("Patched {} files for build {}", count, build_hash)
-                    }
-                    Err(e) => tracing::error!("Patching failed for {}: {}", build_hash, e),
-                }
-
-                let ts = chrono::DateTime::from_timestamp_millis(info.timestamp)
-                    .unwrap_or_default()
-                    .fixed_offset();
-
-                let global_env_db = if info.global_env.as_object().is_some_and(|m| m.is_empty()) {
-                    None
-                } else {
-                    Some(info.global_env)
-                };
-
-                let active = discord_build::ActiveModel {
-                    build_hash: Set(build_hash.clone()),
-                    channel: Set(info.channel),
-                    build_date: Set(ts),
-                    global_env: Set(global_env_db),
-                    scripts: Set(serde_json::to_value(&info.scripts).unwrap()),
-                    index_scripts: Set(serde_json::to_value(&index_scripts).unwrap()),
-                    is_patched: Set(true),
-                    is_active: Set(false),
-                    ..Default::default()
-                };
-
-                if let Err(e) = discord_build::Entity::insert(active)
-                    .on_conflict(
-                        sea_orm::sea_query::OnConflict::column(
-                            discord_build::Column::BuildHash,
-                        )
-                        .update_columns([
-                            discord_build::Column::IsPatched,
-                            discord_build::Column::IndexScripts,
-                            discord_build::Column::GlobalEnv,
-                            discord_build::Column::UpdatedAt,
-                        ])
-                        .to_owned(),
-                    )
-                    .exec_without_returning(&db)
-                    .await
-                {
-                    tracing::error!("Failed to save build to DB: {}", e);
-                }
-
-                let _ = redis_cache::invalidate_builds_cache(&mut redis).await;
-                tracing::info!("Build {} ready! Detected {} entry scripts", build_hash, index_scripts.len());
-            }
-            Err(e) => tracing::error!("Download failed for {}: {}", build_hash, e),
-        }
-    });
+    let spec = DownloadSpec {
+        kind: JobKind::Download,
+        build_hash: info.build_hash,
+        channel: info.channel,
+        scripts: info.scripts,
+        global_env: info.global_env,
+        timestamp: info.timestamp,
+        entry_scripts: None,
+    };
 
-    (
-        StatusCode::ACCEPTED,
-        Json(StatusResponse {
-            status: "accepted".into(),
-            message: format!("Download started for build {}", resp_hash),
-        }),
-    )
-        .into_response()
+    match jobs::enqueue(&state, spec).await {
+        Ok(enqueued) => enqueued_response(enqueued, &build_hash, format!("Download queued for build {}", build_hash)),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to queue download: {}", e)),
+    }
 }
 
-// POST /api/builds/fetch-current
+// POST /_ug2/api/builds/fetch-current
 pub async fn fetch_current_build(State(state): State<AppState>) -> Response {
+    if let Err(e) = state.patching().await.config.server.ensure_online("scrape Discord") {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, e.to_string());
+    }
     let live = match crate::discord_scraper::fetch_live_build(
         &state.http_client,
         &state.config.discord_base_url,
@@ -222,107 +180,56 @@ pub async fn fetch_current_build(State(state): State<AppState>) -> Response {
     };
 
     let build_hash = live.build_hash.clone();
-    let resp_hash = build_hash.clone();
-
-    let index_scripts = live.scripts.clone();
-
-    let config = state.config.clone();
-    let db = state.db.clone();
-    let pipeline = state.pipeline.clone();
-    let fs_cache = state.fs_cache.clone();
-    let mut redis = state.redis.clone();
-
-    state.task_tracker.spawn(async move {
-        tracing::info!(
-            "Fetching current build {} ({} entry scripts)",
-            build_hash,
-            live.scripts.len()
-        );
-
-        let downloader =
-            AssetDownloader::new(config.cache_path.clone(), &config.asset_base_url);
-        match downloader
-            .download_build(&build_hash, &live.scripts)
-            .await
-        {
-            Ok(assets) => {
-                tracing::info!("Downloaded {} assets for build {}", assets.len(), build_hash);
-
-                let build_dir = fs_cache.build_dir(&build_hash);
-
-                match pipeline.patch_build(&build_dir).await {
-                    Ok(count) => {
-                        tracing::info!("Patched {} files for build {}", count, build_hash)
-                    }
-                    Err(e) => tracing::error!("Patching failed for {}: {}", build_hash, e),
-                }
-
-                let ts = chrono::DateTime::from_timestamp_millis(live.timestamp)
-                    .unwrap_or_default()
-                    .fixed_offset();
-
-                let active = discord_build::ActiveModel {
-                    build_hash: Set(build_hash.clone()),
-                    channel: Set(live.channel),
-                    build_date: Set(ts),
-                    global_env: Set(Some(live.global_env)),
-                    scripts: Set(serde_json::to_value(&assets).unwrap()),
-                    index_scripts: Set(serde_json::to_value(&index_scripts).unwrap()),
-                    is_patched: Set(true),
-                    is_active: Set(false),
-                    ..Default::default()
-                };
-
-                if let Err(e) = discord_build::Entity::insert(active)
-                    .on_conflict(
-                        sea_orm::sea_query::OnConflict::column(
-                            discord_build::Column::BuildHash,
-                        )
-                        .update_columns([
-                            discord_build::Column::IsPatched,
-                            discord_build::Column::IndexScripts,
-                            discord_build::Column::Scripts,
-                            discord_build::Column::GlobalEnv,
-                            discord_build::Column::UpdatedAt,
-                        ])
-                        .to_owned(),
-                    )
-                    .exec_without_returning(&db)
-                    .await
-                {
-                    tracing::error!("Failed to save build to DB: {}", e);
-                }
+    let spec = DownloadSpec {
+        kind: JobKind::FetchCurrent,
+        build_hash: live.build_hash,
+        channel: live.channel,
+        entry_scripts: Some(live.scripts.clone()),
+        scripts: live.scripts,
+        global_env: live.global_env,
+        timestamp: live.timestamp,
+    };
 
-                let _ = redis_cache::invalidate_builds_cache(&mut redis).await;
-                tracing::info!("Build {} ready!", build_hash);
-            }
-            Err(e) => tracing::error!("Download failed for {}: {}", build_hash, e),
-        }
-    });
+    match jobs::enqueue(&state, spec).await {
+        Ok(enqueued) => enqueued_response(enqueued, &build_hash, format!("Fetching current build {} from Discord", build_hash)),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to queue download: {}", e)),
+    }
+}
 
+fn job_accepted(job_id: i32, message: String) -> Response {
     (
         StatusCode::ACCEPTED,
-        Json(StatusResponse {
+        Json(JobAcceptedResponse {
             status: "accepted".into(),
-            message: format!(
-                "Fetching current build {} from Discord",
-                resp_hash
-            ),
+            message,
+            job_id,
         }),
     )
         .into_response()
 }
 
+/// a running job for the same build is handed back instead of starting another
+fn enqueued_response(enqueued: Enqueued, build_hash: &str, message: String) -> Response {
+    match enqueued {
+        Enqueued::Started(job_id) => job_accepted(job_id, message),
+        Enqueued::Busy(BuildBusy::Job(job_id)) => {
+            job_accepted(job_id, format!("Build {} already has job {} running", build_hash, job_id))
+        }
+        Enqueued::Busy(BuildBusy::Repatch) => repatch_conflict(build_hash),
+    }
+}
+
+fn repatch_conflict(build_hash: &str) -> Response {
+    error_response(StatusCode::CONFLICT, format!("Build {} is being repatched", build_hash))
+}
+
 fn error_response(status: StatusCode, message: String) -> Response {
     (status, Json(StatusResponse { status: "error".into(), message })).into_response()
 }
 
-// GET /api/builds
+// GET /_ug2/api/builds
 pub async fn list_builds(State(state): State<AppState>) -> Response {
-    let mut redis = state.redis.clone();
-    let cache_key = redis_cache::builds_list_key();
-
-    if let Ok(Some(cached)) = redis_cache::get_cached_json(&mut redis, cache_key).await {
+    if let Ok(Some(cached)) = state.cache_store.get(store::BUILDS_LIST_KEY).await {
         return ([("content-type", "application/json")], cached).into_response();
     }
 
@@ -333,6 +240,8 @@ pub async fn list_builds(State(state): State<AppState>) -> Response {
         .column(discord_build::Column::IsPatched)
         .column(discord_build::Column::IsActive)
         .column(discord_build::Column::BuildDate)
+        .column(discord_build::Column::PatchProfile)
+        .column(discord_build::Column::MissingAssets)
         .order_by_desc(discord_build::Column::BuildDate)
         .into_model::<BuildSummary>()
         .all(&state.db)
@@ -347,11 +256,13 @@ pub async fn list_builds(State(state): State<AppState>) -> Response {
                     is_patched: b.is_patched,
                     is_active: b.is_active,
                     build_date: b.build_date.to_string(),
+                    patch_profile: b.patch_profile,
+                    missing_assets: b.missing_assets,
                 })
                 .collect();
 
             if let Ok(json) = serde_json::to_string(&response) {
-                let _ = redis_cache::cache_json(&mut redis, cache_key, &json, 30).await;
+                let _ = state.cache_store.set_ex(store::BUILDS_LIST_KEY, &json, 30).await;
             }
 
             Json(response).into_response()
@@ -362,7 +273,7 @@ pub async fn list_builds(State(state): State<AppState>) -> Response {
     }
 }
 
-// PUT /api/builds/active
+// PUT /_ug2/api/builds/active
 #[derive(Deserialize)]
 pub struct SetActiveRequest {
     pub build_hash: String,
@@ -387,8 +298,7 @@ pub async fn set_active_build(
         Ok(res) if res.rows_affected > 0 => {
             *state.active_build.write().await = Some(req.build_hash.clone());
 
-            let mut redis = state.redis.clone();
-            let _ = redis_cache::invalidate_builds_cache(&mut redis).await;
+            let _ = store::invalidate_builds_cache(state.cache_store.as_ref()).await;
 
             Json(StatusResponse {
                 status: "ok".into(),
@@ -407,7 +317,7 @@ pub async fn set_active_build(
     }
 }
 
-// PUT /api/builds/{hash}/index-scripts
+// PUT /_ug2/api/builds/{hash}/index-scripts
 #[derive(Deserialize)]
 pub struct SetIndexScriptsRequest {
     pub index_scripts: Vec<String>,
@@ -444,28 +354,35 @@ pub async fn set_index_scripts(
     }
 }
 
-// POST /api/builds/{hash}/repatch
+// POST /_ug2/api/builds/{hash}/repatch
 pub async fn repatch_build(
     State(state): State<AppState>,
     axum::extract::Path(build_hash): axum::extract::Path<String>,
 ) -> Response {
-    let build_dir = state.fs_cache.build_dir(&build_hash);
-    if !build_dir.exists() {
+    if !state.fs_cache.build_exists(&build_hash) {
         return error_response(StatusCode::NOT_FOUND, "Build not found in cache".into());
     }
+    if !state.fs_cache.has_originals(&build_hash) {
+        // the originals are fetched before repatching, see `spawn_repatch`
+        if let Err(e) = state.patching().await.config.server.ensure_online("fetch original assets") {
+            return error_response(
+                StatusCode::CONFLICT,
+                format!("Build was cached without its original assets and cannot be repatched: {}", e),
+            );
+        }
+    }
 
-    let pipeline = state.pipeline.clone();
-    let mut redis = state.redis.clone();
-    let hash = build_hash.clone();
-
-    let _ = redis_cache::invalidate_builds_cache(&mut redis).await;
-
-    state.task_tracker.spawn(async move {
-        match pipeline.patch_build(&build_dir).await {
-            Ok(count) => tracing::info!("Repatched {} files for build {}", count, hash),
-            Err(e) => tracing::error!("Repatching failed: {}", e),
+    match spawn_repatch(&state, build_hash.clone()).await {
+        Ok(()) => {}
+        Err(BuildBusy::Job(job_id)) => {
+            return error_response(
+                StatusCode::CONFLICT,
+                format!("Build {} has job {} running, repatch it once that finishes", build_hash, job_id),
+            )
         }
-    });
+        Err(BuildBusy::Repatch) => return repatch_conflict(&build_hash),
+    }
+    let _ = store::invalidate_builds_cache(state.cache_store.as_ref()).await;
 
     (
         StatusCode::ACCEPTED,
@@ -476,3 +393,389 @@ pub async fn repatch_build(
     )
         .into_response()
 }
+
+/// Rebuilds a cached build's patched tree in the background with the current patch config.
+/// Builds cached before originals were kept get them fetched first.
+/// Nothing is started while a job or another repatch has the build.
+pub async fn spawn_repatch(state: &AppState, build_hash: String) -> Result<(), BuildBusy> {
+    state.jobs.begin_repatch(&build_hash).await?;
+    let patching = state.patching().await;
+    let db = state.db.clone();
+    let fs_cache = state.fs_cache.clone();
+    let http_client = state.http_client.clone();
+    let downloader = AssetDownloader::new(state.config.cache_path.clone(), &state.config.asset_base_url);
+
+    let jobs = state.jobs.clone();
+
+    state.task_tracker.spawn(async move {
+        repatch(&db, &fs_cache, &http_client, &downloader, &patching, &build_hash).await;
+        jobs.end_repatch(&build_hash).await;
+    });
+    Ok(())
+}
+
+async fn repatch(
+    db: &DatabaseConnection,
+    fs_cache: &FsCache,
+    http_client: &reqwest::Client,
+    downloader: &AssetDownloader,
+    patching: &LivePatching,
+    build_hash: &str,
+) {
+    if !fs_cache.has_originals(build_hash) {
+        let backfilled = match patching.config.server.ensure_online("fetch original assets") {
+            Ok(()) => downloader.backfill_originals(build_hash).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = backfilled {
+            tracing::error!("Repatching failed, no originals for {}: {}", build_hash, e);
+            return;
+        }
+    }
+
+    let build_date = discord_build::Entity::find()
+        .filter(discord_build::Column::BuildHash.eq(build_hash))
+        .one(db)
+        .await
+        .ok()
+        .flatten()
+        .map(|b| b.build_date.date_naive());
+
+    // repatching also refreshes the client mod, so a newer bundle can be picked up without redownloading
+    if patching.config.patches.vencord {
+        if let Err(e) = crate::vencord::install_bundle(http_client, &patching.config, fs_cache, build_hash).await {
+            tracing::error!("Client mod install failed for {}: {}", build_hash, e);
+        }
+    }
+
+    let patched = async {
+        let pipeline = crate::patcher::profile::pipeline_for_build(db, &patching.profiles, &patching.config, &patching.pipeline, build_hash).await?;
+        pipeline.patch_cached_build(fs_cache, build_hash, build_date).await
+    }
+    .await;
+    match patched {
+        Ok(report) => {
+            tracing::info!("Repatched {} files for build {}", report.files_changed, build_hash);
+            if let Err(e) = asset_index::index_build(db, fs_cache, build_hash).await {
+                tracing::warn!("Failed to index assets of build {}: {}", build_hash, e);
+            }
+            if let Err(e) = discord_build::Entity::update_many()
+                .col_expr(
+                    discord_build::Column::PatchReport,
+                    Expr::value(serde_json::to_value(&report).unwrap()),
+                )
+                .filter(discord_build::Column::BuildHash.eq(build_hash))
+                .exec(db)
+                .await
+            {
+                tracing::error!("Failed to save patch report for {}: {}", build_hash, e);
+            }
+        }
+        Err(e) => tracing::error!("Repatching failed: {}", e),
+    }
+}
+
+// GET /_ug2/api/builds/{hash}/patch-report
+pub async fn get_patch_report(
+    State(state): State<AppState>,
+    axum::extract::Path(build_hash): axum::extract::Path<String>,
+) -> Response {
+    match discord_build::Entity::find()
+        .filter(discord_build::Column::BuildHash.eq(&build_hash))
+        .one(&state.db)
+        .await
+    {
+        Ok(Some(build)) => match build.patch_report {
+            Some(report) => Json(report).into_response(),
+            None => error_response(StatusCode::NOT_FOUND, "No patch report for this build yet, repatch it to generate one".into()),
+        },
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "Build not found".into()),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// GET /_ug2/api/builds/{hash}/verify
+pub async fn verify_build(
+    State(state): State<AppState>,
+    axum::extract::Path(build_hash): axum::extract::Path<String>,
+) -> Response {
+    if !is_valid_build_hash(&build_hash) || !state.fs_cache.build_dir(&build_hash).exists() {
+        return error_response(StatusCode::NOT_FOUND, "Build not found in cache".into());
+    }
+
+    match crate::asset_downloader::verify::verify_build(&state.fs_cache, &build_hash).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => error_response(StatusCode::CONFLICT, e.to_string()),
+    }
+}
+
+// GET /_ug2/api/builds/{hash}/assets
+pub async fn list_build_assets(
+    State(state): State<AppState>,
+    axum::extract::Path(build_hash): axum::extract::Path<String>,
+) -> Response {
+    match asset_cache::Entity::find()
+        .filter(asset_cache::Column::BuildHash.eq(&build_hash))
+        .order_by_asc(asset_cache::Column::AssetName)
+        .all(&state.db)
+        .await
+    {
+        Ok(assets) if assets.is_empty() => {
+            error_response(StatusCode::NOT_FOUND, "No assets recorded for this build".into())
+        }
+        Ok(assets) => Json(BuildAssetsResponse {
+            total_size: assets.iter().map(|a| a.file_size).sum(),
+            build_hash,
+            assets,
+        })
+        .into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// GET /_ug2/api/assets/usage
+pub async fn asset_usage(State(state): State<AppState>) -> Response {
+    match asset_index::usage_by_build(&state.db).await {
+        Ok(usage) => Json(usage).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// GET /_ug2/api/assets/lru?limit=50
+pub async fn least_recently_used_assets(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<LruQuery>,
+) -> Response {
+    let limit = query.limit.unwrap_or(50).clamp(1, 1000);
+    match asset_index::least_recently_used(&state.db, limit).await {
+        Ok(assets) => Json(assets).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// GET /_ug2/api/offline/missing
+pub async fn list_missing_assets(State(state): State<AppState>) -> Response {
+    Json(state.missing_assets.list()).into_response()
+}
+
+// DELETE /_ug2/api/offline/missing/{hash}
+pub async fn clear_missing_assets(
+    State(state): State<AppState>,
+    axum::extract::Path(build_hash): axum::extract::Path<String>,
+) -> Response {
+    state.missing_assets.clear(&build_hash);
+    Json(StatusResponse {
+        status: "ok".into(),
+        message: format!("Cleared missing assets of build {}", build_hash),
+    })
+    .into_response()
+}
+
+// GET /_ug2/api/profiles
+pub async fn list_profiles(State(state): State<AppState>) -> Response {
+    match patch_profile::Entity::find()
+        .order_by_asc(patch_profile::Column::Name)
+        .all(&state.db)
+        .await
+    {
+        Ok(profiles) => Json(profiles).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// POST /_ug2/api/profiles
+#[derive(Deserialize)]
+pub struct ProfileRequest {
+    pub name: String,
+    pub description: Option<String>,
+    /// overlay over the global patch config, e.g. `{"patches": {"fast_identify": false}}`
+    pub config: serde_json::Value,
+}
+
+/// creates a profile, or replaces the one with the same name
+pub async fn save_profile(
+    State(state): State<AppState>,
+    Json(req): Json<ProfileRequest>,
+) -> Response {
+    if !crate::patcher::profile::is_valid_profile_name(&req.name) {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "Profile names are 1-64 characters of letters, digits, '-', '_' and '.'".into(),
+        );
+    }
+
+    // reject anything the pipeline couldn't be built from, rather than failing on the next repatch
+    let validated = state
+        .patching()
+        .await
+        .config
+        .with_overlay(&req.config)
+        .and_then(|config| crate::patcher::PatchPipeline::new(&config));
+    if let Err(e) = validated {
+        return error_response(StatusCode::BAD_REQUEST, format!("Invalid profile config: {:#}", e));
+    }
+
+    let profile = patch_profile::ActiveModel {
+        name: Set(req.name.clone()),
+        description: Set(req.description),
+        config: Set(req.config),
+        ..Default::default()
+    };
+
+    let result = patch_profile::Entity::insert(profile)
+        .on_conflict(
+            sea_orm::sea_query::OnConflict::column(patch_profile::Column::Name)
+                .update_columns([patch_profile::Column::Description, patch_profile::Column::Config])
+                .value(patch_profile::Column::UpdatedAt, Expr::current_timestamp())
+                .to_owned(),
+        )
+        .exec_without_returning(&state.db)
+        .await;
+
+    match result {
+        Ok(_) => {
+            state.patching().await.profiles.invalidate(&req.name);
+            Json(StatusResponse {
+                status: "ok".into(),
+                message: format!("Saved patch profile {}, repatch builds using it to apply changes", req.name),
+            })
+            .into_response()
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// PUT /_ug2/api/builds/{hash}/profile
+#[derive(Deserialize)]
+pub struct SetProfileRequest {
+    /// `null` detaches the profile, the build then uses the global config again
+    pub profile: Option<String>,
+}
+
+pub async fn set_build_profile(
+    State(state): State<AppState>,
+    axum::extract::Path(build_hash): axum::extract::Path<String>,
+    Json(req): Json<SetProfileRequest>,
+) -> Response {
+    if let Some(ref name) = req.profile {
+        match patch_profile::Entity::find()
+            .filter(patch_profile::Column::Name.eq(name))
+            .one(&state.db)
+            .await
+        {
+            Ok(Some(_)) => {}
+            Ok(None) => return error_response(StatusCode::NOT_FOUND, format!("Patch profile {} not found", name)),
+            Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+        }
+    }
+
+    let result = discord_build::Entity::update_many()
+        .col_expr(discord_build::Column::PatchProfile, Expr::value(req.profile.clone()))
+        .filter(discord_build::Column::BuildHash.eq(&build_hash))
+        .exec(&state.db)
+        .await;
+
+    match result {
+        Ok(res) if res.rows_affected > 0 => {
+            let _ = store::invalidate_builds_cache(state.cache_store.as_ref()).await;
+
+            let message = match req.profile {
+                Some(name) => format!("Build {} now uses patch profile {}, repatch it to apply", build_hash, name),
+                None => format!("Build {} now uses the global patch config, repatch it to apply", build_hash),
+            };
+            Json(StatusResponse { status: "ok".into(), message }).into_response()
+        }
+        Ok(_) => error_response(StatusCode::NOT_FOUND, "Build not found".into()),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// GET /_ug2/api/jobs
+pub async fn list_jobs(State(state): State<AppState>) -> Response {
+    match download_job::Entity::find()
+        .order_by_desc(download_job::Column::Id)
+        .limit(100)
+        .all(&state.db)
+        .await
+    {
+        Ok(jobs) => Json(jobs).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// GET /_ug2/api/jobs/{id}
+pub async fn get_job(
+    State(state): State<AppState>,
+    axum::extract::Path(job_id): axum::extract::Path<i32>,
+) -> Response {
+    match download_job::Entity::find_by_id(job_id).one(&state.db).await {
+        Ok(Some(job)) => Json(job).into_response(),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "Job not found".into()),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// DELETE /_ug2/api/jobs/{id}
+pub async fn cancel_job(
+    State(state): State<AppState>,
+    axum::extract::Path(job_id): axum::extract::Path<i32>,
+) -> Response {
+    if state.jobs.cancel(job_id) {
+        return (
+            StatusCode::ACCEPTED,
+            Json(StatusResponse {
+                status: "accepted".into(),
+                message: format!("Cancelling job {}", job_id),
+            }),
+        )
+            .into_response();
+    }
+
+    match download_job::Entity::find_by_id(job_id).one(&state.db).await {
+        Ok(Some(_)) => error_response(StatusCode::CONFLICT, "Job has already finished".into()),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "Job not found".into()),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    }
+}
+
+// GET /_ug2/api/jobs/{id}/events
+/// Server-sent events: the job's current state and counts, then live updates until it finishes.
+pub async fn job_events(
+    State(state): State<AppState>,
+    axum::extract::Path(job_id): axum::extract::Path<i32>,
+) -> Response {
+    // subscribe before reading the row so nothing slips in between
+    let receiver = state.jobs.subscribe(job_id);
+
+    let job = match download_job::Entity::find_by_id(job_id).one(&state.db).await {
+        Ok(Some(job)) => job,
+        Ok(None) => return error_response(StatusCode::NOT_FOUND, "Job not found".into()),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {}", e)),
+    };
+
+    let snapshot = stream::iter(JobEvent::snapshot(&job));
+    let live = match receiver {
+        Some(receiver) => stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::debug!("SSE subscriber skipped {} job events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed(),
+        None => stream::empty().boxed(),
+    };
+
+    let events = snapshot.chain(live).map(|event| {
+        Event::default()
+            .event(event.name())
+            .json_data(&event)
+            .or_else(|e| Ok::<_, Infallible>(Event::default().event("error").data(e.to_string())))
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
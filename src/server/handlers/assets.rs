@@ -1,13 +1,19 @@
+use crate::asset_downloader::manifest::sha256_hex;
+use crate::cache::asset_index::{self, guess_content_type, AssetRecord};
+use crate::cache::precompress;
+use crate::server::conditional::{self, RangeRequest};
 use crate::server::state::AppState;
 use axum::body::Body;
 use axum::extract::{Path, State};
 use axum::http::{header, HeaderMap, StatusCode};
 use axum::response::{IntoResponse, Response};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio_util::io::ReaderStream;
 
 pub async fn serve_asset(
     State(state): State<AppState>,
     Path(asset_name): Path<String>,
+    headers: HeaderMap,
 ) -> Response {
     let active_build = state.active_build.read().await;
     let build_hash = match active_build.as_ref() {
@@ -16,30 +22,91 @@ pub async fn serve_asset(
     };
     drop(active_build);
 
-    let cache_headers = asset_cache_headers(&asset_name);
+    stream_asset(&state, &build_hash, &asset_name, &headers).await
+}
+
+// GET /build/{hash}/assets/{asset}
+pub async fn serve_build_asset(
+    State(state): State<AppState>,
+    Path((build_hash, asset_name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    if !is_valid_build_hash(&build_hash) || !state.fs_cache.build_exists(&build_hash) {
+        return (StatusCode::NOT_FOUND, "Build not found in cache").into_response();
+    }
+
+    stream_asset(&state, &build_hash, &asset_name, &headers).await
+}
+
+/// build hashes end up in filesystem paths, so only plain alphanumerics are accepted
+pub fn is_valid_build_hash(hash: &str) -> bool {
+    !hash.is_empty() && hash.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+async fn stream_asset(state: &AppState, build_hash: &str, asset_name: &str, request_headers: &HeaderMap) -> Response {
+    let mut cache_headers = asset_cache_headers(asset_name);
 
     // 1. Stream from filesystem — files are already patched on disk, zero RAM
-    let path = state.fs_cache.build_dir(&build_hash).join(&asset_name);
-    if let Ok(file) = tokio::fs::File::open(&path).await {
-        let stream = ReaderStream::new(file);
-        let body = Body::from_stream(stream);
-        return (cache_headers, body).into_response();
+    let path = state.fs_cache.patched_dir(build_hash).join(asset_name);
+    if let Ok(meta) = tokio::fs::metadata(&path).await {
+        if meta.is_file() {
+            return serve_file(state, build_hash, asset_name, &path, &meta, request_headers, cache_headers).await;
+        }
     }
 
-    // 2. Fallback: fetch from Discord, patch on the fly, save to disk for future streaming
+    // 2. Fallback: fetch from Discord, patch on the fly, save both copies so a repatch picks it up
+    let patching = state.patching().await;
+    if patching.config.server.offline {
+        state.missing_assets.record(build_hash, asset_name);
+        return (StatusCode::NOT_FOUND, "Asset not found").into_response();
+    }
     let url = format!("{}/assets/{}", state.config.asset_base_url, asset_name);
     match state.http_client.get(&url).send().await {
         Ok(resp) if resp.status().is_success() => {
             if let Ok(bytes) = resp.bytes().await {
+                if state.fs_cache.has_originals(build_hash) {
+                    let _ = state.fs_cache.put_original(build_hash, asset_name, &bytes).await;
+                }
                 let is_patchable = asset_name.ends_with(".js") || asset_name.ends_with(".css");
                 let data = if is_patchable {
                     let content = String::from_utf8_lossy(&bytes);
-                    let patched = state.pipeline.patch_content(&content);
+                    let patched = crate::patcher::profile::patch_fetched_asset(
+                        &state.db,
+                        &patching.profiles,
+                        &patching.config,
+                        &patching.pipeline,
+                        build_hash,
+                        asset_name,
+                        &content,
+                    )
+                    .await;
                     patched.into_bytes()
                 } else {
                     bytes.to_vec()
                 };
-                let _ = state.fs_cache.put_asset(&build_hash, &asset_name, &data).await;
+                let data = axum::body::Bytes::from(data);
+
+                let record = AssetRecord {
+                    asset_name: asset_name.to_string(),
+                    file_size: data.len() as i64,
+                    is_patched: data[..] != bytes[..],
+                };
+                let db = state.db.clone();
+                let fs_cache = state.fs_cache.clone();
+                let build_hash = build_hash.to_string();
+                let saved = data.clone();
+                // compressing a big chunk takes a while, the response doesn't wait for it
+                tokio::spawn(async move {
+                    if let Err(e) = fs_cache.put_asset(&build_hash, &record.asset_name, &saved).await {
+                        tracing::warn!("Failed to save fetched asset {} of {}: {}", record.asset_name, build_hash, e);
+                    }
+                    // builds only served through a pin may not be in the database
+                    if let Err(e) = asset_index::record_assets(&db, &build_hash, vec![record]).await {
+                        tracing::debug!("Not recording fetched asset for {}: {}", build_hash, e);
+                    }
+                });
+                let etag = format!("\"{}\"", sha256_hex(&data));
+                cache_headers.insert(header::ETAG, etag.parse().unwrap());
                 return (cache_headers, data).into_response();
             }
         }
@@ -49,31 +116,109 @@ pub async fn serve_asset(
     (StatusCode::NOT_FOUND, "Asset not found").into_response()
 }
 
+/// Serves a file from the patched tree with its ETag, answering conditional and range requests.
+/// Compressible files come with .br/.gz siblings written at patch time, ranges are served
+/// from the uncompressed file.
+async fn serve_file(
+    state: &AppState,
+    build_hash: &str,
+    asset_name: &str,
+    path: &std::path::Path,
+    meta: &std::fs::Metadata,
+    request_headers: &HeaderMap,
+    mut headers: HeaderMap,
+) -> Response {
+    let served = match state.content_hashes.get(&state.fs_cache, build_hash, asset_name).await {
+        Ok(served) => served,
+        Err(e) => {
+            tracing::error!("Failed to hash {} of build {}: {}", asset_name, build_hash, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read asset").into_response();
+        }
+    };
+    state.asset_access.touch(build_hash, asset_name);
+    if served.immutable {
+        headers.insert(header::CACHE_CONTROL, "public, max-age=31536000, immutable".parse().unwrap());
+    }
+
+    let range = request_headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+    let mut encoding = None;
+    if precompress::is_compressible(asset_name) {
+        headers.insert(header::VARY, "Accept-Encoding".parse().unwrap());
+        let accept = request_headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if range.is_none() {
+            for candidate in precompress::negotiate(accept) {
+                if candidate.sibling(path).exists() {
+                    encoding = Some(candidate);
+                    break;
+                }
+            }
+        }
+    }
+
+    // each encoding is its own representation and needs its own strong tag
+    let etag = match encoding {
+        Some(encoding) => format!("\"{}-{}\"", served.sha256, encoding.extension()),
+        None => format!("\"{}\"", served.sha256),
+    };
+    let modified = meta.modified().ok();
+    headers.insert(header::ETAG, etag.parse().unwrap());
+    headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+    if let Some(modified) = modified {
+        headers.insert(header::LAST_MODIFIED, conditional::http_date(modified).parse().unwrap());
+    }
+    if conditional::is_not_modified(request_headers, &etag, modified) {
+        headers.remove(header::CONTENT_TYPE);
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    let file_path = match encoding {
+        Some(encoding) => {
+            headers.insert(header::CONTENT_ENCODING, encoding.name().parse().unwrap());
+            encoding.sibling(path)
+        }
+        None => path.to_path_buf(),
+    };
+    let Ok(mut file) = tokio::fs::File::open(&file_path).await else {
+        return (StatusCode::NOT_FOUND, "Asset not found").into_response();
+    };
+    let len = match file.metadata().await {
+        Ok(meta) => meta.len(),
+        Err(_) => return (StatusCode::NOT_FOUND, "Asset not found").into_response(),
+    };
+
+    if let Some(range) = range.filter(|_| conditional::if_range_allows(request_headers, &etag)) {
+        match conditional::parse_range(range, len) {
+            RangeRequest::Partial(range) => {
+                if file.seek(std::io::SeekFrom::Start(range.start)).await.is_err() {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read asset").into_response();
+                }
+                headers.insert(header::CONTENT_RANGE, range.content_range(len).parse().unwrap());
+                headers.insert(header::CONTENT_LENGTH, range.size().into());
+                let body = Body::from_stream(ReaderStream::new(file.take(range.size())));
+                return (StatusCode::PARTIAL_CONTENT, headers, body).into_response();
+            }
+            RangeRequest::Unsatisfiable => {
+                headers.insert(header::CONTENT_RANGE, format!("bytes */{}", len).parse().unwrap());
+                return (StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response();
+            }
+            RangeRequest::Full => {}
+        }
+    }
+
+    headers.insert(header::CONTENT_LENGTH, len.into());
+    let body = Body::from_stream(ReaderStream::new(file));
+    (headers, body).into_response()
+}
+
+/// Asset names are Discord's, a repatch changes the content behind the same URL. Browsers
+/// revalidate every time and get a 304 while the ETag still matches. Assets renamed by
+/// `content_hashed_names` are immutable instead.
 fn asset_cache_headers(name: &str) -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert(header::CONTENT_TYPE, guess_content_type(name).parse().unwrap());
-    headers.insert(header::CACHE_CONTROL, "public, max-age=31536000, immutable".parse().unwrap());
+    headers.insert(header::CACHE_CONTROL, "public, no-cache".parse().unwrap());
     headers
 }
-
-fn guess_content_type(name: &str) -> &'static str {
-    if name.ends_with(".js") {
-        "application/javascript"
-    } else if name.ends_with(".css") {
-        "text/css"
-    } else if name.ends_with(".svg") {
-        "image/svg+xml"
-    } else if name.ends_with(".png") {
-        "image/png"
-    } else if name.ends_with(".woff2") {
-        "font/woff2"
-    } else if name.ends_with(".woff") {
-        "font/woff"
-    } else if name.ends_with(".wasm") {
-        "application/wasm"
-    } else if name.ends_with(".map") {
-        "application/json"
-    } else {
-        "application/octet-stream"
-    }
-}
@@ -1,8 +1,11 @@
 use crate::config::{extract_host, BrandingConfig, DEFAULT_CDN_BYPASS_PATHS};
 use crate::server::state::AppState;
-use axum::extract::State;
+use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse, Response};
+use regex::{NoExpand, Regex};
+use std::collections::HashMap;
+use std::sync::LazyLock;
 
 pub async fn serve_index(State(state): State<AppState>) -> Response {
     let active_build = state.active_build.read().await;
@@ -14,9 +17,33 @@ pub async fn serve_index(State(state): State<AppState>) -> Response {
     };
     drop(active_build);
 
-    if let Ok(Some(data)) = state.fs_cache.get_asset(&build_hash, "index.html").await {
+    render_index(&state, &build_hash, "").await
+}
+
+// GET /build/{hash}/app, /build/{hash}/channels/{*tail}
+/// serves any downloaded build, regardless of which one is active
+pub async fn serve_build_index(
+    State(state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+) -> Response {
+    let build_hash = params.get("hash").map(String::as_str).unwrap_or_default();
+    if !super::assets::is_valid_build_hash(build_hash) || !state.fs_cache.build_exists(build_hash) {
+        return (StatusCode::NOT_FOUND, "Build not found in cache").into_response();
+    }
+
+    render_index(&state, build_hash, &pinned_base(build_hash)).await
+}
+
+/// URL prefix of a build served side by side with the active one
+fn pinned_base(build_hash: &str) -> String {
+    format!("/build/{}", build_hash)
+}
+
+/// `base` is prepended to every asset URL, empty for the active build
+async fn render_index(state: &AppState, build_hash: &str, base: &str) -> Response {
+    if let Ok(Some(data)) = state.fs_cache.get_asset(build_hash, "index.html").await {
         if let Ok(html) = String::from_utf8(data) {
-            return Html(html).into_response();
+            return Html(apply_base(&html, base)).into_response();
         }
     }
 
@@ -24,35 +51,76 @@ pub async fn serve_index(State(state): State<AppState>) -> Response {
     use sea_orm::*;
 
     let build = discord_build::Entity::find()
-        .filter(discord_build::Column::BuildHash.eq(&build_hash))
+        .filter(discord_build::Column::BuildHash.eq(build_hash))
         .one(&state.db)
         .await;
 
     match build {
         Ok(Some(build)) => {
-            let index_scripts: Vec<String> =
+            let mut index_scripts: Vec<String> =
                 serde_json::from_value(build.index_scripts).unwrap_or_default();
-            let branding = &state.config.patch_config.branding;
-            let patches = &state.config.patch_config.patches;
+            // entry scripts renamed by `content_hashed_names` are linked under their new name
+            match state.content_hashes.renames(&state.fs_cache, build_hash).await {
+                Ok(renames) => {
+                    for script in index_scripts.iter_mut() {
+                        let asset = script.trim_start_matches("/assets/");
+                        if let Some(served) = renames.get(asset) {
+                            *script = served.clone();
+                        }
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to read the manifest of build {}: {}", build_hash, e),
+            }
+            // the index is branded like the build's assets were patched
+            let patching = state.patching().await;
+            let config = match &build.patch_profile {
+                Some(name) => match patching.profiles.get(&state.db, &patching.config, name).await {
+                    Ok(profile) => profile.config,
+                    Err(e) => {
+                        tracing::warn!("Using the global config for the index of {}: {}", build_hash, e);
+                        patching.config.clone()
+                    }
+                },
+                None => patching.config.clone(),
+            };
+            let branding = &config.branding;
+            let patches = &config.patches;
 
             if index_scripts.is_empty() {
                 tracing::warn!("No index_scripts for build {}, client won't load. Download the build first.", build_hash);
             }
 
-            let html = generate_index(&build_hash, &index_scripts, branding, patches);
+            let asset_prefix = format!("{}/assets/", base);
+            let vencord_tags = if patches.vencord {
+                let patched_dir = state.fs_cache.patched_dir(build_hash);
+                if patched_dir.join(crate::vencord::BUNDLE_JS).exists() {
+                    let has_stylesheet = patched_dir.join(crate::vencord::BUNDLE_CSS).exists();
+                    crate::vencord::generate_injection(&config.vencord, has_stylesheet, &asset_prefix)
+                } else {
+                    tracing::warn!("vencord is enabled but build {} has no bundle, repatch it to install one", build_hash);
+                    String::new()
+                }
+            } else {
+                String::new()
+            };
+
+            let html = generate_index(build_hash, base, &index_scripts, branding, patches, &vencord_tags);
             Html(html).into_response()
         }
         _ => (StatusCode::SERVICE_UNAVAILABLE, "No build data available").into_response(),
     }
 }
 
-fn generate_index(
+pub fn generate_index(
     build_hash: &str,
+    base: &str,
     scripts: &[String],
     branding: &BrandingConfig,
     patches: &crate::config::PatchToggles,
+    vencord_tags: &str,
 ) -> String {
-    let global_env_js = generate_global_env(branding, patches, build_hash);
+    let global_env_js = generate_global_env(branding, patches, build_hash, base);
+    let pin_script = generate_pin_script(base);
     let cdn_bypass_shim = if patches.cdn_bypass {
         generate_cdn_bypass_shim(branding)
     } else {
@@ -64,7 +132,7 @@ fn generate_index(
         .filter(|s| s.trim_start_matches("/assets/").ends_with(".css"))
         .map(|s| {
             let asset = s.trim_start_matches("/assets/");
-            format!(r#"    <link rel="stylesheet" href="/assets/{}">"#, html_escape(asset))
+            format!(r#"    <link rel="stylesheet" href="{}/assets/{}">"#, base, html_escape(asset))
         })
         .collect::<Vec<_>>()
         .join("\n");
@@ -74,7 +142,7 @@ fn generate_index(
         .filter(|s| !s.trim_start_matches("/assets/").ends_with(".css"))
         .map(|s| {
             let asset = s.trim_start_matches("/assets/");
-            format!(r#"    <script src="/assets/{}" defer></script>"#, html_escape(asset))
+            format!(r#"    <script src="{}/assets/{}" defer></script>"#, base, html_escape(asset))
         })
         .collect::<Vec<_>>()
         .join("\n");
@@ -99,6 +167,7 @@ fn generate_index(
     <meta charset="UTF-8" />
     <meta name="viewport" content="width=device-width, initial-scale=1.0" />
     <title>{title}</title>
+{pin_script}
 {cdn_bypass_shim}
     <script>
         // Intercept XHR & fetch: Discord forces https: on API endpoints,
@@ -137,21 +206,76 @@ fn generate_index(
 
 <body>
     <div id="app-mount"></div>
+{vencord}
 {script_tags}
 {dev_experiments}
 </body>
 
 </html>"#,
         title = branding.instance_name,
+        pin_script = pin_script,
         cdn_bypass_shim = cdn_bypass_shim,
         global_env = global_env_js,
         css_tags = css_tags,
         fast_identify = fast_identify_js,
+        vencord = vencord_tags,
         script_tags = script_tags,
         dev_experiments = dev_experiments_js,
     )
 }
 
+/// The client router only knows root paths (`/app`, `/channels/...`), so a pinned page drops its
+/// `/build/{hash}` prefix from the address bar and marks the URL with `?ug2_build={hash}` instead.
+/// Reloading that URL goes back to the pinned build. Links and in-app navigation drop the flag,
+/// so nothing sticks to the tab once the user leaves the page.
+fn generate_pin_script(base: &str) -> String {
+    let base_json = serde_json::to_string(base).expect("serialize base");
+    format!(r#"    <script>
+        (function() {{
+            var base = {base_json};
+            var flag = "ug2_build";
+            try {{
+                var params = new URLSearchParams(location.search);
+                var pinned = params.get(flag);
+                params.delete(flag);
+                if (base) {{
+                    if (location.pathname.indexOf(base + "/") === 0) {{
+                        params.set(flag, base.slice(base.lastIndexOf("/") + 1));
+                        history.replaceState(history.state, "", location.pathname.slice(base.length) + "?" + params + location.hash);
+                    }}
+                }} else if (pinned && /^[A-Za-z0-9]+$/.test(pinned)) {{
+                    var search = params.toString();
+                    location.replace("/build/" + pinned + location.pathname + (search ? "?" + search : "") + location.hash);
+                }}
+            }} catch (e) {{}}
+        }})();
+    </script>"#)
+}
+
+/// Prepares an `index.html` cached with the build: adds the pin script, and under a `base`
+/// points the asset URLs, `PUBLIC_PATH` and `ASSET_ENDPOINT` at the prefixed routes.
+pub fn apply_base(html: &str, base: &str) -> String {
+    let mut html = if base.is_empty() {
+        html.to_string()
+    } else {
+        let html = html
+            .replace("\"/assets/", &format!("\"{}/assets/", base))
+            .replace("'/assets/", &format!("'{}/assets/", base));
+        let endpoint = format!("ASSET_ENDPOINT: `//${{location.host}}{}`", base);
+        ASSET_ENDPOINT_RE.replace_all(&html, NoExpand(&endpoint)).into_owned()
+    };
+    let pin_script = generate_pin_script(base);
+    match html.find("<head>") {
+        Some(at) => html.insert_str(at + "<head>".len(), &format!("\n{}", pin_script)),
+        None => html.insert_str(0, &pin_script),
+    }
+    html
+}
+
+static ASSET_ENDPOINT_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"ASSET_ENDPOINT\s*:\s*(?:"[^"]*"|'[^']*'|`[^`]*`)"#).unwrap()
+});
+
 fn generate_cdn_bypass_shim(branding: &BrandingConfig) -> String {
     let mut mappings: Vec<(&str, &str)> = Vec::new();
     if let Some(host) = branding.cdn_url.as_deref().and_then(extract_host) {
@@ -238,6 +362,7 @@ fn generate_global_env(
     branding: &BrandingConfig,
     patches: &crate::config::PatchToggles,
     build_hash: &str,
+    base: &str,
 ) -> String {
     let gateway_expr = if let Some(ref gw) = branding.gateway_url {
         format!(r#""{}""#, gw)
@@ -270,8 +395,8 @@ fn generate_global_env(
             GATEWAY_ENDPOINT: {gateway},
             WEBAPP_ENDPOINT: `//${{location.host}}`,
             CDN_HOST: "{cdn_host}",
-            ASSET_ENDPOINT: `//${{location.host}}`,
-            PUBLIC_PATH: "/assets/",
+            ASSET_ENDPOINT: `//${{location.host}}{base}`,
+            PUBLIC_PATH: "{base}/assets/",
             MEDIA_PROXY_ENDPOINT: "{media_proxy_endpoint}",
             WIDGET_ENDPOINT: `//${{location.host}}/widget`,
             INVITE_HOST: `${{location.host}}/invite`,
@@ -297,10 +422,11 @@ fn generate_global_env(
         cdn_host = cdn_host,
         media_proxy_endpoint = media_proxy_endpoint,
         build_hash = build_hash,
+        base = base,
     )
 }
 
-fn html_escape(s: &str) -> String {
+pub fn html_escape(s: &str) -> String {
     s.replace('&', "&amp;")
         .replace('"', "&quot;")
         .replace('<', "&lt;")
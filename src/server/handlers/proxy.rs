@@ -5,6 +5,10 @@ use axum::response::{IntoResponse, Response};
 use reqwest::Url;
 
 pub async fn discord_api_proxy(State(state): State<AppState>, request: Request) -> Response {
+    if let Err(e) = state.patching().await.config.server.ensure_online("proxy the Discord API") {
+        return (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response();
+    }
+
     let _permit = match state.proxy_semaphore.acquire().await {
         Ok(p) => p,
         Err(_) => {
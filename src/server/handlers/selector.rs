@@ -1,9 +1,18 @@
+use crate::server::handlers::index::html_escape;
+use crate::server::state::AppState;
+use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse, Response};
 
-pub async fn serve_selector() -> Response {
+/// stands in for `[server] admin_prefix` in selector.html
+const ADMIN_PREFIX_PLACEHOLDER: &str = "__UG2_ADMIN_PREFIX__";
+
+pub async fn serve_selector(State(state): State<AppState>) -> Response {
     match tokio::fs::read_to_string("static/selector.html").await {
-        Ok(html) => Html(html).into_response(),
+        Ok(html) => {
+            let prefix = state.config.patch_config.server.admin_prefix.trim_end_matches('/');
+            Html(html.replace(ADMIN_PREFIX_PLACEHOLDER, &html_escape(prefix))).into_response()
+        }
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "selector.html not found").into_response(),
     }
 }
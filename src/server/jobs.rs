@@ -0,0 +1,543 @@
+use crate::asset_downloader::{AssetDownloader, BuildManifest, DownloadEvent, DownloadProgress};
+use crate::cache::{asset_index, store};
+use crate::db::models::{discord_build, download_job};
+use crate::server::state::AppState;
+use anyhow::Result;
+use sea_orm::prelude::Expr;
+use sea_orm::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+/// builds downloaded at the same time, later jobs wait in `queued`
+const MAX_RUNNING_JOBS: usize = 2;
+/// progress is written to the database at most this often
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+/// keeps a build full of 404s from bloating its job row
+const MAX_RECORDED_ERRORS: usize = 200;
+/// events a slow subscriber may fall behind before it skips ahead
+const EVENT_BUFFER: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Downloading,
+    DetectingEntries,
+    Patching,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Downloading => "downloading",
+            JobState::DetectingEntries => "detecting_entries",
+            JobState::Patching => "patching",
+            JobState::Done => "done",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_finished(self) -> bool {
+        matches!(self, JobState::Done | JobState::Failed | JobState::Cancelled)
+    }
+
+    pub fn parse(value: &str) -> Option<JobState> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+}
+
+/// what `GET /_ug2/api/jobs/{id}/events` streams, one SSE event per variant
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEvent {
+    State {
+        state: JobState,
+        /// why the job failed
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Progress {
+        queued: usize,
+        downloaded: usize,
+        failed: usize,
+    },
+    AssetFailed {
+        asset: String,
+        error: String,
+    },
+}
+
+impl JobEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobEvent::State { .. } => "state",
+            JobEvent::Progress { .. } => "progress",
+            JobEvent::AssetFailed { .. } => "asset_failed",
+        }
+    }
+
+    /// where a job stands according to its row, sent first to new subscribers
+    pub fn snapshot(job: &download_job::Model) -> Vec<JobEvent> {
+        let state = JobState::parse(&job.state).unwrap_or(JobState::Failed);
+        let error = if state == JobState::Failed {
+            job.errors.as_array().and_then(|e| e.last()).and_then(|e| e.as_str()).map(str::to_string)
+        } else {
+            None
+        };
+        vec![
+            JobEvent::State { state, error },
+            JobEvent::Progress {
+                queued: job.assets_queued.max(0) as usize,
+                downloaded: job.assets_downloaded.max(0) as usize,
+                failed: job.assets_failed.max(0) as usize,
+            },
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    /// a build known from the DB or the build logger repo
+    Download,
+    /// the build currently live on Discord
+    FetchCurrent,
+}
+
+impl JobKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobKind::Download => "download",
+            JobKind::FetchCurrent => "fetch_current",
+        }
+    }
+}
+
+/// what a job needs to download, patch and register a build
+pub struct DownloadSpec {
+    pub kind: JobKind,
+    pub build_hash: String,
+    pub channel: String,
+    pub scripts: Vec<String>,
+    pub global_env: serde_json::Value,
+    pub timestamp: i64,
+    /// entry scripts scraped from the live index.html, detected from the assets when `None`
+    pub entry_scripts: Option<Vec<String>>,
+}
+
+/// handles to a job that hasn't finished yet
+struct LiveJob {
+    events: broadcast::Sender<JobEvent>,
+    cancel: CancellationToken,
+}
+
+/// what is already working on a build's tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildBusy {
+    Job(i32),
+    Repatch,
+}
+
+/// result of `enqueue`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Enqueued {
+    Started(i32),
+    /// nothing was queued, another job or a repatch has the build
+    Busy(BuildBusy),
+}
+
+pub struct JobRunner {
+    slots: Semaphore,
+    live: std::sync::Mutex<HashMap<i32, LiveJob>>,
+    /// one job or repatch per build at a time, they would race on the same tree
+    busy: tokio::sync::Mutex<HashMap<String, BuildBusy>>,
+}
+
+impl Default for JobRunner {
+    fn default() -> Self {
+        Self {
+            slots: Semaphore::new(MAX_RUNNING_JOBS),
+            live: std::sync::Mutex::new(HashMap::new()),
+            busy: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl JobRunner {
+    /// live events of a job, `None` once it has finished (or never ran in this process)
+    pub fn subscribe(&self, job_id: i32) -> Option<broadcast::Receiver<JobEvent>> {
+        self.live.lock().unwrap().get(&job_id).map(|job| job.events.subscribe())
+    }
+
+    /// Asks a queued or running job to stop, false if it isn't running in this process.
+    /// A cancelled download keeps its manifest and resumes when the build is downloaded again.
+    pub fn cancel(&self, job_id: i32) -> bool {
+        match self.live.lock().unwrap().get(&job_id) {
+            Some(job) => {
+                job.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn open(&self, job_id: i32) -> (broadcast::Sender<JobEvent>, CancellationToken) {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let cancel = CancellationToken::new();
+        let job = LiveJob { events: events.clone(), cancel: cancel.clone() };
+        self.live.lock().unwrap().insert(job_id, job);
+        (events, cancel)
+    }
+
+    /// subscribers see the channel close once the job's own sender is dropped too
+    async fn close(&self, job_id: i32, build_hash: &str) {
+        self.live.lock().unwrap().remove(&job_id);
+        self.busy.lock().await.remove(build_hash);
+    }
+
+    pub async fn busy(&self, build_hash: &str) -> Option<BuildBusy> {
+        self.busy.lock().await.get(build_hash).copied()
+    }
+
+    /// builds a job or repatch is working on right now
+    pub async fn busy_builds(&self) -> HashSet<String> {
+        self.busy.lock().await.keys().cloned().collect()
+    }
+
+    /// Claims a build for a repatch, release it with `end_repatch`.
+    pub async fn begin_repatch(&self, build_hash: &str) -> Result<(), BuildBusy> {
+        let mut busy = self.busy.lock().await;
+        if let Some(current) = busy.get(build_hash) {
+            return Err(*current);
+        }
+        busy.insert(build_hash.to_string(), BuildBusy::Repatch);
+        Ok(())
+    }
+
+    pub async fn end_repatch(&self, build_hash: &str) {
+        let mut busy = self.busy.lock().await;
+        if busy.get(build_hash) == Some(&BuildBusy::Repatch) {
+            busy.remove(build_hash);
+        }
+    }
+}
+
+/// Records a queued job and runs it in the background once a slot is free.
+/// A build that already has a job or a repatch running gets nothing new.
+pub async fn enqueue(state: &AppState, spec: DownloadSpec) -> Result<Enqueued> {
+    // held until the job is registered, so two requests can't both start one
+    let mut busy = state.jobs.busy.lock().await;
+    if let Some(current) = busy.get(&spec.build_hash) {
+        return Ok(Enqueued::Busy(*current));
+    }
+    let job = download_job::ActiveModel {
+        build_hash: Set(spec.build_hash.clone()),
+        kind: Set(spec.kind.as_str().into()),
+        state: Set(JobState::Queued.as_str().into()),
+        ..Default::default()
+    }
+    .insert(&state.db)
+    .await?;
+
+    let job_id = job.id;
+    busy.insert(spec.build_hash.clone(), BuildBusy::Job(job_id));
+    drop(busy);
+    let (events, cancel) = state.jobs.open(job_id);
+    let task_state = state.clone();
+    let build_hash = spec.build_hash.clone();
+    state.task_tracker.spawn(async move {
+        let mut job = JobProgress::new(task_state.db.clone(), job_id, events);
+        let slot = tokio::select! {
+            slot = task_state.jobs.slots.acquire() => slot.ok(),
+            _ = cancel.cancelled() => None,
+        };
+        let result = match slot {
+            Some(_slot) => run(&task_state, &mut job, spec, &cancel).await,
+            None => Err(anyhow::anyhow!("cancelled while queued")),
+        };
+        job.finish(result, cancel.is_cancelled()).await;
+        task_state.jobs.close(job_id, &build_hash).await;
+    });
+
+    Ok(Enqueued::Started(job_id))
+}
+
+/// jobs that were running when the server went down will never finish
+pub async fn fail_interrupted(db: &DatabaseConnection) -> Result<()> {
+    // all or nothing, a half-updated table would leave some jobs looking alive
+    let txn = db.begin().await?;
+    let interrupted = download_job::Entity::find()
+        .filter(download_job::Column::State.is_not_in([
+            JobState::Done.as_str(),
+            JobState::Failed.as_str(),
+            JobState::Cancelled.as_str(),
+        ]))
+        .all(&txn)
+        .await?;
+    // appended row by row, JSON operators differ between Postgres and SQLite
+    for job in &interrupted {
+        let mut errors = match &job.errors {
+            serde_json::Value::Array(errors) => errors.clone(),
+            _ => Vec::new(),
+        };
+        errors.push("interrupted by a server restart".into());
+        download_job::Entity::update_many()
+            .col_expr(download_job::Column::State, Expr::value(JobState::Failed.as_str()))
+            .col_expr(download_job::Column::Errors, Expr::value(serde_json::Value::Array(errors)))
+            .col_expr(download_job::Column::FinishedAt, Expr::current_timestamp().into())
+            .col_expr(download_job::Column::UpdatedAt, Expr::current_timestamp().into())
+            .filter(download_job::Column::Id.eq(job.id))
+            .exec(&txn)
+            .await?;
+    }
+    txn.commit().await?;
+    if !interrupted.is_empty() {
+        tracing::warn!("Marked {} interrupted download jobs as failed", interrupted.len());
+    }
+    Ok(())
+}
+
+async fn run(state: &AppState, job: &mut JobProgress, spec: DownloadSpec, cancel: &CancellationToken) -> Result<()> {
+    let patching = state.patching().await;
+    let build_hash = spec.build_hash.as_str();
+    // offline mode may have been switched on while the job was queued
+    patching.config.server.ensure_online("download build assets")?;
+
+    job.set_state(JobState::Downloading).await;
+    tracing::info!("Starting download for build {} ({} scripts)", build_hash, spec.scripts.len());
+
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+    let downloader = AssetDownloader::new(state.config.cache_path.clone(), &state.config.asset_base_url)
+        .with_events(events_tx)
+        .with_cancellation(cancel.clone());
+    let download = downloader.download_build(build_hash, &spec.scripts);
+    tokio::pin!(download);
+
+    let assets = loop {
+        tokio::select! {
+            result = &mut download => break result?,
+            Some(event) = events.recv() => job.record(event).await,
+        }
+    };
+    while let Ok(event) = events.try_recv() {
+        job.record(event).await;
+    }
+    tracing::info!("Downloaded {} assets for build {}", assets.len(), build_hash);
+    let missing = BuildManifest::load(&state.fs_cache.build_dir(build_hash))
+        .await?
+        .map_or(0, |manifest| manifest.missing());
+    if missing > 0 {
+        tracing::warn!("Build {} is missing {} assets, marking it incomplete", build_hash, missing);
+    }
+    // patching isn't interruptible, this is the last point to stop
+    if cancel.is_cancelled() {
+        anyhow::bail!("cancelled");
+    }
+
+    job.set_state(JobState::DetectingEntries).await;
+    let index_scripts = match spec.entry_scripts {
+        Some(ref scripts) => scripts.clone(),
+        None => crate::asset_downloader::detect_entry_scripts(&state.fs_cache.original_dir(build_hash), &spec.scripts),
+    };
+    if index_scripts.is_empty() {
+        job.error("no entry scripts detected, set them with PUT /_ug2/api/builds/{hash}/index-scripts".into());
+    }
+
+    job.set_state(JobState::Patching).await;
+    if patching.config.patches.vencord {
+        if let Err(e) = crate::vencord::install_bundle(&state.http_client, &patching.config, &state.fs_cache, build_hash).await {
+            tracing::error!("Client mod install failed for {}: {}", build_hash, e);
+            job.error(format!("client mod install failed: {}", e));
+        }
+    }
+
+    let build_date = chrono::DateTime::from_timestamp_millis(spec.timestamp).map(|dt| dt.date_naive());
+    let patched = async {
+        let pipeline = crate::patcher::profile::pipeline_for_build(&state.db, &patching.profiles, &patching.config, &patching.pipeline, build_hash).await?;
+        pipeline.patch_cached_build(&state.fs_cache, build_hash, build_date).await
+    }
+    .await;
+    let (patch_report, patch_error) = match patched {
+        Ok(report) => {
+            tracing::info!("Patched {} files for build {}", report.files_changed, build_hash);
+            (Some(report), None)
+        }
+        Err(e) => {
+            tracing::error!("Patching failed for {}: {}", build_hash, e);
+            (None, Some(e))
+        }
+    };
+
+    let ts = chrono::DateTime::from_timestamp_millis(spec.timestamp)
+        .unwrap_or_default()
+        .fixed_offset();
+    let global_env_db = if spec.global_env.as_object().is_some_and(|m| m.is_empty()) {
+        None
+    } else {
+        Some(spec.global_env)
+    };
+    // the live index only lists the entry scripts, so a fetched build remembers everything it downloaded
+    let scripts = match spec.kind {
+        JobKind::Download => &spec.scripts,
+        JobKind::FetchCurrent => &assets,
+    };
+
+    let is_patched = patch_report.is_some();
+    let active = discord_build::ActiveModel {
+        build_hash: Set(spec.build_hash.clone()),
+        channel: Set(spec.channel),
+        build_date: Set(ts),
+        global_env: Set(global_env_db),
+        scripts: Set(serde_json::to_value(scripts)?),
+        index_scripts: Set(serde_json::to_value(&index_scripts)?),
+        // an incomplete build is patched on disk but not offered as ready
+        is_patched: Set(is_patched && missing == 0),
+        missing_assets: Set(missing as i32),
+        is_active: Set(false),
+        patch_report: Set(patch_report.map(serde_json::to_value).transpose()?),
+        ..Default::default()
+    };
+
+    let mut update_columns = vec![
+        discord_build::Column::IsPatched,
+        discord_build::Column::MissingAssets,
+        discord_build::Column::IndexScripts,
+        discord_build::Column::GlobalEnv,
+        discord_build::Column::PatchReport,
+        discord_build::Column::UpdatedAt,
+    ];
+    if spec.kind == JobKind::FetchCurrent {
+        update_columns.push(discord_build::Column::Scripts);
+    }
+
+    discord_build::Entity::insert(active)
+        .on_conflict(
+            sea_orm::sea_query::OnConflict::column(discord_build::Column::BuildHash)
+                .update_columns(update_columns)
+                .to_owned(),
+        )
+        .exec_without_returning(&state.db)
+        .await?;
+
+    if is_patched {
+        if let Err(e) = asset_index::index_build(&state.db, &state.fs_cache, build_hash).await {
+            tracing::warn!("Failed to index assets of build {}: {}", build_hash, e);
+        }
+    }
+
+    let _ = store::invalidate_builds_cache(state.cache_store.as_ref()).await;
+
+    if let Some(e) = patch_error {
+        anyhow::bail!("patching failed: {:#}", e);
+    }
+    tracing::info!("Build {} ready! Detected {} entry scripts", build_hash, index_scripts.len());
+    Ok(())
+}
+
+/// Buffers a job's progress and writes it to its row. Subscribers get every change right away.
+struct JobProgress {
+    db: DatabaseConnection,
+    id: i32,
+    events: broadcast::Sender<JobEvent>,
+    progress: DownloadProgress,
+    errors: Vec<String>,
+    last_flush: Instant,
+}
+
+impl JobProgress {
+    fn new(db: DatabaseConnection, id: i32, events: broadcast::Sender<JobEvent>) -> Self {
+        Self {
+            db,
+            id,
+            events,
+            progress: DownloadProgress::default(),
+            errors: Vec::new(),
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn publish(&self, event: JobEvent) {
+        // no subscribers is fine
+        let _ = self.events.send(event);
+    }
+
+    fn error(&mut self, message: String) {
+        if self.errors.len() < MAX_RECORDED_ERRORS {
+            self.errors.push(message);
+        } else if self.errors.len() == MAX_RECORDED_ERRORS {
+            self.errors.push("too many errors, the rest were dropped".into());
+        }
+    }
+
+    async fn record(&mut self, event: DownloadEvent) {
+        match event {
+            DownloadEvent::Progress(progress) => {
+                self.progress = progress;
+                self.publish(JobEvent::Progress {
+                    queued: progress.queued,
+                    downloaded: progress.downloaded,
+                    failed: progress.failed,
+                });
+            }
+            DownloadEvent::AssetFailed { asset, error } => {
+                self.error(format!("{}: {}", asset, error));
+                self.publish(JobEvent::AssetFailed { asset, error });
+            }
+        }
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush(None).await;
+        }
+    }
+
+    async fn set_state(&mut self, state: JobState) {
+        self.flush(Some(state)).await;
+        let error = if state == JobState::Failed { self.errors.last().cloned() } else { None };
+        self.publish(JobEvent::State { state, error });
+    }
+
+    async fn flush(&mut self, state: Option<JobState>) {
+        self.last_flush = Instant::now();
+
+        let mut update = download_job::Entity::update_many()
+            .col_expr(download_job::Column::AssetsQueued, Expr::value(self.progress.queued as i32))
+            .col_expr(download_job::Column::AssetsDownloaded, Expr::value(self.progress.downloaded as i32))
+            .col_expr(download_job::Column::AssetsFailed, Expr::value(self.progress.failed as i32))
+            .col_expr(download_job::Column::Errors, Expr::value(serde_json::json!(self.errors)))
+            .col_expr(download_job::Column::UpdatedAt, Expr::current_timestamp().into());
+        if let Some(state) = state {
+            update = update.col_expr(download_job::Column::State, Expr::value(state.as_str()));
+            if state == JobState::Downloading {
+                update = update.col_expr(download_job::Column::StartedAt, Expr::current_timestamp().into());
+            }
+            if state.is_finished() {
+                update = update.col_expr(download_job::Column::FinishedAt, Expr::current_timestamp().into());
+            }
+        }
+
+        if let Err(e) = update.filter(download_job::Column::Id.eq(self.id)).exec(&self.db).await {
+            tracing::warn!("Failed to update download job {}: {}", self.id, e);
+        }
+    }
+
+    async fn finish(&mut self, result: Result<()>, cancelled: bool) {
+        match result {
+            Ok(()) => self.set_state(JobState::Done).await,
+            Err(_) if cancelled => {
+                tracing::info!("Download job {} cancelled", self.id);
+                self.set_state(JobState::Cancelled).await;
+            }
+            Err(e) => {
+                tracing::error!("Download job {} failed: {:#}", self.id, e);
+                self.error(format!("{:#}", e));
+                self.set_state(JobState::Failed).await;
+            }
+        }
+    }
+}
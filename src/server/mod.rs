@@ -1,32 +1,33 @@
+pub mod auth;
+pub mod conditional;
 pub mod handlers;
 pub mod ip;
+pub mod jobs;
+pub mod offline;
 pub mod rate_limit;
+pub mod reload;
 pub mod routes;
 pub mod state;
 
-use crate::cache::FsCache;
+use crate::cache::asset_index::{AccessTracker, ACCESS_FLUSH_INTERVAL};
+use crate::cache::store::CacheStore;
 use crate::config::AppConfig;
-use crate::db::models::discord_build;
-use crate::patcher::PatchPipeline;
+use crate::db::models::{api_key, discord_build};
 use anyhow::Result;
-use redis::aio::ConnectionManager;
 use sea_orm::*;
 use state::AppState;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tokio_util::task::TaskTracker;
+use std::future::IntoFuture;
+use tokio_util::sync::CancellationToken;
 
 pub async fn run(
     config: AppConfig,
     db: DatabaseConnection,
-    redis: ConnectionManager,
+    cache_store: Arc<dyn CacheStore>,
 ) -> Result<()> {
     crate::db::run_migrations(&db).await?;
-
-    let fs_cache = Arc::new(FsCache::new(config.cache_path.clone()));
-    let pipeline = Arc::new(PatchPipeline::new(&config.patch_config));
-    let task_tracker = TaskTracker::new();
+    jobs::fail_interrupted(&db).await?;
 
     let active_build = discord_build::Entity::find()
         .filter(discord_build::Column::IsActive.eq(true))
@@ -37,44 +38,103 @@ pub async fn run(
     if let Some(ref hash) = active_build {
         tracing::info!("Active build: {}", hash);
     } else {
-        tracing::warn!("No active build set. Use PUT /api/builds/active to set one.");
+        tracing::warn!("No active build set. Use PUT /_ug2/api/builds/active to set one.");
+    }
+
+    if !config.patch_config.auth.enabled {
+        tracing::warn!("API auth is off, anyone who can reach the management API can use it. See [auth] in patch_config.toml");
+    } else if config.admin_token.is_none() && api_key::Entity::find().count(&db).await? == 0 {
+        tracing::warn!("API auth is on but there is no ADMIN_TOKEN or key, create one with `ug2-client create-key`");
     }
 
-    let state = AppState {
-        config: config.clone(),
-        db,
-        redis,
-        fs_cache,
-        pipeline,
-        active_build: Arc::new(RwLock::new(active_build)),
-        http_client: reqwest::Client::builder()
-            .pool_max_idle_per_host(20)
-            .pool_idle_timeout(std::time::Duration::from_secs(30))
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-            .gzip(true)
-            .build()
-            .expect("Failed to build HTTP client"),
-        proxy_semaphore: Arc::new(tokio::sync::Semaphore::new(50)),
-        task_tracker: task_tracker.clone(),
-    };
+    let state = AppState::new(config.clone(), db, cache_store, active_build)?;
+    let task_tracker = state.task_tracker.clone();
+    let db = state.db.clone();
+    let asset_access = state.asset_access.clone();
 
-    let app = routes::build_router(state);
+    reload::spawn_watcher(state.clone());
+    spawn_access_flusher(db.clone(), asset_access.clone());
+    if config.patch_config.gc.enabled {
+        spawn_collector(state.clone());
+    }
+
+    let admin_state = state.clone();
+    let app = routes::build_router(state)?;
     let listener = tokio::net::TcpListener::bind(&config.bind_addr).await?;
     tracing::info!("Server listening on {}", config.bind_addr);
 
-    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            shutdown_signal().await;
+            shutdown.cancel();
+        }
+    });
+    let public = axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown.clone().cancelled_owned());
+    match &config.admin_bind_addr {
+        Some(admin_bind_addr) => {
+            let admin_app = routes::build_admin_router(admin_state)?;
+            let admin_listener = tokio::net::TcpListener::bind(admin_bind_addr).await?;
+            tracing::info!("Admin API listening on {}", admin_bind_addr);
+            let admin = axum::serve(admin_listener, admin_app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(shutdown.cancelled_owned());
+            tokio::try_join!(public.into_future(), admin.into_future())?;
+        }
+        None => public.await?,
+    }
 
     // The HTTP server has stopped accepting connections and finished in-flight requests; now wait for any background download tasks to complete so we don't leave partial files on disk.
     tracing::info!("HTTP server stopped, waiting for background download tasks...");
     task_tracker.close();
     task_tracker.wait().await;
+    if let Err(e) = asset_access.flush(&db).await {
+        tracing::warn!("Failed to record asset access times: {}", e);
+    }
     tracing::info!("All tasks complete, shutting down.");
 
     Ok(())
 }
 
+fn spawn_access_flusher(db: DatabaseConnection, asset_access: Arc<AccessTracker>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ACCESS_FLUSH_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = asset_access.flush(&db).await {
+                tracing::warn!("Failed to record asset access times: {}", e);
+            }
+        }
+    });
+}
+
+/// Runs the cache GC on `[gc] interval_mins`, with the limits of the live patch config.
+fn spawn_collector(state: AppState) {
+    let interval_mins = state.config.patch_config.gc.interval_mins.max(1);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_mins * 60));
+        loop {
+            interval.tick().await;
+            let gc = state.patching().await.config.gc.clone();
+            let in_use = state.jobs.busy_builds().await;
+            match crate::cache::gc::collect(&state.db, &state.fs_cache, &gc, &in_use, false).await {
+                Ok(report) if !report.evicted.is_empty() => {
+                    tracing::info!(
+                        "Cache GC evicted {} builds, {} -> {} bytes",
+                        report.evicted.len(),
+                        report.total_bytes,
+                        report.total_bytes_after
+                    );
+                    let _ = crate::cache::store::invalidate_builds_cache(state.cache_store.as_ref()).await;
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Cache GC failed: {:#}", e),
+            }
+        }
+    });
+}
+
 async fn shutdown_signal() {
     tokio::signal::ctrl_c()
         .await
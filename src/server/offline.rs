@@ -0,0 +1,40 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+/// names kept per build, so clients asking for made up assets can't grow the list forever
+pub const MAX_MISSING_PER_BUILD: usize = 1000;
+
+/// Assets requested in offline mode that aren't in the cache, by build. An admin brings them
+/// in with the next archive of the cache.
+#[derive(Default)]
+pub struct MissingAssets {
+    builds: Mutex<BTreeMap<String, BTreeSet<String>>>,
+}
+
+impl MissingAssets {
+    /// true the first time an asset is recorded
+    pub fn record(&self, build_hash: &str, asset_name: &str) -> bool {
+        let mut builds = self.builds.lock().unwrap();
+        let assets = builds.entry(build_hash.to_string()).or_default();
+        if assets.len() >= MAX_MISSING_PER_BUILD || assets.contains(asset_name) {
+            return false;
+        }
+        assets.insert(asset_name.to_string());
+        tracing::warn!(
+            "Offline: {} of build {} is not cached ({} missing so far, see GET /_ug2/api/offline/missing)",
+            asset_name,
+            build_hash,
+            assets.len()
+        );
+        true
+    }
+
+    pub fn list(&self) -> BTreeMap<String, BTreeSet<String>> {
+        self.builds.lock().unwrap().clone()
+    }
+
+    /// forgets a build's list, e.g. once its assets were imported
+    pub fn clear(&self, build_hash: &str) {
+        self.builds.lock().unwrap().remove(build_hash);
+    }
+}
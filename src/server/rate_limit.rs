@@ -1,23 +1,151 @@
+use crate::asset_downloader::manifest::sha256_hex;
+use crate::cache::store::CacheStore;
+use crate::config::{BucketLimit, ServerConfig};
 use crate::server::ip;
 use crate::server::state::AppState;
+use anyhow::Result;
 use axum::extract::{ConnectInfo, State};
-use axum::http::{HeaderMap, Request, StatusCode};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
-use redis::AsyncCommands;
 use serde::Serialize;
 use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Requests are counted per bucket, each with its own limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+    /// `/api`, the Discord API proxy
+    Proxy,
+    /// the management API
+    Admin,
+    /// starting downloads
+    Downloads,
+}
+
+impl Bucket {
+    pub fn name(self) -> &'static str {
+        match self {
+            Bucket::Proxy => "proxy",
+            Bucket::Admin => "admin",
+            Bucket::Downloads => "downloads",
+        }
+    }
+
+    pub fn limit(self, server: &ServerConfig) -> BucketLimit {
+        match self {
+            Bucket::Proxy => BucketLimit {
+                requests: server.rate_limit_requests,
+                window_secs: server.rate_limit_window_secs,
+            },
+            Bucket::Admin => server.rate_limits.admin,
+            Bucket::Downloads => server.rate_limits.downloads,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// until the bucket is full again
+    pub reset_after_ms: u64,
+    /// until the next request is allowed, 0 when this one was
+    pub retry_after_ms: u64,
+}
+
+/// GCRA: every request moves the theoretical arrival time `tat` forward by `window / requests`,
+/// and is allowed as long as `tat` stays within one window of `now`. That is `requests` at
+/// once from a full bucket, then one every `window / requests`.
+/// Returns the new `tat` to store when the request is allowed.
+pub fn gcra(tat: Option<u64>, now_ms: u64, limit: BucketLimit) -> (Decision, Option<u64>) {
+    let (window, interval) = window_and_interval(limit);
+    let tat = tat.unwrap_or(now_ms).max(now_ms);
+    let new_tat = tat + interval;
+    if new_tat - now_ms > window {
+        let decision = Decision {
+            allowed: false,
+            limit: limit.requests,
+            remaining: 0,
+            reset_after_ms: tat - now_ms,
+            retry_after_ms: new_tat - window - now_ms,
+        };
+        return (decision, None);
+    }
+    let decision = Decision {
+        allowed: true,
+        limit: limit.requests,
+        remaining: ((window - (new_tat - now_ms)) / interval) as u32,
+        reset_after_ms: new_tat - now_ms,
+        retry_after_ms: 0,
+    };
+    (decision, Some(new_tat))
+}
+
+/// window and emission interval in ms
+pub fn window_and_interval(limit: BucketLimit) -> (u64, u64) {
+    let window = limit.window_secs.max(1) as u64 * 1000;
+    (window, (window / limit.requests.max(1) as u64).max(1))
+}
+
+/// users behind one IP that the IP's shared bucket leaves room for
+pub const SHARED_IP_FACTOR: u32 = 10;
+
+/// Who a request is counted against: the hash of its `Authorization` header when it has one,
+/// so users behind one NAT don't share a bucket, its IP otherwise.
+pub fn client_key(bucket: Bucket, headers: &HeaderMap, ip: &str) -> String {
+    match headers.get(header::AUTHORIZATION).filter(|v| !v.is_empty()) {
+        Some(token) => format!("rl:{}:token:{}", bucket.name(), &sha256_hex(token.as_bytes())[..32]),
+        None => format!("rl:{}:ip:{}", bucket.name(), ip),
+    }
+}
+
+/// Counts a request against its `client_key`, and against a bucket of its IP `SHARED_IP_FACTOR`
+/// times as large. The header isn't verified, so a client rotating it only gets fresh client
+/// buckets until the IP's runs out. Returns the decision of whichever bucket said no.
+pub async fn check(store: &dyn CacheStore, bucket: Bucket, headers: &HeaderMap, ip: &str, server: &ServerConfig) -> Result<Decision> {
+    let limit = bucket.limit(server);
+    let shared = BucketLimit { requests: limit.requests.saturating_mul(SHARED_IP_FACTOR), ..limit };
+    let ip_decision = store.rate_limit(&format!("rl:{}:shared:{}", bucket.name(), ip), shared).await?;
+    if !ip_decision.allowed {
+        return Ok(ip_decision);
+    }
+    store.rate_limit(&client_key(bucket, headers, ip), limit).await
+}
+
+/// Discord-style `X-RateLimit-*` headers
+pub fn rate_limit_headers(bucket: Bucket, decision: &Decision, now_unix_ms: u64) -> HeaderMap {
+    let seconds = |ms: u64| format!("{:.3}", ms as f64 / 1000.0);
+    let mut headers = HeaderMap::new();
+    let mut set = |name: &'static str, value: String| {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from_str(&value).unwrap());
+    };
+    set("x-ratelimit-limit", decision.limit.to_string());
+    set("x-ratelimit-remaining", decision.remaining.to_string());
+    set("x-ratelimit-reset", seconds(now_unix_ms + decision.reset_after_ms));
+    set("x-ratelimit-reset-after", seconds(decision.reset_after_ms));
+    set("x-ratelimit-bucket", bucket.name().to_string());
+    if !decision.allowed {
+        set("x-ratelimit-scope", "user".to_string());
+        set("retry-after", decision.retry_after_ms.div_ceil(1000).to_string());
+    }
+    headers
+}
 
 #[derive(Serialize)]
 struct RateLimitError {
     status: &'static str,
     message: String,
-    retry_after: u32,
+    /// seconds, like Discord's
+    retry_after: f64,
+    global: bool,
 }
 
+/// Limits requests per `Bucket`, use with `from_fn_with_state((state, bucket), ...)`.
 pub async fn rate_limit_middleware(
-    State(state): State<AppState>,
+    State((state, bucket)): State<(AppState, Bucket)>,
     connect_info: ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     request: Request<axum::body::Body>,
@@ -30,36 +158,47 @@ pub async fn rate_limit_middleware(
     }
 
     let ip = ip::extract_real_ip(&headers, &connect_info, server_config.trust_proxy_headers);
-    let key = format!("rl:{}", ip);
-    let window = server_config.rate_limit_window_secs;
-    let max_requests = server_config.rate_limit_requests;
-
-    let mut redis = state.redis.clone();
-
-    let count: u32 = match redis.incr::<_, _, u32>(&key, 1u32).await {
-        Ok(c) => {
-            if c == 1 {
-                let _ = redis.expire::<_, ()>(&key, window as i64).await;
-            }
-            c
+    let decision = match check(state.cache_store.as_ref(), bucket, &headers, &ip, server_config).await {
+        Ok(decision) => decision,
+        Err(e) if server_config.rate_limit_fail_open => {
+            tracing::warn!("Rate limiter store error (fail-open): {}", e);
+            return next.run(request).await;
         }
         Err(e) => {
-            tracing::warn!("Rate limiter Redis error (fail-open): {}", e);
-            return next.run(request).await;
+            tracing::error!("Rate limiter store error (fail-closed): {}", e);
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(RateLimitError {
+                    status: "error",
+                    message: "Rate limiter unavailable".into(),
+                    retry_after: 1.0,
+                    global: true,
+                }),
+            )
+                .into_response();
         }
     };
 
-    if count > max_requests {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64;
+    let limit_headers = rate_limit_headers(bucket, &decision, now);
+    if !decision.allowed {
         return (
             StatusCode::TOO_MANY_REQUESTS,
+            limit_headers,
             Json(RateLimitError {
                 status: "error",
                 message: "Rate limit exceeded".into(),
-                retry_after: window,
+                retry_after: decision.retry_after_ms as f64 / 1000.0,
+                global: false,
             }),
         )
             .into_response();
     }
 
-    next.run(request).await
+    let mut response = next.run(request).await;
+    // Discord's own headers on proxied responses win, the client tracks its routes with them
+    if !response.headers().contains_key("x-ratelimit-limit") {
+        response.headers_mut().extend(limit_headers);
+    }
+    response
 }
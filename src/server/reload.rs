@@ -0,0 +1,148 @@
+use crate::config::{PatchConfig, ServerConfig, PATCH_CONFIG_PATH};
+use crate::patcher::PatchPipeline;
+use crate::server::state::{AppState, LivePatching};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Loads and validates the patch config from disk, then swaps it in along with a fresh pipeline.
+/// A broken file or patch definition leaves the running config untouched.
+pub async fn reload(state: &AppState) -> Result<()> {
+    let config = PatchConfig::load(Path::new(PATCH_CONFIG_PATH))?;
+    let pipeline = PatchPipeline::new(&config)?;
+
+    warn_restart_only(&state.config.patch_config, &config);
+    let repatch_active = config.server.repatch_active_on_reload;
+
+    *state.patching.write().await = LivePatching {
+        config: Arc::new(config),
+        pipeline: Arc::new(pipeline),
+        profiles: Arc::default(),
+    };
+    tracing::info!("Reloaded {}", PATCH_CONFIG_PATH);
+
+    if repatch_active {
+        let active = state.active_build.read().await.clone();
+        if let Some(hash) = active {
+            tracing::info!("Repatching active build {} with the reloaded config", hash);
+            if let Err(busy) = super::handlers::api::spawn_repatch(state, hash.clone()).await {
+                tracing::warn!("Not repatching {}, it is busy ({:?}), repatch it once that finishes", hash, busy);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the router and rate limiter are set up once at startup
+fn warn_restart_only(running: &PatchConfig, new: &PatchConfig) {
+    if running.patches.api_proxy != new.patches.api_proxy {
+        tracing::warn!("patches.api_proxy changed, restart the server to apply it");
+    }
+    let limits = |s: &ServerConfig| {
+        (
+            s.trust_proxy_headers,
+            s.rate_limit_enabled,
+            s.rate_limit_requests,
+            s.rate_limit_window_secs,
+            s.rate_limit_fail_open,
+            s.rate_limits.clone(),
+            s.cache_backend,
+            s.watch_config,
+        )
+    };
+    let admin = |s: &ServerConfig| (s.admin_prefix.clone(), s.legacy_api_redirects);
+    if limits(&running.server) != limits(&new.server) || admin(&running.server) != admin(&new.server) {
+        tracing::warn!("[server] settings changed, restart the server to apply them");
+    }
+    if (running.auth.enabled, running.auth.public_builds_list) != (new.auth.enabled, new.auth.public_builds_list) {
+        tracing::warn!("[auth] settings changed, restart the server to apply them");
+    }
+    if (running.gc.enabled, running.gc.interval_mins) != (new.gc.enabled, new.gc.interval_mins) {
+        tracing::warn!("[gc] enabled/interval_mins changed, restart the server to apply them");
+    }
+}
+
+/// Reloads on SIGHUP, and when `watch_config` is on, whenever `patch_config.toml`
+/// or a file in `patches_dir` changes. Runs until the process exits.
+pub fn spawn_watcher(state: AppState) {
+    let watch = state.config.patch_config.server.watch_config;
+
+    tokio::spawn(async move {
+        let mut hangup = hangup_listener();
+        let mut last = fingerprint(&state.patching().await.config.patches_dir);
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick(), if watch => {
+                    let current = fingerprint(&state.patching().await.config.patches_dir);
+                    if current == last {
+                        continue;
+                    }
+                    tracing::info!("Patch config changed on disk, reloading");
+                }
+                _ = next_hangup(&mut hangup) => tracing::info!("SIGHUP received, reloading patch config"),
+            }
+
+            if let Err(e) = reload(&state).await {
+                tracing::error!("Patch config reload failed, keeping the running config: {:#}", e);
+            }
+            // patches_dir itself may have moved
+            last = fingerprint(&state.patching().await.config.patches_dir);
+        }
+    });
+}
+
+/// modification times of the config file and everything in `patches_dir`
+pub fn fingerprint(patches_dir: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut files = vec![PathBuf::from(PATCH_CONFIG_PATH)];
+    if let Ok(entries) = std::fs::read_dir(patches_dir) {
+        files.extend(entries.filter_map(|e| e.ok().map(|e| e.path())));
+    }
+    files.sort();
+    files
+        .into_iter()
+        .map(|path| {
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+type Hangup = Option<tokio::signal::unix::Signal>;
+
+#[cfg(unix)]
+fn hangup_listener() -> Hangup {
+    use tokio::signal::unix::{signal, SignalKind};
+    signal(SignalKind::hangup())
+        .map_err(|e| tracing::warn!("Failed to install SIGHUP handler: {}", e))
+        .ok()
+}
+
+#[cfg(unix)]
+async fn next_hangup(hangup: &mut Hangup) {
+    match hangup {
+        Some(signal) => {
+            if signal.recv().await.is_none() {
+                *hangup = None;
+                std::future::pending::<()>().await;
+            }
+        }
+        None => std::future::pending::<()>().await,
+    }
+}
+
+#[cfg(not(unix))]
+type Hangup = ();
+
+#[cfg(not(unix))]
+fn hangup_listener() -> Hangup {}
+
+#[cfg(not(unix))]
+async fn next_hangup(_hangup: &mut Hangup) {
+    std::future::pending::<()>().await
+}
@@ -1,31 +1,42 @@
+use super::auth::auth_middleware;
 use super::handlers;
-use super::rate_limit::rate_limit_middleware;
+use super::rate_limit::{rate_limit_middleware, Bucket};
 use super::state::AppState;
+use crate::config::ServerConfig;
+use anyhow::Result;
+use axum::extract::{OriginalUri, State};
+use axum::http::Uri;
 use axum::middleware;
-use axum::routing::{get, post, put};
+use axum::response::Redirect;
+use axum::routing::{any, delete, get, post, put};
 use axum::Router;
 use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
 
-pub fn build_router(state: AppState) -> Router {
+/// where the management API used to live, these get redirected to the admin prefix
+const LEGACY_API_ROUTES: &[&str] = &[
+    "/builds",
+    "/builds/{*rest}",
+    "/assets/usage",
+    "/assets/lru",
+    "/offline/{*rest}",
+    "/jobs",
+    "/jobs/{*rest}",
+    "/profiles",
+];
+
+/// The public app. The admin API and the selector are only part of it without `ADMIN_BIND_ADDR`.
+pub fn build_router(state: AppState) -> Result<Router> {
     let api_proxy = state.config.patch_config.patches.api_proxy;
+    let server_config = &state.config.patch_config.server;
+    let separate_admin = state.config.admin_bind_addr.is_some();
 
-    let mut api_router = Router::new()
-        .route("/builds", get(handlers::api::list_builds))
-        .route("/builds/download", post(handlers::api::download_build))
-        .route(
-            "/builds/fetch-current",
-            post(handlers::api::fetch_current_build),
-        )
-        .route("/builds/active", put(handlers::api::set_active_build))
-        .route(
-            "/builds/{hash}/index-scripts",
-            put(handlers::api::set_index_scripts),
-        )
-        .route(
-            "/builds/{hash}/repatch",
-            post(handlers::api::repatch_build),
-        );
+    let mut api_router = Router::new();
+    if server_config.legacy_api_redirects && !separate_admin {
+        for route in LEGACY_API_ROUTES {
+            api_router = api_router.route(route, any(redirect_legacy_api));
+        }
+    }
 
     if api_proxy {
         tracing::info!(
@@ -36,17 +47,127 @@ pub fn build_router(state: AppState) -> Router {
     }
 
     api_router = api_router
-        .layer(middleware::from_fn_with_state(state.clone(), rate_limit_middleware));
+        .layer(middleware::from_fn_with_state((state.clone(), Bucket::Proxy), rate_limit_middleware));
 
-    Router::new()
+    let mut router = Router::new()
         .route("/", get(handlers::index::serve_index))
         .route("/app", get(handlers::index::serve_index))
-        .route("/selector", get(handlers::selector::serve_selector))
         .route("/channels/{*tail}", get(handlers::index::serve_index))
+        .route("/build/{hash}/app", get(handlers::index::serve_build_index))
+        .route("/build/{hash}/channels/{*tail}", get(handlers::index::serve_build_index))
+        .route("/static/{file}", get(handlers::static_files::serve_static))
+        .nest("/api", api_router);
+    if !separate_admin {
+        router = router
+            .route("/selector", get(handlers::selector::serve_selector))
+            .nest(&admin_prefix(&state.config.patch_config.server)?, admin_api_router(&state));
+    }
+
+    Ok(router
+        .layer(CompressionLayer::new())
+        // added after the compression layer: assets are served precompressed from disk
         .route("/assets/{asset}", get(handlers::assets::serve_asset))
+        .route("/build/{hash}/assets/{asset}", get(handlers::assets::serve_build_asset))
+        .layer(CorsLayer::very_permissive())
+        .with_state(state))
+}
+
+/// The app on `ADMIN_BIND_ADDR`: the selector and the admin API.
+pub fn build_admin_router(state: AppState) -> Result<Router> {
+    Ok(Router::new()
+        .route("/", get(|| async { Redirect::temporary("/selector") }))
+        .route("/selector", get(handlers::selector::serve_selector))
         .route("/static/{file}", get(handlers::static_files::serve_static))
-        .nest("/api", api_router)
+        .nest(&admin_prefix(&state.config.patch_config.server)?, admin_api_router(&state))
         .layer(CompressionLayer::new())
         .layer(CorsLayer::very_permissive())
-        .with_state(state)
+        .with_state(state))
+}
+
+/// `[server] admin_prefix` without its trailing slash
+pub fn admin_prefix(server: &ServerConfig) -> Result<String> {
+    let prefix = server.admin_prefix.trim_end_matches('/');
+    if !prefix.starts_with('/') || prefix == "/api" || prefix.starts_with("/api/") {
+        anyhow::bail!("admin_prefix must start with / and be outside of /api, got {:?}", prefix);
+    }
+    Ok(prefix.to_string())
+}
+
+/// where a request to a legacy `/api` management route goes now
+pub fn legacy_api_target(admin_prefix: &str, uri: &Uri) -> String {
+    let path = uri.path().strip_prefix("/api").unwrap_or(uri.path());
+    let query = uri.query().map(|q| format!("?{}", q)).unwrap_or_default();
+    format!("{}{}{}", admin_prefix.trim_end_matches('/'), path, query)
+}
+
+// ANY /api/{legacy management route}
+async fn redirect_legacy_api(State(state): State<AppState>, OriginalUri(uri): OriginalUri) -> Redirect {
+    // 308 keeps the method and body
+    Redirect::permanent(&legacy_api_target(&state.config.patch_config.server.admin_prefix, &uri))
+}
+
+fn admin_api_router(state: &AppState) -> Router<AppState> {
+    let public_builds_list = state.config.patch_config.auth.public_builds_list;
+
+    let downloads_limit = middleware::from_fn_with_state((state.clone(), Bucket::Downloads), rate_limit_middleware);
+
+    let mut api_router = Router::new();
+    if !public_builds_list {
+        api_router = api_router.route("/builds", get(handlers::api::list_builds));
+    }
+    api_router = api_router
+        .route(
+            "/builds/download",
+            post(handlers::api::download_build).layer(downloads_limit.clone()),
+        )
+        .route(
+            "/builds/fetch-current",
+            post(handlers::api::fetch_current_build).layer(downloads_limit),
+        )
+        .route("/builds/active", put(handlers::api::set_active_build))
+        .route(
+            "/builds/{hash}/index-scripts",
+            put(handlers::api::set_index_scripts),
+        )
+        .route(
+            "/builds/{hash}/repatch",
+            post(handlers::api::repatch_build),
+        )
+        .route(
+            "/builds/{hash}/patch-report",
+            get(handlers::api::get_patch_report),
+        )
+        .route(
+            "/builds/{hash}/verify",
+            get(handlers::api::verify_build),
+        )
+        .route(
+            "/builds/{hash}/assets",
+            get(handlers::api::list_build_assets),
+        )
+        .route("/assets/usage", get(handlers::api::asset_usage))
+        .route("/assets/lru", get(handlers::api::least_recently_used_assets))
+        .route("/offline/missing", get(handlers::api::list_missing_assets))
+        .route(
+            "/offline/missing/{hash}",
+            delete(handlers::api::clear_missing_assets),
+        )
+        .route(
+            "/builds/{hash}/profile",
+            put(handlers::api::set_build_profile),
+        )
+        .route("/jobs", get(handlers::api::list_jobs))
+        .route("/jobs/{id}", get(handlers::api::get_job).delete(handlers::api::cancel_job))
+        .route("/jobs/{id}/events", get(handlers::api::job_events))
+        .route(
+            "/profiles",
+            get(handlers::api::list_profiles).post(handlers::api::save_profile),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth_middleware));
+    if public_builds_list {
+        api_router = api_router.route("/builds", get(handlers::api::list_builds));
+    }
+
+    // outside of auth, so guessing keys counts too
+    api_router.layer(middleware::from_fn_with_state((state.clone(), Bucket::Admin), rate_limit_middleware))
 }
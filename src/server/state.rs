@@ -1,22 +1,85 @@
+use crate::cache::asset_index::AccessTracker;
+use crate::cache::content_hash::ContentHashes;
+use crate::cache::store::CacheStore;
 use crate::cache::FsCache;
-use crate::config::AppConfig;
+use crate::config::{AppConfig, PatchConfig};
+use crate::patcher::profile::ProfileCache;
 use crate::patcher::PatchPipeline;
-use redis::aio::ConnectionManager;
+use crate::server::jobs::JobRunner;
+use crate::server::offline::MissingAssets;
+use anyhow::Result;
 use sea_orm::DatabaseConnection;
 use std::sync::Arc;
 use tokio::sync::{RwLock, Semaphore};
 use tokio_util::task::TaskTracker;
 
+/// Patch config and the pipeline built from it, replaced as a whole when the config is reloaded.
+/// Work that already took a snapshot keeps using it until it finishes.
+#[derive(Clone)]
+pub struct LivePatching {
+    pub config: Arc<PatchConfig>,
+    pub pipeline: Arc<PatchPipeline>,
+    pub profiles: Arc<ProfileCache>,
+}
+
 #[derive(Clone)]
 pub struct AppState {
+    /// as loaded at startup, `patching` holds the reloadable patch config
     pub config: AppConfig,
     pub db: DatabaseConnection,
-    pub redis: ConnectionManager,
+    /// builds list cache and rate limits, Redis or in-process
+    pub cache_store: Arc<dyn CacheStore>,
     pub fs_cache: Arc<FsCache>,
-    pub pipeline: Arc<PatchPipeline>,
+    pub patching: Arc<RwLock<LivePatching>>,
     pub active_build: Arc<RwLock<Option<String>>>,
     pub http_client: reqwest::Client,
     pub proxy_semaphore: Arc<Semaphore>,
+    pub jobs: Arc<JobRunner>,
+    /// asset hits waiting to be written to `asset_cache.last_accessed`
+    pub asset_access: Arc<AccessTracker>,
+    /// ETags of served assets
+    pub content_hashes: Arc<ContentHashes>,
+    /// uncached assets asked for in offline mode
+    pub missing_assets: Arc<MissingAssets>,
     /// Tracks background download tasks so graceful shutdown can wait for them.
     pub task_tracker: TaskTracker,
 }
+
+impl AppState {
+    pub fn new(
+        config: AppConfig,
+        db: DatabaseConnection,
+        cache_store: Arc<dyn CacheStore>,
+        active_build: Option<String>,
+    ) -> Result<Self> {
+        let patching = LivePatching {
+            config: Arc::new(config.patch_config.clone()),
+            pipeline: Arc::new(PatchPipeline::new(&config.patch_config)?),
+            profiles: Arc::default(),
+        };
+        Ok(Self {
+            fs_cache: Arc::new(FsCache::new(config.cache_path.clone())),
+            config,
+            db,
+            cache_store,
+            patching: Arc::new(RwLock::new(patching)),
+            active_build: Arc::new(RwLock::new(active_build)),
+            http_client: reqwest::Client::builder()
+                .pool_max_idle_per_host(20)
+                .pool_idle_timeout(std::time::Duration::from_secs(30))
+                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
+                .gzip(true)
+                .build()?,
+            proxy_semaphore: Arc::new(Semaphore::new(50)),
+            jobs: Arc::default(),
+            asset_access: Arc::default(),
+            content_hashes: Arc::default(),
+            missing_assets: Arc::default(),
+            task_tracker: TaskTracker::new(),
+        })
+    }
+
+    pub async fn patching(&self) -> LivePatching {
+        self.patching.read().await.clone()
+    }
+}
@@ -0,0 +1,66 @@
+use super::{BUNDLE_CSS, BUNDLE_JS};
+use crate::asset_downloader::manifest::sha256_hex;
+use crate::cache::FsCache;
+use crate::config::{PatchConfig, ServerConfig};
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+
+/// fetches (or reads) the configured browser bundle and stores it next to the build's assets
+pub async fn install_bundle(
+    client: &Client,
+    patch_config: &PatchConfig,
+    fs_cache: &FsCache,
+    build_hash: &str,
+) -> Result<()> {
+    let config = &patch_config.vencord;
+    let js = load_source(client, &patch_config.server, &config.bundle, config.bundle_sha256.as_deref())
+        .await
+        .context(format!("Failed to load client mod bundle from {}", config.bundle))?;
+    fs_cache.put_original(build_hash, BUNDLE_JS, &js).await?;
+
+    let css = match config.stylesheet {
+        Some(ref stylesheet) => {
+            match load_source(client, &patch_config.server, stylesheet, config.stylesheet_sha256.as_deref()).await {
+                Ok(css) => Some(css),
+                Err(e) => {
+                    // the bundle still works without its stylesheet, settings UI just looks off
+                    tracing::warn!("Failed to load client mod stylesheet from {}: {:#}", stylesheet, e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    match css {
+        Some(css) => fs_cache.put_original(build_hash, BUNDLE_CSS, &css).await?,
+        // a stylesheet left over from an older bundle would be served next to the new one
+        None => match tokio::fs::remove_file(fs_cache.original_dir(build_hash).join(BUNDLE_CSS)).await {
+            Ok(()) => tracing::info!("Removed the previous client mod stylesheet of build {}", build_hash),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        },
+    }
+
+    tracing::info!("Installed client mod bundle for build {} ({} bytes)", build_hash, js.len());
+    Ok(())
+}
+
+async fn load_source(client: &Client, server: &ServerConfig, source: &str, sha256: Option<&str>) -> Result<Vec<u8>> {
+    let remote = source.starts_with("http://") || source.starts_with("https://");
+    let data = if remote {
+        server.ensure_online("download the client mod bundle, point it at a local file")?;
+        let resp = client.get(source).send().await?.error_for_status()?;
+        resp.bytes().await?.to_vec()
+    } else {
+        tokio::fs::read(source).await?
+    };
+    let actual = sha256_hex(&data);
+    match sha256 {
+        Some(expected) if !actual.eq_ignore_ascii_case(expected.trim()) => {
+            bail!("{} has SHA-256 {}, expected {}", source, actual, expected)
+        }
+        // a release URL can be re-uploaded, so remote files are only installed once pinned
+        None if remote => bail!("{} has SHA-256 {}, set it in [vencord] to install this file", source, actual),
+        _ => Ok(data),
+    }
+}
@@ -0,0 +1,41 @@
+use super::{BUNDLE_CSS, BUNDLE_JS};
+use crate::config::VencordConfig;
+
+/// HTML to place right before the webpack entry scripts.
+/// The bundle is loaded synchronously so it is in place before the deferred entry scripts run.
+/// `asset_prefix` is where the build's assets are served, e.g. `/assets/`.
+pub fn generate_injection(config: &VencordConfig, has_stylesheet: bool, asset_prefix: &str) -> String {
+    let mut tags = Vec::new();
+
+    if has_stylesheet {
+        tags.push(format!(r#"    <link rel="stylesheet" href="{}{}">"#, asset_prefix, BUNDLE_CSS));
+    }
+
+    if !config.plugins.is_empty() {
+        let key_json = serde_json::to_string(&config.settings_key).expect("serialize settings key");
+        let plugins_json = serde_json::to_string(&config.plugins).expect("serialize plugins");
+        // only seed plugins the user never touched, so disabling one in the settings UI sticks
+        tags.push(format!(r#"    <script>
+        (function() {{
+            var key = {key_json};
+            var defaults = {plugins_json};
+            var settings;
+            try {{
+                settings = JSON.parse(localStorage.getItem(key)) || {{}};
+            }} catch (e) {{
+                settings = {{}};
+            }}
+            settings.plugins = settings.plugins || {{}};
+            for (var i = 0; i < defaults.length; i++) {{
+                if (!settings.plugins[defaults[i]]) {{
+                    settings.plugins[defaults[i]] = {{ enabled: true }};
+                }}
+            }}
+            localStorage.setItem(key, JSON.stringify(settings));
+        }})();
+    </script>"#));
+    }
+
+    tags.push(format!(r#"    <script src="{}{}"></script>"#, asset_prefix, BUNDLE_JS));
+    tags.join("\n")
+}
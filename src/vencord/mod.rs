@@ -0,0 +1,14 @@
+pub mod bundle;
+pub mod injector;
+
+pub use bundle::install_bundle;
+pub use injector::generate_injection;
+
+/// file names the bundle is stored under inside a build's cache directory
+pub const BUNDLE_JS: &str = "vencord.js";
+pub const BUNDLE_CSS: &str = "vencord.css";
+
+/// the client mod ships its own code, it must never go through the patch pipeline
+pub fn is_bundle_file(name: &str) -> bool {
+    name == BUNDLE_JS || name == BUNDLE_CSS
+}
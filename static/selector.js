@@ -1,11 +1,32 @@
 let allBuilds = [];
 let currentPage = 1;
 const PAGE_SIZE = 50;
+// the admin API is mounted at [server] admin_prefix, /api is Discord's
+const API = document.querySelector('meta[name="ug2-admin-prefix"]').content;
+
+// management calls need an API key (or ADMIN_TOKEN) unless [auth] is disabled.
+// Kept in memory only: patched Discord and the client mod run on this origin and can read its storage.
+let currentApiKey = '';
+function apiKey() {
+    return currentApiKey;
+}
+
+function api(url, options = {}) {
+    const key = apiKey();
+    const headers = Object.assign({}, options.headers);
+    if (key) headers['Authorization'] = 'Bearer ' + key;
+    return fetch(url, Object.assign({}, options, { headers }));
+}
 
 async function loadBuilds() {
     try {
-        const res = await fetch('/api/builds');
-        allBuilds = await res.json();
+        const res = await api(API + '/builds');
+        const data = await res.json();
+        if (!res.ok) {
+            showToast(data.message, true);
+            return;
+        }
+        allBuilds = data;
         currentPage = 1;
         renderBuilds();
         updateStatus();
@@ -44,13 +65,16 @@ function renderBuilds() {
             <td>
                 <div class="badges">
                     ${b.is_active ? '<span class="badge badge-active">active</span>' : ''}
-                    ${b.is_patched ? '<span class="badge badge-patched">patched</span>' : '<span class="badge badge-pending">pending</span>'}
+                    ${b.is_patched ? '<span class="badge badge-patched">patched</span>'
+                        : b.missing_assets ? `<span class="badge badge-incomplete" title="Download again to retry the missing assets">incomplete (${b.missing_assets} missing)</span>`
+                        : '<span class="badge badge-pending">pending</span>'}
                 </div>
             </td>
             <td class="actions">
                 ${!b.is_patched ? `<button class="button button-primary button-sm" onclick="downloadBuild('${b.build_hash}')">Download</button>` : ''}
                 ${b.is_patched && !b.is_active ? `<button class="button button-green button-sm" onclick="activateBuild('${b.build_hash}')">Activate</button>` : ''}
                 ${b.is_patched ? `<button class="button button-ghost button-sm" onclick="repatchBuild('${b.build_hash}')">Repatch</button>` : ''}
+                ${b.is_patched ? `<a class="button button-ghost button-sm" href="/build/${b.build_hash}/app" target="_blank" rel="noopener">Open</a>` : ''}
             </td>
         </tr>
     `).join('');
@@ -83,7 +107,7 @@ async function fetchCurrentBuild() {
     btn.disabled = true;
     btn.textContent = 'Fetching...';
     try {
-        const res = await fetch('/api/builds/fetch-current', { method: 'POST' });
+        const res = await api(API + '/builds/fetch-current', { method: 'POST' });
         const data = await res.json();
         if (data.status === 'error') {
             showToast(data.message, true);
@@ -91,9 +115,8 @@ async function fetchCurrentBuild() {
             return;
         }
         showToast(data.message);
-        const hashMatch = data.message.match(/([a-f0-9]{40})/);
-        if (hashMatch) {
-            pollBuildStatus(hashMatch[1]);
+        if (data.job_id) {
+            watchJob(data.job_id, 'current build');
         }
         setTimeout(() => resetFetchBtn(btn), 5000);
     } catch (e) {
@@ -112,14 +135,20 @@ async function downloadBuild(hash) {
     btn.disabled = true;
     btn.textContent = 'Downloading...';
     try {
-        const res = await fetch('/api/builds/download', {
+        const res = await api(API + '/builds/download', {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ build_hash: hash })
         });
         const data = await res.json();
+        if (data.status === 'error') {
+            showToast(data.message, true);
+            btn.disabled = false;
+            btn.textContent = 'Download';
+            return;
+        }
         showToast(data.message);
-        pollBuildStatus(hash);
+        watchJob(data.job_id, hash.slice(0, 12));
     } catch (e) {
         showToast('Download failed: ' + e.message, true);
         btn.disabled = false;
@@ -127,25 +156,66 @@ async function downloadBuild(hash) {
     }
 }
 
-function pollBuildStatus(hash) {
-    const interval = setInterval(async () => {
-        const res = await fetch('/api/builds');
-        const builds = await res.json();
-        const build = builds.find(b => b.build_hash === hash);
-        if (build && build.is_patched) {
-            clearInterval(interval);
-            allBuilds = builds;
-            renderBuilds();
-            updateStatus();
-            showToast('Build ' + hash.slice(0, 12) + ' ready!');
+function watchJob(jobId, label) {
+    const el = document.createElement('div');
+    el.className = 'job';
+    el.innerHTML = '<div class="job-line"><span>' + label + '</span>' +
+        '<span class="job-state">queued</span>' +
+        '<button class="button button-ghost button-sm job-cancel">Cancel</button></div>' +
+        '<div class="job-bar"><div class="job-bar-fill"></div></div>';
+    document.getElementById('jobsBlock').appendChild(el);
+    const stateEl = el.querySelector('.job-state');
+    const fillEl = el.querySelector('.job-bar-fill');
+    const cancelBtn = el.querySelector('.job-cancel');
+    cancelBtn.onclick = async () => {
+        cancelBtn.disabled = true;
+        const res = await api(API + '/jobs/' + jobId, { method: 'DELETE' });
+        if (!res.ok) showToast((await res.json()).message, true);
+    };
+    let progress = { queued: 0, downloaded: 0, failed: 0 };
+
+    // EventSource can't send headers, the key goes in the query instead
+    const query = apiKey() ? '?access_token=' + encodeURIComponent(apiKey()) : '';
+    const source = new EventSource(API + '/jobs/' + jobId + '/events' + query);
+    source.addEventListener('progress', e => {
+        progress = JSON.parse(e.data);
+        const total = progress.queued + progress.downloaded + progress.failed;
+        const done = progress.downloaded + progress.failed;
+        fillEl.style.width = (total ? Math.round(done / total * 100) : 0) + '%';
+        stateEl.textContent = 'downloading ' + done + '/' + total +
+            (progress.failed ? ' (' + progress.failed + ' failed)' : '');
+    });
+    source.addEventListener('state', async e => {
+        const data = JSON.parse(e.data);
+        if (data.state !== 'downloading') stateEl.textContent = data.state.replace('_', ' ');
+        if (data.state !== 'done' && data.state !== 'failed' && data.state !== 'cancelled') return;
+
+        source.close();
+        cancelBtn.remove();
+        el.classList.add(data.state);
+        fillEl.style.width = '100%';
+        setTimeout(() => el.remove(), 8000);
+        await loadBuilds();
+        if (data.state === 'cancelled') {
+            showToast('Download of ' + label + ' cancelled, downloading it again resumes it');
+        } else if (data.state === 'done') {
+            const failed = progress.failed ? ' (' + progress.failed + ' assets failed)' : '';
+            showToast('Build ' + label + ' ready!' + failed);
+        } else {
+            showToast('Build ' + label + ' failed' + (data.error ? ': ' + data.error : ''), true);
         }
-    }, 3000);
-    setTimeout(() => clearInterval(interval), 300000);
+    });
+    // the stream ends once the job has finished, don't let EventSource reconnect forever
+    source.onerror = () => {
+        if (source.readyState === EventSource.CLOSED || el.className !== 'job') return;
+        source.close();
+        stateEl.textContent = 'lost connection';
+    };
 }
 
 async function activateBuild(hash) {
     try {
-        const res = await fetch('/api/builds/active', {
+        const res = await api(API + '/builds/active', {
             method: 'PUT',
             headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ build_hash: hash })
@@ -165,7 +235,7 @@ async function activateBuild(hash) {
 
 async function repatchBuild(hash) {
     try {
-        const res = await fetch(`/api/builds/${hash}/repatch`, { method: 'POST' });
+        const res = await api(API + `/builds/${hash}/repatch`, { method: 'POST' });
         const data = await res.json();
         showToast(data.message);
     } catch (e) {
@@ -192,4 +262,12 @@ document.getElementById('search').addEventListener('input', () => {
 });
 document.getElementById('filterPatched').addEventListener('change', () => { currentPage = 1; renderBuilds(); });
 
+const apiKeyInput = document.getElementById('apiKey');
+// older versions saved the key
+localStorage.removeItem('ug2_api_key');
+apiKeyInput.addEventListener('change', () => {
+    currentApiKey = apiKeyInput.value.trim();
+    loadBuilds();
+});
+
 loadBuilds();
@@ -0,0 +1,28 @@
+use axum::http::Uri;
+use ug2_client::config::ServerConfig;
+use ug2_client::server::routes::{admin_prefix, legacy_api_target};
+
+fn server(admin_prefix: &str) -> ServerConfig {
+    ServerConfig { admin_prefix: admin_prefix.to_string(), ..Default::default() }
+}
+
+#[test]
+fn test_admin_prefix() {
+    assert_eq!(admin_prefix(&ServerConfig::default()).unwrap(), "/_ug2/api");
+    assert_eq!(admin_prefix(&server("/admin/")).unwrap(), "/admin");
+
+    // the Discord API proxy owns /api
+    assert!(admin_prefix(&server("/api")).is_err());
+    assert!(admin_prefix(&server("/api/admin")).is_err());
+    assert!(admin_prefix(&server("admin")).is_err());
+    assert!(admin_prefix(&server("/")).is_err());
+}
+
+#[test]
+fn test_legacy_api_target() {
+    let uri: Uri = "/api/jobs/12/events?access_token=ug2_abc".parse().unwrap();
+    assert_eq!(legacy_api_target("/_ug2/api", &uri), "/_ug2/api/jobs/12/events?access_token=ug2_abc");
+
+    let uri: Uri = "/api/builds".parse().unwrap();
+    assert_eq!(legacy_api_target("/admin/", &uri), "/admin/builds");
+}
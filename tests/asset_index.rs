@@ -0,0 +1,134 @@
+use sea_orm::*;
+use ug2_client::cache::asset_index::{self, guess_content_type, AccessTracker, AssetRecord};
+use ug2_client::db::{self, models::{asset_cache, discord_build}};
+
+async fn db_with_build(hash: &str) -> DatabaseConnection {
+    let db = db::connect("sqlite::memory:").await.unwrap();
+    db::run_migrations(&db).await.unwrap();
+    discord_build::ActiveModel {
+        build_hash: Set(hash.into()),
+        channel: Set("canary".into()),
+        build_date: Set(chrono::Utc::now().fixed_offset()),
+        scripts: Set(serde_json::json!([])),
+        index_scripts: Set(serde_json::json!([])),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+    db
+}
+
+fn long_ago() -> sea_orm::prelude::DateTimeWithTimeZone {
+    "2020-01-01T00:00:00+00:00".parse().unwrap()
+}
+
+/// pretends every asset was created and last requested long ago
+async fn age_assets(db: &DatabaseConnection) {
+    asset_cache::Entity::update_many()
+        .col_expr(asset_cache::Column::CreatedAt, sea_orm::prelude::Expr::value(long_ago()))
+        .col_expr(asset_cache::Column::LastAccessed, sea_orm::prelude::Expr::value(long_ago()))
+        .exec(db)
+        .await
+        .unwrap();
+}
+
+async fn asset(db: &DatabaseConnection, name: &str) -> asset_cache::Model {
+    asset_cache::Entity::find()
+        .filter(asset_cache::Column::AssetName.eq(name))
+        .one(db)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[test]
+fn test_content_types() {
+    assert_eq!(guess_content_type("web.a1b2.js"), "application/javascript");
+    assert_eq!(guess_content_type("app.css"), "text/css");
+    assert_eq!(guess_content_type("font.woff2"), "font/woff2");
+    assert_eq!(guess_content_type("web.js.map"), "application/json");
+    assert_eq!(guess_content_type("blob"), "application/octet-stream");
+}
+
+#[test]
+fn test_access_tracker_batches_hits() {
+    let tracker = AccessTracker::default();
+    tracker.touch("abc", "web.js");
+    tracker.touch("abc", "web.js");
+    tracker.touch("abc", "app.css");
+    tracker.touch("def", "web.js");
+
+    let touched = tracker.take();
+    assert_eq!(touched.len(), 2);
+    assert_eq!(touched["abc"].len(), 2);
+    assert!(touched["def"].contains("web.js"));
+    assert!(tracker.take().is_empty());
+}
+
+#[tokio::test]
+async fn test_record_assets_keeps_timestamps() {
+    let db = db_with_build("abc").await;
+    let record = |size, is_patched| vec![AssetRecord { asset_name: "web.js".into(), file_size: size, is_patched }];
+    asset_index::record_assets(&db, "abc", record(100, false)).await.unwrap();
+    age_assets(&db).await;
+
+    // a repatch updates the row in place
+    asset_index::record_assets(&db, "abc", record(120, true)).await.unwrap();
+    let row = asset(&db, "web.js").await;
+    assert_eq!((row.file_size, row.is_patched), (120, true));
+    assert_eq!(row.created_at, long_ago());
+    assert_eq!(row.last_accessed, long_ago());
+    assert_eq!(asset_cache::Entity::find().count(&db).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_replace_assets_forgets_renamed_files() {
+    let db = db_with_build("abc").await;
+    let record = |name: &str| AssetRecord { asset_name: name.into(), file_size: 10, is_patched: true };
+    asset_index::replace_assets(&db, "abc", vec![record("web.1111.js"), record("logo.png")]).await.unwrap();
+    age_assets(&db).await;
+
+    // a repatch renamed web.js, the old name must not count towards the build's size anymore
+    asset_index::replace_assets(&db, "abc", vec![record("web.2222.js"), record("logo.png")]).await.unwrap();
+    let mut names: Vec<String> = asset_cache::Entity::find().all(&db).await.unwrap().into_iter().map(|a| a.asset_name).collect();
+    names.sort();
+    assert_eq!(names, ["logo.png", "web.2222.js"]);
+    assert_eq!(asset(&db, "logo.png").await.created_at, long_ago());
+
+    // recording a single fetched asset leaves the rest alone
+    asset_index::record_assets(&db, "abc", vec![record("extra.js")]).await.unwrap();
+    assert_eq!(asset_cache::Entity::find().count(&db).await.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_access_flush_updates_touched_assets() {
+    let db = db_with_build("abc").await;
+    // more hits than fit in one update statement
+    let names: Vec<String> = (0..1200).map(|i| format!("{}.js", i)).collect();
+    let records = names
+        .iter()
+        .chain(["untouched.js".to_string()].iter())
+        .map(|name| AssetRecord { asset_name: name.clone(), file_size: 1, is_patched: false })
+        .collect();
+    asset_index::record_assets(&db, "abc", records).await.unwrap();
+    age_assets(&db).await;
+
+    let tracker = AccessTracker::default();
+    for name in &names {
+        tracker.touch("abc", name);
+    }
+    tracker.touch("gone", "web.js");
+    tracker.flush(&db).await.unwrap();
+
+    let touched = asset_cache::Entity::find()
+        .filter(asset_cache::Column::LastAccessed.gt(long_ago()))
+        .count(&db)
+        .await
+        .unwrap();
+    assert_eq!(touched, 1200);
+    assert_eq!(asset(&db, "untouched.js").await.last_accessed, long_ago());
+    assert_eq!(asset(&db, "0.js").await.created_at, long_ago());
+    // the buffer is empty after a flush
+    assert!(tracker.take().is_empty());
+}
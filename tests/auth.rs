@@ -0,0 +1,103 @@
+use axum::http::{HeaderMap, Method};
+use std::collections::HashMap;
+use ug2_client::config::PatchConfig;
+use sea_orm::*;
+use ug2_client::db::{self, models::api_key};
+use ug2_client::server::auth::{self, Scope};
+
+#[test]
+fn test_scopes() {
+    assert_eq!(Scope::required_for(&Method::GET), Scope::Read);
+    assert_eq!(Scope::required_for(&Method::HEAD), Scope::Read);
+    assert_eq!(Scope::required_for(&Method::PUT), Scope::Admin);
+    assert_eq!(Scope::required_for(&Method::DELETE), Scope::Admin);
+    assert!(Scope::Admin > Scope::Read);
+
+    assert_eq!(Scope::parse("admin"), Some(Scope::Admin));
+    assert_eq!(Scope::parse(Scope::Read.as_str()), Some(Scope::Read));
+    assert_eq!(Scope::parse("root"), None);
+}
+
+#[test]
+fn test_generated_keys() {
+    let (key, hash) = auth::generate_key();
+    assert!(key.starts_with(auth::KEY_PREFIX));
+    assert_eq!(key.len(), auth::KEY_PREFIX.len() + 64);
+    assert_eq!(hash, auth::hash_key(&key));
+    assert_ne!(auth::generate_key().0, key);
+}
+
+#[test]
+fn test_presented_token() {
+    let mut headers = HeaderMap::new();
+    let mut query = HashMap::new();
+    assert_eq!(auth::presented_token(&headers, &query), None);
+
+    query.insert("access_token".to_string(), "from-query".to_string());
+    assert_eq!(auth::presented_token(&headers, &query).as_deref(), Some("from-query"));
+
+    headers.insert("authorization", "Bearer ug2_abc".parse().unwrap());
+    assert_eq!(auth::presented_token(&headers, &query).as_deref(), Some("ug2_abc"));
+
+    // Discord tokens sent to the proxy aren't bearer tokens
+    headers.insert("authorization", "mfa.discord-token".parse().unwrap());
+    assert_eq!(auth::presented_token(&headers, &HashMap::new()), None);
+}
+
+#[test]
+fn test_auth_defaults() {
+    let config: PatchConfig = toml::from_str(r#"
+[patches]
+nitro_rebranding = false
+discord_rebranding = false
+title_rebranding = false
+server_to_guild = false
+sentry_redirect = false
+status_page_redirect = false
+prevent_localstorage_deletion = false
+fast_identify = false
+gateway_reconnect = false
+remove_qr_login = false
+enable_dev_experiments = false
+remove_modals = false
+no_xss_warning = false
+vencord = false
+api_proxy = false
+
+[branding]
+instance_name = "Underground"
+instance_url = "http://localhost:5002"
+sentry_url = "https://sentry.example.com"
+status_url = "status.example.com"
+"#).unwrap();
+    // an upgraded deployment without [auth] is protected too
+    assert!(config.auth.enabled);
+    assert!(config.auth.public_builds_list);
+}
+
+#[test]
+fn test_query_tokens_only_for_event_streams() {
+    assert!(auth::accepts_query_token(&Method::GET, "/_ug2/api/jobs/3/events"));
+    assert!(!auth::accepts_query_token(&Method::GET, "/_ug2/api/builds"));
+    assert!(!auth::accepts_query_token(&Method::POST, "/_ug2/api/jobs/3/events"));
+}
+
+#[tokio::test]
+async fn test_last_used_is_debounced() {
+    let db = db::connect("sqlite::memory:").await.unwrap();
+    db::run_migrations(&db).await.unwrap();
+    auth::create_key(&db, "ci", Scope::Read).await.unwrap();
+    let key = || async { api_key::Entity::find().one(&db).await.unwrap().unwrap() };
+
+    assert!(auth::touch_key(&db, &key().await).await.unwrap());
+    let used = key().await.last_used_at.unwrap();
+    // a second request right after doesn't write again
+    assert!(!auth::touch_key(&db, &key().await).await.unwrap());
+    assert_eq!(key().await.last_used_at, Some(used));
+
+    let mut stale = key().await.into_active_model();
+    stale.last_used_at = Set(Some((chrono::Utc::now() - chrono::TimeDelta::minutes(5)).fixed_offset()));
+    stale.update(&db).await.unwrap();
+    assert!(auth::touch_key(&db, &key().await).await.unwrap());
+    assert!(key().await.last_used_at.unwrap() > chrono::Utc::now() - chrono::TimeDelta::minutes(1));
+}
@@ -0,0 +1,103 @@
+use std::os::unix::fs::MetadataExt;
+use ug2_client::asset_downloader::manifest::sha256_hex;
+use ug2_client::cache::FsCache;
+use ug2_client::config::PatchConfig;
+use ug2_client::patcher::PatchPipeline;
+
+fn pipeline() -> PatchPipeline {
+    let config: PatchConfig = toml::from_str(r#"
+patches_dir = "does-not-exist"
+
+[patches]
+nitro_rebranding = false
+discord_rebranding = true
+title_rebranding = false
+server_to_guild = false
+sentry_redirect = false
+status_page_redirect = false
+prevent_localstorage_deletion = false
+fast_identify = false
+gateway_reconnect = false
+remove_qr_login = false
+enable_dev_experiments = false
+remove_modals = false
+no_xss_warning = false
+vencord = false
+api_proxy = false
+
+[branding]
+instance_name = "Underground"
+instance_url = "http://localhost:5002"
+sentry_url = "https://sentry.example.com"
+status_url = "status.example.com"
+"#).unwrap();
+    PatchPipeline::new(&config).unwrap()
+}
+
+fn temp_cache(name: &str) -> (std::path::PathBuf, FsCache) {
+    let base = std::env::temp_dir().join(format!("ug2-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(&base).unwrap();
+    (base.clone(), FsCache::new(base))
+}
+
+fn inode(path: std::path::PathBuf) -> u64 {
+    std::fs::metadata(path).unwrap().ino()
+}
+
+#[tokio::test]
+async fn test_builds_share_patched_blobs() {
+    let (base, cache) = temp_cache("blob-share");
+    for build in ["aaa", "bbb"] {
+        cache.put_original(build, "web.js", b"Welcome to Discord").await.unwrap();
+    }
+    cache.put_original("bbb", "other.js", b"Discord only here").await.unwrap();
+
+    let pipeline = pipeline();
+    let first = pipeline.patch_cached_build(&cache, "aaa", None).await.unwrap();
+    let second = pipeline.patch_cached_build(&cache, "bbb", None).await.unwrap();
+
+    // the second build reuses the first one's output and still reports what the patch did there
+    assert_eq!(
+        inode(cache.patched_dir("aaa").join("web.js")),
+        inode(cache.patched_dir("bbb").join("web.js")),
+    );
+    assert_eq!(cache.get_asset("bbb", "web.js").await.unwrap().unwrap(), b"Welcome to Underground");
+    assert_eq!(first.files_changed, 1);
+    assert_eq!(second.files_changed, 2);
+    assert_eq!(second.patches["discord_rebranding"].replacements, 2);
+
+    let mut builds = cache.list_builds().await.unwrap();
+    builds.sort();
+    assert_eq!(builds, vec!["aaa", "bbb"]);
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[tokio::test]
+async fn test_store_keeps_originals_until_unreferenced() {
+    let (base, cache) = temp_cache("blob-prune");
+    let store = cache.blob_store();
+    let data = b"chunk contents";
+    let sha = sha256_hex(data);
+
+    cache.put_original("aaa", "1234.js", data).await.unwrap();
+    store.adopt_original("1234.js", &cache.original_dir("aaa").join("1234.js"), &sha).await.unwrap();
+
+    let dest = cache.original_dir("bbb");
+    std::fs::create_dir_all(&dest).unwrap();
+    assert_eq!(store.link_by_name("1234.js", &dest.join("1234.js")).await.unwrap(), Some(sha.clone()));
+    assert_eq!(store.link_by_name("5678.js", &dest.join("5678.js")).await.unwrap(), None);
+    assert_eq!(std::fs::read(dest.join("1234.js")).unwrap(), data);
+
+    // still linked from one build
+    std::fs::remove_dir_all(cache.build_dir("aaa")).unwrap();
+    assert_eq!(store.prune().await.unwrap(), 0);
+
+    std::fs::remove_dir_all(cache.build_dir("bbb")).unwrap();
+    assert_eq!(store.prune().await.unwrap(), data.len() as u64);
+    let scratch = base.join("scratch.js");
+    assert_eq!(store.link_by_name("1234.js", &scratch).await.unwrap(), None);
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,144 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use ug2_client::cache::memory_store::MemoryStore;
+use ug2_client::cache::store::{self, CacheStore, FallbackStore};
+use ug2_client::config::{BucketLimit, CacheBackend};
+use ug2_client::server::rate_limit::Decision;
+
+/// fails every call while `down` is set, stores nothing
+struct FlakyStore {
+    down: Arc<AtomicBool>,
+    calls: Arc<AtomicUsize>,
+}
+
+impl FlakyStore {
+    fn new(down: &Arc<AtomicBool>) -> Self {
+        Self { down: down.clone(), calls: Arc::default() }
+    }
+}
+
+#[async_trait]
+impl CacheStore for FlakyStore {
+    fn backend(&self) -> &'static str {
+        "flaky"
+    }
+
+    async fn get(&self, _key: &str) -> Result<Option<String>> {
+        self.check().map(|_| None)
+    }
+
+    async fn set_ex(&self, _key: &str, _value: &str, _ttl_secs: u64) -> Result<()> {
+        self.check()
+    }
+
+    async fn del(&self, _key: &str) -> Result<()> {
+        self.check()
+    }
+
+    async fn rate_limit(&self, _key: &str, limit: BucketLimit) -> Result<Decision> {
+        self.check()?;
+        Ok(Decision { allowed: true, limit: limit.requests, remaining: limit.requests, reset_after_ms: 0, retry_after_ms: 0 })
+    }
+}
+
+impl FlakyStore {
+    fn check(&self) -> Result<()> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        if self.down.load(Ordering::Relaxed) {
+            anyhow::bail!("connection refused");
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_memory_store_ttl() {
+    let store = MemoryStore::default();
+    store.set_ex(store::BUILDS_LIST_KEY, "[]", 30).await.unwrap();
+    store.set_ex("expired", "x", 0).await.unwrap();
+    assert_eq!(store.get(store::BUILDS_LIST_KEY).await.unwrap().as_deref(), Some("[]"));
+    assert_eq!(store.get("expired").await.unwrap(), None);
+
+    store::invalidate_builds_cache(&store).await.unwrap();
+    assert_eq!(store.get(store::BUILDS_LIST_KEY).await.unwrap(), None);
+}
+
+#[test]
+fn test_memory_store_rate_limit() {
+    let store = MemoryStore::default();
+    let limit = BucketLimit { requests: 2, window_secs: 10 };
+    assert!(store.rate_limit_at("a", limit, 1_000).allowed);
+    assert!(store.rate_limit_at("a", limit, 1_000).allowed);
+    let denied = store.rate_limit_at("a", limit, 1_000);
+    assert!(!denied.allowed);
+    assert_eq!(denied.retry_after_ms, 5_000);
+    // keys don't share buckets
+    assert!(store.rate_limit_at("b", limit, 1_000).allowed);
+    assert!(store.rate_limit_at("a", limit, 6_000).allowed);
+}
+
+const RETRY: Duration = Duration::from_millis(10);
+
+async fn wait_for_recovery(store: &FallbackStore) {
+    for _ in 0..200 {
+        if !store.is_degraded() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    panic!("the primary store never came back");
+}
+
+#[tokio::test]
+async fn test_fallback_store() {
+    let down = Arc::new(AtomicBool::new(false));
+    let store = FallbackStore::with_retry(Box::new(FlakyStore::new(&down)), RETRY, RETRY);
+    let limit = BucketLimit { requests: 1, window_secs: 60 };
+    assert_eq!(store.backend(), "flaky");
+
+    down.store(true, Ordering::Relaxed);
+    store.set_ex("key", "value", 30).await.unwrap();
+    assert!(store.is_degraded());
+    assert_eq!(store.backend(), "memory");
+    assert_eq!(store.get("key").await.unwrap().as_deref(), Some("value"));
+    assert!(store.rate_limit("rl", limit).await.unwrap().allowed);
+    assert!(!store.rate_limit("rl", limit).await.unwrap().allowed);
+
+    down.store(false, Ordering::Relaxed);
+    wait_for_recovery(&store).await;
+    assert_eq!(store.get("key").await.unwrap(), None);
+    assert!(store.rate_limit("rl", limit).await.unwrap().allowed);
+
+    // deletes reach the in-process copy too
+    down.store(true, Ordering::Relaxed);
+    store.del("key").await.unwrap();
+    assert_eq!(store.get("key").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_fallback_store_skips_a_failed_primary() {
+    let down = Arc::new(AtomicBool::new(true));
+    let primary = FlakyStore::new(&down);
+    let calls = primary.calls.clone();
+    let store = FallbackStore::with_retry(Box::new(primary), Duration::from_secs(60), Duration::from_secs(60));
+
+    // only the first request waits on the failed primary
+    store.set_ex("key", "value", 30).await.unwrap();
+    for _ in 0..10 {
+        assert_eq!(store.get("key").await.unwrap().as_deref(), Some("value"));
+        store.rate_limit("rl", BucketLimit { requests: 100, window_secs: 60 }).await.unwrap();
+    }
+    assert_eq!(calls.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn test_auto_backend_without_redis() {
+    // nothing listens on port 1, the server starts on the in-process store anyway
+    let store = store::connect(CacheBackend::Auto, "redis://127.0.0.1:1").await.unwrap();
+    assert_eq!(store.backend(), "memory");
+    store.set_ex("key", "value", 30).await.unwrap();
+    assert_eq!(store.get("key").await.unwrap().as_deref(), Some("value"));
+}
@@ -0,0 +1,87 @@
+use axum::http::{header, HeaderMap};
+use std::time::{Duration, SystemTime};
+use ug2_client::cache::content_hash::ContentHashes;
+use ug2_client::cache::FsCache;
+use ug2_client::server::conditional::{self, ByteRange, RangeRequest};
+
+#[test]
+fn test_parse_range() {
+    let partial = |start, end| RangeRequest::Partial(ByteRange { start, end });
+    assert_eq!(conditional::parse_range("bytes=0-99", 1000), partial(0, 99));
+    assert_eq!(conditional::parse_range("bytes=900-", 1000), partial(900, 999));
+    assert_eq!(conditional::parse_range("bytes=-100", 1000), partial(900, 999));
+    assert_eq!(conditional::parse_range("bytes=990-2000", 1000), partial(990, 999));
+    assert_eq!(conditional::parse_range("bytes=-5000", 1000), partial(0, 999));
+    assert_eq!(conditional::parse_range("bytes=1000-", 1000), RangeRequest::Unsatisfiable);
+    assert_eq!(conditional::parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+    assert_eq!(conditional::parse_range("bytes=0-1,5-9", 1000), RangeRequest::Full);
+    assert_eq!(conditional::parse_range("bytes=9-5", 1000), RangeRequest::Full);
+    assert_eq!(conditional::parse_range("items=0-5", 1000), RangeRequest::Full);
+    assert_eq!(ByteRange { start: 900, end: 999 }.content_range(1000), "bytes 900-999/1000");
+}
+
+#[test]
+fn test_not_modified() {
+    let etag = "\"abc\"";
+    let mut headers = HeaderMap::new();
+    assert!(!conditional::is_not_modified(&headers, etag, None));
+
+    headers.insert(header::IF_NONE_MATCH, "\"old\", \"abc\"".parse().unwrap());
+    assert!(conditional::is_not_modified(&headers, etag, None));
+    headers.insert(header::IF_NONE_MATCH, "\"abc-br\"".parse().unwrap());
+    assert!(!conditional::is_not_modified(&headers, etag, None));
+
+    // If-Modified-Since is ignored next to If-None-Match
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    headers.insert(header::IF_MODIFIED_SINCE, conditional::http_date(modified).parse().unwrap());
+    assert!(!conditional::is_not_modified(&headers, etag, Some(modified)));
+    headers.remove(header::IF_NONE_MATCH);
+    assert!(conditional::is_not_modified(&headers, etag, Some(modified)));
+    assert!(!conditional::is_not_modified(&headers, etag, Some(modified + Duration::from_secs(60))));
+}
+
+#[tokio::test]
+async fn test_content_hash_follows_repatch() {
+    let base = std::env::temp_dir().join(format!("ug2-content-hash-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    let cache = FsCache::new(base.clone());
+    let hashes = ContentHashes::default();
+
+    let dir = cache.build_dir("abc");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("web.js"), "one").unwrap();
+    let first = hashes.get(&cache, "abc", "web.js").await.unwrap();
+    assert!(!first.immutable);
+    assert_eq!(first.sha256, ug2_client::asset_downloader::manifest::sha256_hex(b"one"));
+
+    // hashes come from the manifest once there is one, a rewritten manifest is picked up
+    std::fs::write(
+        dir.join("manifest.json"),
+        r#"{"assets":{"web.js":{"status":"downloaded","patched_sha256":"feed"}}}"#,
+    )
+    .unwrap();
+    assert_eq!(hashes.get(&cache, "abc", "web.js").await.unwrap().sha256, "feed");
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[tokio::test]
+async fn test_content_hash_follows_repatch_without_manifest() {
+    let base = std::env::temp_dir().join(format!("ug2-content-hash-backfilled-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    let cache = FsCache::new(base.clone());
+    let hashes = ContentHashes::default();
+
+    // a backfilled build: originals and a patched tree, but no manifest
+    cache.put_original("abc", "web.js", b"original").await.unwrap();
+    for content in ["one", "two"] {
+        let staging = cache.stage_patched("abc").await.unwrap();
+        std::fs::write(staging.join("web.js"), content).unwrap();
+        cache.publish_patched("abc", &staging).await.unwrap();
+
+        let served = hashes.get(&cache, "abc", "web.js").await.unwrap();
+        assert_eq!(served.sha256, ug2_client::asset_downloader::manifest::sha256_hex(content.as_bytes()));
+    }
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,100 @@
+use chrono::NaiveDate;
+use ug2_client::patcher::declarative::*;
+use ug2_client::patcher::Patch;
+
+fn load(source: &str) -> anyhow::Result<Vec<DeclarativePatch>> {
+    parse_definitions("test.toml", source)?
+        .into_iter()
+        .map(DeclarativePatch::from_definition)
+        .collect()
+}
+
+#[test]
+fn test_literal_patch() {
+    let patches = load(r#"
+[[patches]]
+name = "hide_gift_button"
+find = "showGiftButton:!0"
+replace = "showGiftButton:!1"
+"#).unwrap();
+    assert_eq!(patches[0].name(), "hide_gift_button");
+    assert_eq!(patches[0].apply("a={showGiftButton:!0}".into()), "a={showGiftButton:!1}");
+}
+
+#[test]
+fn test_regex_patch_with_captures() {
+    let patches = load(r#"
+[[patches]]
+name = "max_upload"
+find_regex = '(maxFileSize:)\d+'
+replace = "${1}524288000"
+"#).unwrap();
+    assert_eq!(patches[0].apply("{maxFileSize:10485760}".into()), "{maxFileSize:524288000}");
+}
+
+#[test]
+fn test_json_definitions() {
+    let defs = parse_definitions(
+        "x.json",
+        r#"{"patches":[{"name":"a","find":"x","replace":"y","required":true}]}"#,
+    )
+    .unwrap();
+    let patch = DeclarativePatch::from_definition(defs.into_iter().next().unwrap()).unwrap();
+    assert!(patch.is_required());
+}
+
+#[test]
+fn test_file_glob() {
+    let patches = load(r#"
+[[patches]]
+name = "web_only"
+find = "a"
+replace = "b"
+files = "web.*.js"
+"#).unwrap();
+    assert!(patches[0].applies_to_file("web.65877e3d81a538c8.js"));
+    assert!(!patches[0].applies_to_file("sentry.fe742426952e96ab.js"));
+}
+
+#[test]
+fn test_build_date_range() {
+    let patches = load(r#"
+[[patches]]
+name = "2022_only"
+find = "a"
+replace = "b"
+min_build_date = 2022-01-01
+max_build_date = 2022-12-31
+"#).unwrap();
+    let date = |s: &str| Some(NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap());
+    assert!(patches[0].applies_to_build(date("2022-06-15")));
+    assert!(!patches[0].applies_to_build(date("2025-01-01")));
+    assert!(patches[0].applies_to_build(None));
+}
+
+#[test]
+fn test_malformed_definitions_are_rejected() {
+    assert!(load("[[patches]]\nname = \"a\"\nreplace = \"b\"\n").is_err());
+    assert!(load("[[patches]]\nname = \"a\"\nfind = \"x\"\nfind_regex = \"x\"\nreplace = \"b\"\n").is_err());
+    assert!(load("[[patches]]\nname = \"a\"\nfind_regex = \"(\"\nreplace = \"b\"\n").is_err());
+    assert!(load("[[patches]]\nname = \"a\"\nfind = \"x\"\nreplace = \"b\"\nfiles = \"[\"\n").is_err());
+    assert!(load("[[patches]]\nname = \"a\"\nfind = \"x\"\nreplace = \"b\"\ntypo = 1\n").is_err());
+}
+
+#[test]
+fn test_load_dir() {
+    let dir = std::env::temp_dir().join(format!("ug2-patches-d-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.toml"), "[[patches]]\nname = \"a\"\nfind = \"x\"\nreplace = \"y\"\n").unwrap();
+    std::fs::write(dir.join("b.json"), r#"{"patches":[{"name":"b","find":"y","replace":"z"}]}"#).unwrap();
+    std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+    let patches = load_dir(&dir).unwrap();
+    assert_eq!(patches.iter().map(|p| p.name()).collect::<Vec<_>>(), ["a", "b"]);
+
+    std::fs::write(dir.join("c.toml"), "[[patches]]\nname = \"a\"\nfind = \"x\"\nreplace = \"y\"\n").unwrap();
+    assert!(load_dir(&dir).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(load_dir(&dir).unwrap().is_empty());
+}
@@ -0,0 +1,154 @@
+use chrono::{DateTime, FixedOffset};
+use std::collections::HashSet;
+use sea_orm::*;
+use ug2_client::cache::gc::{collect, dir_size, plan, CachedBuild};
+use ug2_client::cache::FsCache;
+use ug2_client::db::{self, models::discord_build};
+use ug2_client::config::GcConfig;
+
+fn at(day: u32) -> Option<DateTime<FixedOffset>> {
+    Some(DateTime::parse_from_rfc3339(&format!("2023-01-{:02}T00:00:00Z", day)).unwrap())
+}
+
+fn build(hash: &str, built: u32, served: Option<u32>) -> CachedBuild {
+    CachedBuild {
+        build_hash: hash.into(),
+        size: 100,
+        last_accessed: served.and_then(at),
+        build_date: at(built),
+        downloaded_at: at(built),
+    }
+}
+
+fn config(max_total_bytes: u64, keep_recent: usize) -> GcConfig {
+    GcConfig { max_total_bytes, keep_recent, ..Default::default() }
+}
+
+#[test]
+fn test_plan_evicts_least_recently_served_first() {
+    let builds = [
+        build("a", 1, Some(20)),
+        build("b", 2, Some(10)),
+        build("c", 3, None),
+        build("d", 4, Some(5)),
+        build("e", 5, Some(1)),
+    ];
+    // 500 bytes, 250 allowed, the newest build is kept
+    assert_eq!(plan(&builds, &HashSet::new(), &config(250, 1)), vec!["c", "d", "b"]);
+}
+
+#[test]
+fn test_plan_dates_unserved_builds_by_download() {
+    let mut fresh = build("fresh", 1, None);
+    fresh.downloaded_at = at(30);
+    let builds = [fresh, build("old", 2, Some(10)), build("older", 3, Some(5))];
+    assert_eq!(plan(&builds, &HashSet::new(), &config(150, 0)), vec!["older", "old"]);
+}
+
+#[test]
+fn test_plan_keeps_protected_and_recent_builds() {
+    let builds = [build("a", 1, None), build("b", 2, None), build("c", 3, None)];
+    let protected = HashSet::from(["a".to_string()]);
+    assert_eq!(plan(&builds, &protected, &config(1, 1)), vec!["b"]);
+    // under the limit, or no limit at all
+    assert!(plan(&builds, &HashSet::new(), &config(300, 0)).is_empty());
+    assert!(plan(&builds, &HashSet::new(), &config(0, 0)).is_empty());
+}
+
+#[test]
+fn test_dir_size_counts_hard_links_once() {
+    let dir = std::env::temp_dir().join(format!("ug2-gc-size-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("original")).unwrap();
+    std::fs::create_dir_all(dir.join("patched")).unwrap();
+    std::fs::write(dir.join("original/a.js"), [0u8; 10]).unwrap();
+    std::fs::write(dir.join("patched/b.js"), [0u8; 5]).unwrap();
+    std::fs::hard_link(dir.join("original/a.js"), dir.join("patched/a.js")).unwrap();
+
+    let expected = if cfg!(unix) { 15 } else { 25 };
+    assert_eq!(dir_size(&dir).unwrap(), expected);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_collect_counts_shared_blobs_once() {
+    let dir = std::env::temp_dir().join(format!("ug2-gc-shared-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let blob = dir.join(".store/original/ab/abcd");
+    std::fs::create_dir_all(blob.parent().unwrap()).unwrap();
+    std::fs::write(&blob, [0u8; 10]).unwrap();
+
+    let db = db::connect("sqlite::memory:").await.unwrap();
+    db::run_migrations(&db).await.unwrap();
+    // both builds link the stored blob and have a file of their own
+    for (day, hash, own) in [(1, "old", 5), (2, "new", 7)] {
+        std::fs::create_dir_all(dir.join(hash).join("original")).unwrap();
+        std::fs::hard_link(&blob, dir.join(hash).join("original/shared.js")).unwrap();
+        std::fs::write(dir.join(hash).join("original/own.js"), vec![0u8; own]).unwrap();
+        discord_build::ActiveModel {
+            build_hash: Set(hash.into()),
+            channel: Set("canary".into()),
+            build_date: Set(at(day).unwrap()),
+            scripts: Set(serde_json::json!([])),
+            index_scripts: Set(serde_json::json!([])),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+    }
+    let cache = FsCache::new(dir.clone());
+
+    // the shared blob stays with the newer build, so evicting the older one only frees its own file
+    let report = collect(&db, &cache, &config(20, 0), &HashSet::new(), true).await.unwrap();
+    assert_eq!(report.total_bytes, 22);
+    assert_eq!(report.evicted.iter().map(|b| (b.build_hash.as_str(), b.size)).collect::<Vec<_>>(), [("old", 5)]);
+    assert_eq!(report.total_bytes_after, 17);
+
+    // the second build out frees the blob too
+    let report = collect(&db, &cache, &config(10, 0), &HashSet::new(), false).await.unwrap();
+    assert_eq!(
+        report.evicted.iter().map(|b| (b.build_hash.as_str(), b.size)).collect::<Vec<_>>(),
+        [("old", 5), ("new", 17)]
+    );
+    assert_eq!(report.total_bytes_after, 0);
+    assert_eq!(dir_size(&dir).unwrap(), 0);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_collect_keeps_builds_in_use() {
+    let dir = std::env::temp_dir().join(format!("ug2-gc-in-use-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let db = db::connect("sqlite::memory:").await.unwrap();
+    db::run_migrations(&db).await.unwrap();
+    for (day, hash) in [(1, "patching"), (2, "unrecorded"), (3, "idle")] {
+        std::fs::create_dir_all(dir.join(hash).join("original")).unwrap();
+        std::fs::write(dir.join(hash).join("original/web.js"), [0u8; 10]).unwrap();
+        // the download finished but the patch that records it hasn't
+        if hash == "unrecorded" {
+            continue;
+        }
+        discord_build::ActiveModel {
+            build_hash: Set(hash.into()),
+            channel: Set("canary".into()),
+            build_date: Set(at(day).unwrap()),
+            scripts: Set(serde_json::json!([])),
+            index_scripts: Set(serde_json::json!([])),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+    }
+    let cache = FsCache::new(dir.clone());
+
+    let in_use = HashSet::from(["patching".to_string()]);
+    let report = collect(&db, &cache, &config(1, 0), &in_use, true).await.unwrap();
+    assert_eq!(report.evicted.iter().map(|b| b.build_hash.as_str()).collect::<Vec<_>>(), ["idle"]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
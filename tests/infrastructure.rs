@@ -68,3 +68,17 @@ fn test_cdn_redirect_reapplies_correctly_on_already_patched_content() {
     let expected = "https://cdn.celeste.gg/attachments/x.png https://cdn.discordapp.com/assets/y.png";
     assert_eq!(patch.apply(input.into()), expected);
 }
+
+#[test]
+fn test_cdn_redirect_counts_forward_replacements_only() {
+    let bypass = vec!["/assets/".to_string(), "/detectables/".to_string()];
+    let patch = CdnRedirect::new("cdn.celeste.gg", "media.celeste.gg", bypass);
+    let input = "https://cdn.discordapp.com/attachments/x.png https://media.discordapp.net/y.png";
+    assert_eq!(patch.apply_counted(input.into()).1, 2);
+
+    // already patched content only gets bypass paths put back
+    let input = "https://cdn.celeste.gg/assets/y.png https://media.celeste.gg/detectables/z.json";
+    let (patched, matches) = patch.apply_counted(input.into());
+    assert_eq!(patched, "https://cdn.discordapp.com/assets/y.png https://media.discordapp.net/detectables/z.json");
+    assert_eq!(matches, 0);
+}
@@ -0,0 +1,198 @@
+use std::sync::Arc;
+use std::time::Duration;
+use ug2_client::cache::memory_store::MemoryStore;
+use ug2_client::config::{AppConfig, PatchConfig};
+use ug2_client::server::jobs::{self, BuildBusy, DownloadSpec, Enqueued, JobEvent, JobKind, JobState};
+use ug2_client::server::state::AppState;
+
+#[test]
+fn test_job_state_names_match_serde() {
+    let states = [
+        JobState::Queued,
+        JobState::Downloading,
+        JobState::DetectingEntries,
+        JobState::Patching,
+        JobState::Done,
+        JobState::Failed,
+        JobState::Cancelled,
+    ];
+    for state in states {
+        assert_eq!(serde_json::to_value(state).unwrap(), state.as_str());
+    }
+    assert_eq!(JobState::DetectingEntries.as_str(), "detecting_entries");
+}
+
+#[test]
+fn test_finished_states() {
+    assert!(JobState::Done.is_finished());
+    assert!(JobState::Failed.is_finished());
+    assert!(JobState::Cancelled.is_finished());
+    assert!(!JobState::Patching.is_finished());
+    assert!(!JobState::Queued.is_finished());
+}
+
+#[test]
+fn test_job_kinds() {
+    assert_eq!(JobKind::Download.as_str(), "download");
+    assert_eq!(JobKind::FetchCurrent.as_str(), "fetch_current");
+}
+
+#[test]
+fn test_job_state_parse() {
+    assert_eq!(JobState::parse("detecting_entries"), Some(JobState::DetectingEntries));
+    assert_eq!(JobState::parse("done"), Some(JobState::Done));
+    assert_eq!(JobState::parse("bogus"), None);
+}
+
+#[test]
+fn test_job_events_are_tagged() {
+    let progress = JobEvent::Progress { queued: 3, downloaded: 5, failed: 1 };
+    assert_eq!(progress.name(), "progress");
+    assert_eq!(
+        serde_json::to_value(&progress).unwrap(),
+        serde_json::json!({"type": "progress", "queued": 3, "downloaded": 5, "failed": 1})
+    );
+
+    let done = JobEvent::State { state: JobState::Done, error: None };
+    assert_eq!(done.name(), "state");
+    assert_eq!(serde_json::to_value(&done).unwrap(), serde_json::json!({"type": "state", "state": "done"}));
+
+    let failed = JobEvent::State { state: JobState::Failed, error: Some("boom".into()) };
+    assert_eq!(serde_json::to_value(&failed).unwrap()["error"], "boom");
+
+    let asset = JobEvent::AssetFailed { asset: "a.js".into(), error: "404".into() };
+    assert_eq!(asset.name(), "asset_failed");
+    assert_eq!(serde_json::to_value(&asset).unwrap()["type"], "asset_failed");
+}
+
+/// serves `/assets/*`, names starting with `slow` never finish
+async fn asset_server() -> String {
+    async fn asset(axum::extract::Path(name): axum::extract::Path<String>) -> String {
+        if name.starts_with("slow") {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+        "console.log(1);".to_string()
+    }
+    let app = axum::Router::new().route("/assets/{name}", axum::routing::get(asset));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+async fn test_state(name: &str) -> AppState {
+    let cache_path = std::env::temp_dir().join(format!("ug2-jobs-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache_path);
+    std::fs::create_dir_all(&cache_path).unwrap();
+    let patch_config: PatchConfig = toml::from_str(include_str!("../patch_config.toml")).unwrap();
+    let config = AppConfig {
+        database_url: "sqlite::memory:".into(),
+        redis_url: String::new(),
+        bind_addr: "127.0.0.1:0".into(),
+        admin_bind_addr: None,
+        api_base_url: "http://127.0.0.1:9".into(),
+        discord_base_url: "http://127.0.0.1:9".into(),
+        asset_base_url: asset_server().await,
+        github_builds_repo: String::new(),
+        cache_path,
+        admin_token: None,
+        patch_config,
+    };
+    let db = ug2_client::db::connect("sqlite::memory:").await.unwrap();
+    ug2_client::db::run_migrations(&db).await.unwrap();
+    AppState::new(config, db, Arc::new(MemoryStore::default()), None).unwrap()
+}
+
+fn spec(build_hash: &str, scripts: &[&str]) -> DownloadSpec {
+    DownloadSpec {
+        kind: JobKind::Download,
+        build_hash: build_hash.into(),
+        channel: "stable".into(),
+        scripts: scripts.iter().map(|s| s.to_string()).collect(),
+        global_env: serde_json::json!({}),
+        timestamp: 1_700_000_000_000,
+        entry_scripts: Some(vec![scripts[0].to_string()]),
+    }
+}
+
+async fn next_state(events: &mut tokio::sync::broadcast::Receiver<JobEvent>) -> Option<JobState> {
+    loop {
+        match tokio::time::timeout(Duration::from_secs(30), events.recv()).await.unwrap() {
+            Ok(JobEvent::State { state, .. }) => return Some(state),
+            Ok(_) => continue,
+            Err(_) => return None,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_job_runs_through_its_states() {
+    let state = test_state("lifecycle").await;
+    let Enqueued::Started(job_id) = jobs::enqueue(&state, spec("lifecycle", &["a.js", "b.js"])).await.unwrap() else {
+        panic!("build was not free");
+    };
+    let mut events = state.jobs.subscribe(job_id).unwrap();
+
+    let mut seen = Vec::new();
+    while let Some(job_state) = next_state(&mut events).await {
+        seen.push(job_state);
+        if job_state.is_finished() {
+            break;
+        }
+    }
+    assert_eq!(
+        seen,
+        [JobState::Downloading, JobState::DetectingEntries, JobState::Patching, JobState::Done]
+    );
+    assert!(state.fs_cache.build_exists("lifecycle"));
+    // the build is free again
+    state.task_tracker.close();
+    state.task_tracker.wait().await;
+    assert_eq!(state.jobs.busy("lifecycle").await, None);
+}
+
+#[tokio::test]
+async fn test_job_cancellation() {
+    let state = test_state("cancel").await;
+    let Enqueued::Started(job_id) = jobs::enqueue(&state, spec("cancel", &["slow.js"])).await.unwrap() else {
+        panic!("build was not free");
+    };
+    let mut events = state.jobs.subscribe(job_id).unwrap();
+    assert_eq!(next_state(&mut events).await, Some(JobState::Downloading));
+
+    assert!(state.jobs.cancel(job_id));
+    assert_eq!(next_state(&mut events).await, Some(JobState::Cancelled));
+    state.task_tracker.close();
+    state.task_tracker.wait().await;
+    assert!(!state.jobs.cancel(job_id));
+    assert_eq!(state.jobs.busy("cancel").await, None);
+}
+
+#[tokio::test]
+async fn test_one_job_per_build() {
+    let state = test_state("busy").await;
+    let first = jobs::enqueue(&state, spec("busy", &["slow.js"])).await.unwrap();
+    let Enqueued::Started(job_id) = first else {
+        panic!("build was not free");
+    };
+    // a second download gets the running job, a repatch has to wait
+    let second = jobs::enqueue(&state, spec("busy", &["slow.js"])).await.unwrap();
+    assert_eq!(second, Enqueued::Busy(BuildBusy::Job(job_id)));
+    assert_eq!(state.jobs.begin_repatch("busy").await, Err(BuildBusy::Job(job_id)));
+    // other builds are unaffected
+    assert!(matches!(
+        jobs::enqueue(&state, spec("other", &["a.js"])).await.unwrap(),
+        Enqueued::Started(_)
+    ));
+
+    state.jobs.cancel(job_id);
+    state.task_tracker.close();
+    state.task_tracker.wait().await;
+    assert!(state.jobs.begin_repatch("busy").await.is_ok());
+    assert_eq!(
+        jobs::enqueue(&state, spec("busy", &["a.js"])).await.unwrap(),
+        Enqueued::Busy(BuildBusy::Repatch)
+    );
+    state.jobs.end_repatch("busy").await;
+    assert_eq!(state.jobs.busy("busy").await, None);
+}
@@ -0,0 +1,71 @@
+use ug2_client::asset_downloader::{AssetDownloader, AssetStatus, BuildManifest};
+use ug2_client::cache::filesystem::DOWNLOADING_MARKER;
+use ug2_client::cache::FsCache;
+
+fn temp_cache(name: &str) -> std::path::PathBuf {
+    let base = std::env::temp_dir().join(format!("ug2-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(&base).unwrap();
+    base
+}
+
+#[tokio::test]
+async fn test_manifest_round_trip() {
+    let base = temp_cache("manifest");
+    assert!(BuildManifest::load(&base).await.unwrap().is_none());
+
+    let mut manifest = BuildManifest::default();
+    assert!(manifest.track("web.js", None));
+    assert!(manifest.track("chunk.js", Some("web.js")));
+    assert!(manifest.track("logo.png", Some("web.js")));
+    manifest.set("web.js", AssetStatus::Downloaded);
+    manifest.set("logo.png", AssetStatus::Failed);
+    // tracking again leaves the status alone
+    assert!(!manifest.track("web.js", None));
+    manifest.save(&base).await.unwrap();
+
+    let loaded = BuildManifest::load(&base).await.unwrap().unwrap();
+    assert_eq!(loaded.status("web.js"), Some(AssetStatus::Downloaded));
+    assert_eq!(loaded.unfinished(), vec!["chunk.js".to_string(), "logo.png".to_string()]);
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[tokio::test]
+async fn test_download_resumes_from_manifest() {
+    let base = temp_cache("resume");
+    let build_dir = base.join("abc");
+    std::fs::create_dir_all(&build_dir).unwrap();
+    std::fs::write(build_dir.join(DOWNLOADING_MARKER), b"").unwrap();
+    let mut manifest = BuildManifest::default();
+    manifest.track("web.js", None);
+    manifest.set("web.js", AssetStatus::Downloaded);
+    manifest.save(&build_dir).await.unwrap();
+
+    let cache = FsCache::new(base.clone());
+    assert!(!cache.build_exists("abc"));
+
+    // nothing is left to fetch, so the unreachable host is never contacted
+    let downloader = AssetDownloader::new(base.clone(), "http://127.0.0.1:1");
+    let downloaded = downloader.download_build("abc", &["web".to_string()]).await.unwrap();
+    assert_eq!(downloaded, vec!["web.js".to_string()]);
+    assert!(cache.build_exists("abc"));
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[tokio::test]
+async fn test_cancelled_download_stays_incomplete() {
+    let base = temp_cache("cancel");
+    let cancel = tokio_util::sync::CancellationToken::new();
+    cancel.cancel();
+
+    let downloader = AssetDownloader::new(base.clone(), "http://127.0.0.1:1").with_cancellation(cancel);
+    assert!(downloader.download_build("abc", &["web.js".to_string()]).await.is_err());
+
+    assert!(!FsCache::new(base.clone()).build_exists("abc"));
+    let manifest = BuildManifest::load(&base.join("abc")).await.unwrap().unwrap();
+    assert_eq!(manifest.status("web.js"), Some(AssetStatus::Pending));
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,60 @@
+use ug2_client::config::ModalFamily;
+use ug2_client::patcher::Patch;
+use ug2_client::patcher::patches::modals::*;
+
+#[test]
+fn test_remove_nitro_upsell() {
+    let patch = RemoveNitroUpsellModals;
+    let input = r#"o.Z.dispatch({type:"PREMIUM_UPSELL_MODAL_OPEN",analyticsSource:e})"#;
+    let result = patch.apply(input.into());
+    assert_eq!(result, r#"o.Z.dispatch({type:"UG2_SUPPRESSED_PREMIUM_UPSELL_MODAL_OPEN",analyticsSource:e})"#);
+}
+
+#[test]
+fn test_remove_nitro_upsell_keeps_handlers() {
+    let patch = RemoveNitroUpsellModals;
+    let input = r#"PREMIUM_UPSELL_MODAL_OPEN:function(e){i=!0}"#;
+    assert_eq!(patch.apply(input.into()), input);
+}
+
+#[test]
+fn test_remove_nitro_upsell_keeps_other_actions() {
+    let patch = RemoveNitroUpsellModals;
+    let input = r#"a.Z.dispatch({type:"PREMIUM_PROMOTION_FETCH_SUCCESS",promotions:e}),a.Z.dispatch({type:"NITRO_UPSELL_DISMISS"})"#;
+    assert_eq!(patch.apply(input.into()), input);
+
+    let input = r#"a.Z.dispatch({type:"PREMIUM_SHOW_PROMOTION"})"#;
+    assert_eq!(patch.apply(input.into()), r#"a.Z.dispatch({type:"UG2_SUPPRESSED_PREMIUM_SHOW_PROMOTION"})"#);
+}
+
+#[test]
+fn test_remove_new_feature() {
+    let patch = RemoveNewFeatureModals;
+    let input = r#"a.Z.dispatch({type:"CHANGE_LOG_OPEN"}),a.Z.dispatch({type:"NEW_FEATURE_MODAL_SHOW",key:t})"#;
+    let result = patch.apply(input.into());
+    assert!(result.contains(r#"type:"UG2_SUPPRESSED_CHANGE_LOG_OPEN""#));
+    assert!(result.contains(r#"type:"UG2_SUPPRESSED_NEW_FEATURE_MODAL_SHOW""#));
+}
+
+#[test]
+fn test_remove_age_gate() {
+    let patch = RemoveAgeGateModals;
+    let input = r#"r.Z.dispatch({type:"AGE_GATE_MODAL_OPEN",source:n});r.Z.dispatch({type:"AGE_GATE_SUCCESS"})"#;
+    let result = patch.apply(input.into());
+    assert!(result.contains(r#"type:"UG2_SUPPRESSED_AGE_GATE_MODAL_OPEN""#));
+    assert!(result.contains(r#"type:"AGE_GATE_SUCCESS""#));
+}
+
+#[test]
+fn test_remove_verification_nag() {
+    let patch = RemoveAgeGateModals;
+    let input = r#"dispatch({type:"EMAIL_VERIFICATION_MODAL_OPEN"})"#;
+    assert_eq!(patch.apply(input.into()), r#"dispatch({type:"UG2_SUPPRESSED_EMAIL_VERIFICATION_MODAL_OPEN"})"#);
+}
+
+#[test]
+fn test_families_are_independent() {
+    let patch = for_family(ModalFamily::AgeGate);
+    let input = r#"dispatch({type:"PREMIUM_UPSELL_MODAL_OPEN"})"#;
+    assert_eq!(patch.apply(input.into()), input);
+}
@@ -0,0 +1,33 @@
+use ug2_client::config::ServerConfig;
+use ug2_client::server::offline::{MissingAssets, MAX_MISSING_PER_BUILD};
+
+#[test]
+fn test_ensure_online() {
+    let mut server = ServerConfig::default();
+    assert!(server.ensure_online("scrape Discord").is_ok());
+
+    server = toml::from_str("offline = true").unwrap();
+    let err = server.ensure_online("scrape Discord").unwrap_err();
+    assert_eq!(err.to_string(), "offline mode is on, refusing to scrape Discord");
+}
+
+#[test]
+fn test_missing_assets() {
+    let missing = MissingAssets::default();
+    assert!(missing.record("abc", "b.js"));
+    assert!(missing.record("abc", "a.js"));
+    assert!(!missing.record("abc", "a.js"));
+    assert!(missing.record("def", "a.js"));
+
+    let list = missing.list();
+    assert_eq!(list["abc"].iter().collect::<Vec<_>>(), ["a.js", "b.js"]);
+    assert_eq!(list["def"].len(), 1);
+
+    missing.clear("abc");
+    assert!(!missing.list().contains_key("abc"));
+
+    for i in 0..MAX_MISSING_PER_BUILD + 10 {
+        missing.record("ghi", &format!("{}.js", i));
+    }
+    assert_eq!(missing.list()["ghi"].len(), MAX_MISSING_PER_BUILD);
+}
@@ -0,0 +1,99 @@
+use regex::Regex;
+use ug2_client::config::PatchConfig;
+use ug2_client::patcher::patches::branding::*;
+use ug2_client::patcher::patches::features::*;
+use ug2_client::patcher::{Patch, PatchContext, PatchPipeline, PatchReport};
+
+#[test]
+fn test_context_counts_literal_replacements() {
+    let mut ctx = PatchContext::default();
+    let result = ctx.replace("a Discord b Discord".into(), "Discord", "X");
+    assert_eq!(result, "a X b X");
+    assert_eq!(ctx.matches(), 2);
+}
+
+#[test]
+fn test_context_counts_regex_replacements() {
+    let re = Regex::new(r"(\w+)=!1").unwrap();
+    let mut ctx = PatchContext::default();
+    let result = ctx.replace_regex("a=!1,b=!1,c=!0".into(), &re, "${1}=!0");
+    assert_eq!(result, "a=!0,b=!0,c=!0");
+    assert_eq!(ctx.matches(), 2);
+
+    let result = ctx.replace_regex("nothing here".into(), &re, "x");
+    assert_eq!(result, "nothing here");
+    assert_eq!(ctx.matches(), 2);
+}
+
+#[test]
+fn test_apply_counted() {
+    let (result, count) = NitroRebranding::new("Underground").apply_counted("Get Nitro now, Nitro rocks".into());
+    assert_eq!(result, "Get Premium now, Premium rocks");
+    assert_eq!(count, 2);
+
+    let (_, count) = RemoveQrCodeLogin.apply_counted("no qr code here".into());
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn test_report_warns_on_zero_matches() {
+    let mut report = PatchReport::new();
+    report.record("a", 3);
+    report.record("a", 1);
+    report.record("a", 0);
+    report.record("b", 0);
+    report.finish();
+
+    assert_eq!(report.patches["a"].files_touched, 2);
+    assert_eq!(report.patches["a"].replacements, 4);
+    assert_eq!(report.warnings, vec!["patch b matched nothing".to_string()]);
+}
+
+#[tokio::test]
+async fn test_patch_build_report() {
+    let config: PatchConfig = toml::from_str(r#"
+patches_dir = "does-not-exist"
+
+[patches]
+nitro_rebranding = false
+discord_rebranding = true
+title_rebranding = false
+server_to_guild = false
+sentry_redirect = true
+status_page_redirect = false
+prevent_localstorage_deletion = false
+fast_identify = false
+gateway_reconnect = false
+remove_qr_login = false
+enable_dev_experiments = false
+remove_modals = false
+no_xss_warning = false
+vencord = false
+api_proxy = false
+
+[branding]
+instance_name = "Underground"
+instance_url = "http://localhost:5002"
+sentry_url = "https://sentry.example.com"
+status_url = "status.example.com"
+"#).unwrap();
+    let pipeline = PatchPipeline::new(&config).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("ug2-patch-report-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.js"), "Welcome to Discord ").unwrap();
+    std::fs::write(dir.join("b.js"), "Discord is great").unwrap();
+    std::fs::write(dir.join("c.css"), ".x{}").unwrap();
+    std::fs::write(dir.join("d.png"), "Discord ").unwrap();
+
+    let out = dir.join("out");
+    let report = pipeline.patch_build(&dir, &out, None).await.unwrap();
+    assert_eq!(report.files_scanned, 3);
+    assert_eq!(report.files_changed, 2);
+    assert_eq!(report.patches["discord_rebranding"].files_touched, 2);
+    assert_eq!(report.patches["sentry_redirect"].replacements, 0);
+    assert_eq!(report.warnings, vec!["patch sentry_redirect matched nothing".to_string()]);
+    assert_eq!(std::fs::read_to_string(out.join("d.png")).unwrap(), "Discord ");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
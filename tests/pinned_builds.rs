@@ -0,0 +1,48 @@
+use ug2_client::config::PatchConfig;
+use ug2_client::server::handlers::index::{apply_base, generate_index};
+use ug2_client::server::handlers::assets::is_valid_build_hash;
+
+#[test]
+fn test_build_hash_validation() {
+    assert!(is_valid_build_hash("f2a5c5bd0b7d5d7bd1d1c3d8a2cd0e5ab45a1f2e"));
+    assert!(!is_valid_build_hash(""));
+    assert!(!is_valid_build_hash(".."));
+    assert!(!is_valid_build_hash("abc%2F.."));
+}
+
+#[test]
+fn test_generated_index_under_prefix() {
+    let config: PatchConfig = toml::from_str(include_str!("../patch_config.toml")).unwrap();
+    let scripts = vec!["/assets/web.abc.js".to_string(), "/assets/app.css".to_string()];
+    let html = generate_index("abc", "/build/abc", &scripts, &config.branding, &config.patches, "");
+    assert!(html.contains(r#"PUBLIC_PATH: "/build/abc/assets/""#));
+    assert!(html.contains("ASSET_ENDPOINT: `//${location.host}/build/abc`"));
+    assert!(html.contains(r#"<script src="/build/abc/assets/web.abc.js" defer>"#));
+    assert!(html.contains(r#"href="/build/abc/assets/app.css""#));
+    assert!(!html.contains("sessionStorage"));
+
+    let html = generate_index("abc", "", &scripts, &config.branding, &config.patches, "");
+    assert!(html.contains(r#"PUBLIC_PATH: "/assets/""#));
+    assert!(html.contains(r#"<script src="/assets/web.abc.js" defer>"#));
+}
+
+#[test]
+fn test_cached_index_under_prefix() {
+    let cached = concat!(
+        "<html><head><script>window.GLOBAL_ENV = {ASSET_ENDPOINT: '//discord.com', PUBLIC_PATH: '/assets/'};</script>",
+        "<link rel=\"stylesheet\" href=\"/assets/app.css\"></head>",
+        "<body><script src=\"/assets/web.abc.js\" defer></script></body></html>",
+    );
+    let html = apply_base(cached, "/build/abc");
+    assert!(html.contains("PUBLIC_PATH: '/build/abc/assets/'"));
+    assert!(html.contains("ASSET_ENDPOINT: `//${location.host}/build/abc`"));
+    assert!(html.contains("href=\"/build/abc/assets/app.css\""));
+    assert!(html.contains("src=\"/build/abc/assets/web.abc.js\""));
+    assert!(html.contains("ug2_build"));
+
+    // the active build keeps its URLs, only the pin script is added
+    let html = apply_base(cached, "");
+    assert!(html.contains("PUBLIC_PATH: '/assets/'"));
+    assert!(html.contains("ASSET_ENDPOINT: '//discord.com'"));
+    assert!(html.starts_with("<html><head>\n    <script>"));
+}
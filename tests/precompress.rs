@@ -0,0 +1,81 @@
+use std::io::Read;
+use ug2_client::cache::precompress::{self, Encoding};
+use ug2_client::cache::FsCache;
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("ug2-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_negotiate_prefers_brotli() {
+    assert_eq!(precompress::negotiate("gzip, deflate, br"), vec![Encoding::Brotli, Encoding::Gzip]);
+    assert_eq!(precompress::negotiate("gzip;q=1.0, br;q=0.5"), vec![Encoding::Gzip, Encoding::Brotli]);
+    assert_eq!(precompress::negotiate("br;q=0, gzip"), vec![Encoding::Gzip]);
+    assert_eq!(precompress::negotiate("*"), vec![Encoding::Brotli, Encoding::Gzip]);
+    assert_eq!(precompress::negotiate("identity"), vec![]);
+    assert_eq!(precompress::negotiate(""), vec![]);
+}
+
+#[tokio::test]
+async fn test_precompress_dir_writes_siblings() {
+    let dir = temp_dir("precompress");
+    let script = "console.log('Welcome to Underground');\n".repeat(100);
+    std::fs::write(dir.join("web.js"), &script).unwrap();
+    std::fs::write(dir.join("tiny.js"), "1").unwrap();
+    std::fs::write(dir.join("logo.png"), vec![0u8; 4096]).unwrap();
+
+    assert_eq!(precompress::precompress_dir(&dir, None).await.unwrap(), 1);
+
+    let mut decoded = String::new();
+    brotli::Decompressor::new(std::fs::File::open(dir.join("web.js.br")).unwrap(), 4096)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, script);
+
+    decoded.clear();
+    flate2::read::GzDecoder::new(std::fs::File::open(dir.join("web.js.gz")).unwrap())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, script);
+
+    assert!(!dir.join("tiny.js.br").exists());
+    assert!(!dir.join("logo.png.gz").exists());
+    assert!(precompress::is_variant("web.js.br"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_put_asset_recompresses() {
+    let dir = temp_dir("put-asset-compress");
+    let cache = FsCache::new(dir.clone());
+    let patched = dir.join("abc").join("patched");
+    std::fs::create_dir_all(dir.join("abc").join("original")).unwrap();
+    std::fs::create_dir_all(&patched).unwrap();
+    std::fs::write(patched.join("web.js.br"), "stale").unwrap();
+    std::fs::write(patched.join("web.js.gz"), "stale").unwrap();
+
+    // an asset fetched on demand replaces the stale siblings with its own
+    let script = "console.log('fetched on demand');\n".repeat(100);
+    cache.put_asset("abc", "web.js", script.as_bytes()).await.unwrap();
+    let mut decoded = String::new();
+    brotli::Decompressor::new(std::fs::File::open(patched.join("web.js.br")).unwrap(), 4096)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, script);
+    decoded.clear();
+    flate2::read::GzDecoder::new(std::fs::File::open(patched.join("web.js.gz")).unwrap())
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, script);
+
+    // too small to be worth it, the old siblings are still dropped
+    std::fs::write(patched.join("tiny.js.gz"), "stale").unwrap();
+    cache.put_asset("abc", "tiny.js", b"1").await.unwrap();
+    assert!(!patched.join("tiny.js.gz").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,111 @@
+use ug2_client::config::PatchConfig;
+use ug2_client::patcher::profile::is_valid_profile_name;
+use ug2_client::patcher::PatchPipeline;
+
+#[test]
+fn test_profile_names() {
+    assert!(is_valid_profile_name("builds-2022"));
+    assert!(is_valid_profile_name("v1.2_legacy"));
+    assert!(!is_valid_profile_name(""));
+    assert!(!is_valid_profile_name("with space"));
+    assert!(!is_valid_profile_name("../etc"));
+    assert!(!is_valid_profile_name(&"a".repeat(65)));
+}
+
+#[test]
+fn test_profile_changes_pipeline() {
+    let base: PatchConfig = toml::from_str(include_str!("../patch_config.toml")).unwrap();
+    let profile = base
+        .with_overlay(&serde_json::json!({
+            "patches_dir": "does-not-exist",
+            "patches": { "discord_rebranding": false },
+            "branding": { "instance_name": "Legacy" },
+        }))
+        .unwrap();
+    assert!(base.patches.discord_rebranding);
+
+    let pipeline = PatchPipeline::new(&profile).unwrap();
+    let patched = pipeline.patch_content("web.js", "Welcome to Discord", None);
+    assert_eq!(patched, "Welcome to Discord");
+}
+
+#[tokio::test]
+async fn test_fetched_asset_respects_build_date() {
+    use sea_orm::{ActiveModelTrait, Set};
+    use std::sync::Arc;
+    use ug2_client::db::{self, models::discord_build};
+    use ug2_client::patcher::profile::{patch_fetched_asset, ProfileCache};
+
+    let dir = std::env::temp_dir().join(format!("ug2-dated-patches-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("dated.toml"),
+        "[[patches]]\nname = \"new_builds_only\"\nfind = \"oldFlag\"\nreplace = \"newFlag\"\nmin_build_date = \"2023-01-01\"\n",
+    )
+    .unwrap();
+    let base: PatchConfig = toml::from_str(include_str!("../patch_config.toml")).unwrap();
+    let config = base.with_overlay(&serde_json::json!({ "patches_dir": dir })).unwrap();
+    let pipeline = Arc::new(PatchPipeline::new(&config).unwrap());
+    let profiles = ProfileCache::default();
+
+    let db = db::connect("sqlite::memory:").await.unwrap();
+    db::run_migrations(&db).await.unwrap();
+    discord_build::ActiveModel {
+        build_hash: Set("old".into()),
+        channel: Set("canary".into()),
+        build_date: Set("2020-06-01T00:00:00+00:00".parse().unwrap()),
+        scripts: Set(serde_json::json!([])),
+        index_scripts: Set(serde_json::json!([])),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+
+    let patched = patch_fetched_asset(&db, &profiles, &config, &pipeline, "old", "a.js", "x=oldFlag").await;
+    assert_eq!(patched, "x=oldFlag");
+    // no date to go by for builds outside the database
+    let patched = patch_fetched_asset(&db, &profiles, &config, &pipeline, "pinned", "a.js", "x=oldFlag").await;
+    assert_eq!(patched, "x=newFlag");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_profile_cache() {
+    use sea_orm::{ActiveModelTrait, ActiveValue::Unchanged, EntityTrait, Set};
+    use std::sync::Arc;
+    use ug2_client::db::{self, models::patch_profile};
+    use ug2_client::patcher::profile::ProfileCache;
+
+    let db = db::connect("sqlite::memory:").await.unwrap();
+    db::run_migrations(&db).await.unwrap();
+    let saved = patch_profile::ActiveModel {
+        name: Set("legacy".into()),
+        config: Set(serde_json::json!({ "patches_dir": "does-not-exist", "branding": { "instance_name": "Legacy" } })),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await
+    .unwrap();
+
+    let base: PatchConfig = toml::from_str(include_str!("../patch_config.toml")).unwrap();
+    let profiles = ProfileCache::default();
+    let first = profiles.get(&db, &base, "legacy").await.unwrap();
+    assert_eq!(first.config.branding.instance_name, "Legacy");
+    assert!(Arc::ptr_eq(&first.pipeline, &profiles.get(&db, &base, "legacy").await.unwrap().pipeline));
+
+    patch_profile::Entity::update(patch_profile::ActiveModel {
+        id: Unchanged(saved.id),
+        config: Set(serde_json::json!({ "patches_dir": "does-not-exist", "branding": { "instance_name": "Renamed" } })),
+        ..Default::default()
+    })
+    .exec(&db)
+    .await
+    .unwrap();
+    // served from the cache until the profile is saved through the API
+    assert_eq!(profiles.get(&db, &base, "legacy").await.unwrap().config.branding.instance_name, "Legacy");
+    profiles.invalidate("legacy");
+    assert_eq!(profiles.get(&db, &base, "legacy").await.unwrap().config.branding.instance_name, "Renamed");
+    assert!(profiles.get(&db, &base, "missing").await.is_err());
+}
@@ -0,0 +1,109 @@
+use axum::http::HeaderMap;
+use ug2_client::config::{BucketLimit, ServerConfig};
+use ug2_client::cache::memory_store::MemoryStore;
+use ug2_client::server::rate_limit::{self, client_key, gcra, rate_limit_headers, Bucket, SHARED_IP_FACTOR};
+
+const LIMIT: BucketLimit = BucketLimit { requests: 3, window_secs: 3 };
+
+#[test]
+fn test_gcra_burst_then_refill() {
+    let mut tat = None;
+    for remaining in [2, 1, 0] {
+        let (decision, new_tat) = gcra(tat, 10_000, LIMIT);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, remaining);
+        tat = new_tat;
+    }
+    assert_eq!(tat, Some(13_000));
+
+    let (denied, new_tat) = gcra(tat, 10_000, LIMIT);
+    assert!(!denied.allowed);
+    assert_eq!(new_tat, None);
+    assert_eq!(denied.retry_after_ms, 1000);
+    assert_eq!(denied.reset_after_ms, 3000);
+
+    // one request frees up every second, not the whole window at once
+    let (decision, tat) = gcra(tat, 11_000, LIMIT);
+    assert!(decision.allowed);
+    assert_eq!(decision.remaining, 0);
+    assert!(!gcra(tat, 11_500, LIMIT).0.allowed);
+
+    // an old tat counts as a full bucket
+    let (decision, _) = gcra(tat, 60_000, LIMIT);
+    assert_eq!(decision.remaining, 2);
+}
+
+#[test]
+fn test_bucket_limits() {
+    let server = ServerConfig::default();
+    assert_eq!(Bucket::Proxy.limit(&server), BucketLimit { requests: 60, window_secs: 60 });
+    assert_eq!(Bucket::Downloads.limit(&server), server.rate_limits.downloads);
+
+    let server: ServerConfig = toml::from_str("[rate_limits]\nadmin = { requests = 5, window_secs = 10 }").unwrap();
+    assert_eq!(Bucket::Admin.limit(&server), BucketLimit { requests: 5, window_secs: 10 });
+    assert!(server.rate_limit_fail_open);
+}
+
+#[test]
+fn test_client_key() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(client_key(Bucket::Proxy, &headers, "1.2.3.4"), "rl:proxy:ip:1.2.3.4");
+
+    headers.insert("authorization", "discord-token".parse().unwrap());
+    let key = client_key(Bucket::Admin, &headers, "1.2.3.4");
+    assert!(key.starts_with("rl:admin:token:"));
+    assert!(!key.contains("discord-token"));
+    assert_eq!(key, client_key(Bucket::Admin, &headers, "5.6.7.8"));
+}
+
+#[test]
+fn test_rate_limit_headers() {
+    let (allowed, tat) = gcra(None, 10_000, LIMIT);
+    let headers = rate_limit_headers(Bucket::Proxy, &allowed, 1_700_000_000_000);
+    assert_eq!(headers["x-ratelimit-limit"], "3");
+    assert_eq!(headers["x-ratelimit-remaining"], "2");
+    assert_eq!(headers["x-ratelimit-reset-after"], "1.000");
+    assert_eq!(headers["x-ratelimit-reset"], "1700000001.000");
+    assert_eq!(headers["x-ratelimit-bucket"], "proxy");
+    assert!(!headers.contains_key("retry-after"));
+
+    let (_, tat) = gcra(tat, 10_000, LIMIT);
+    let (_, tat) = gcra(tat, 10_000, LIMIT);
+    let (denied, _) = gcra(tat, 10_200, LIMIT);
+    let headers = rate_limit_headers(Bucket::Proxy, &denied, 1_700_000_000_000);
+    assert_eq!(headers["retry-after"], "1");
+    assert_eq!(headers["x-ratelimit-scope"], "user");
+}
+
+#[tokio::test]
+async fn test_rotating_authorization_is_still_limited() {
+    let store = MemoryStore::default();
+    let server: ServerConfig = toml::from_str("rate_limit_requests = 3\nrate_limit_window_secs = 60").unwrap();
+    let request = |token: String| {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", token.parse().unwrap());
+        headers
+    };
+
+    // one header runs out of its own bucket
+    for _ in 0..3 {
+        assert!(rate_limit::check(&store, Bucket::Proxy, &request("steady".into()), "1.2.3.4", &server).await.unwrap().allowed);
+    }
+    assert!(!rate_limit::check(&store, Bucket::Proxy, &request("steady".into()), "1.2.3.4", &server).await.unwrap().allowed);
+
+    // a fresh header per request gets new client buckets, but not past the IP's
+    let mut allowed = 3;
+    for i in 0..100 {
+        if rate_limit::check(&store, Bucket::Proxy, &request(format!("token-{}", i)), "1.2.3.4", &server).await.unwrap().allowed {
+            allowed += 1;
+        }
+    }
+    // the denied request counted against the IP as well
+    assert_eq!(allowed, 3 * SHARED_IP_FACTOR - 1);
+    let denied = rate_limit::check(&store, Bucket::Proxy, &request("another".into()), "1.2.3.4", &server).await.unwrap();
+    assert!(!denied.allowed);
+    assert!(denied.retry_after_ms > 0);
+
+    // other addresses are unaffected
+    assert!(rate_limit::check(&store, Bucket::Proxy, &request("another".into()), "5.6.7.8", &server).await.unwrap().allowed);
+}
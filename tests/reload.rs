@@ -0,0 +1,28 @@
+use ug2_client::server::reload::fingerprint;
+
+#[test]
+fn test_fingerprint_tracks_patch_definitions() {
+    let dir = std::env::temp_dir().join(format!("ug2-reload-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let empty = fingerprint(&dir);
+    assert_eq!(empty.len(), 1);
+    assert!(empty[0].1.is_some(), "patch_config.toml should be found from the crate root");
+
+    std::fs::write(dir.join("legacy.toml"), "").unwrap();
+    let added = fingerprint(&dir);
+    assert_ne!(empty, added);
+    assert_eq!(added, fingerprint(&dir));
+
+    std::fs::remove_file(dir.join("legacy.toml")).unwrap();
+    assert_eq!(empty, fingerprint(&dir));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_fingerprint_without_patch_directory() {
+    let missing = std::env::temp_dir().join("ug2-reload-does-not-exist");
+    assert_eq!(fingerprint(&missing).len(), 1);
+}
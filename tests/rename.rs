@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use ug2_client::asset_downloader::extractor::rewrite_asset_refs;
+use ug2_client::asset_downloader::BuildManifest;
+use ug2_client::cache::content_hash::ContentHashes;
+use ug2_client::cache::FsCache;
+use ug2_client::config::PatchConfig;
+use ug2_client::patcher::rename::rename_tree;
+use ug2_client::patcher::PatchPipeline;
+
+const CHUNK: &str = "aaaaaaaaaaaaaaaaaaaa";
+
+fn pipeline(instance_name: &str) -> PatchPipeline {
+    let config: PatchConfig = toml::from_str(&format!(r#"
+patches_dir = "does-not-exist"
+
+[patches]
+nitro_rebranding = false
+discord_rebranding = true
+title_rebranding = false
+server_to_guild = false
+sentry_redirect = false
+status_page_redirect = false
+prevent_localstorage_deletion = false
+fast_identify = false
+gateway_reconnect = false
+remove_qr_login = false
+enable_dev_experiments = false
+remove_modals = false
+no_xss_warning = false
+vencord = false
+api_proxy = false
+content_hashed_names = true
+
+[branding]
+instance_name = "{}"
+instance_url = "http://localhost:5002"
+sentry_url = "https://sentry.example.com"
+status_url = "status.example.com"
+"#, instance_name)).unwrap();
+    PatchPipeline::new(&config).unwrap()
+}
+
+#[test]
+fn test_rewrite_asset_refs() {
+    let renames = HashMap::from([
+        (format!("{}.js", CHUNK), format!("{}.0123abcd.js", CHUNK)),
+        ("logo.png".to_string(), "logo.0123abcd.png".to_string()),
+    ]);
+    let runtime = format!(r#"{{12:"{}",34:"bbbbbbbbbbbbbbbbbbbb"}}; url("/assets/logo.png")"#, CHUNK);
+    assert_eq!(
+        rewrite_asset_refs(&runtime, &renames),
+        format!(r#"{{12:"{}.0123abcd",34:"bbbbbbbbbbbbbbbbbbbb"}}; url("/assets/logo.0123abcd.png")"#, CHUNK),
+    );
+}
+
+#[tokio::test]
+async fn test_repatch_renames_assets() {
+    let base = std::env::temp_dir().join(format!("ug2-rename-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    let cache = FsCache::new(base.clone());
+    let runtime = format!(r#"var chunks={{12:"{}"}};"#, CHUNK);
+    let mut manifest = BuildManifest::default();
+    for (name, data) in [("web.js", runtime.as_str()), (&format!("{}.js", CHUNK), "Welcome to Discord")] {
+        cache.put_original("abc", name, data.as_bytes()).await.unwrap();
+        manifest.record_download(name, data.len() as u64, String::new());
+    }
+    std::fs::create_dir_all(cache.build_dir("abc")).unwrap();
+    manifest.save(&cache.build_dir("abc")).await.unwrap();
+
+    pipeline("Underground").patch_cached_build(&cache, "abc", None).await.unwrap();
+    let manifest = BuildManifest::load(&cache.build_dir("abc")).await.unwrap().unwrap();
+    let chunk = manifest.served_name(&format!("{}.js", CHUNK)).to_string();
+    let web = manifest.served_name("web.js").to_string();
+    assert_ne!(chunk, format!("{}.js", CHUNK));
+    assert!(web.starts_with("web.") && web.ends_with(".js"));
+
+    let patched = cache.patched_dir("abc");
+    assert!(!patched.join("web.js").exists());
+    assert_eq!(std::fs::read_to_string(patched.join(&chunk)).unwrap(), "Welcome to Underground");
+    let served_runtime = std::fs::read_to_string(patched.join(&web)).unwrap();
+    assert_eq!(served_runtime, format!(r#"var chunks={{12:"{}"}};"#, chunk.trim_end_matches(".js")));
+
+    let hashes = ContentHashes::default();
+    assert!(hashes.get(&cache, "abc", &chunk).await.unwrap().immutable);
+    assert_eq!(hashes.renames(&cache, "abc").await.unwrap()["web.js"], web);
+
+    // another config gives every file a new name
+    pipeline("Overground").patch_cached_build(&cache, "abc", None).await.unwrap();
+    let manifest = BuildManifest::load(&cache.build_dir("abc")).await.unwrap().unwrap();
+    assert_ne!(manifest.served_name("web.js"), web);
+    assert!(!cache.patched_dir("abc").join(&chunk).exists());
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+/// renames `files` in a fresh directory, returns the new names and contents
+async fn rename_files(name: &str, files: &[(&str, &str)]) -> HashMap<String, (String, String)> {
+    let dir = std::env::temp_dir().join(format!("ug2-rename-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut hashes = HashMap::new();
+    for (file, content) in files {
+        std::fs::write(dir.join(file), content).unwrap();
+        hashes.insert(file.to_string(), ug2_client::asset_downloader::manifest::sha256_hex(content.as_bytes()));
+    }
+    let renames = rename_tree(&dir, "fingerprint", &hashes, &mut HashMap::new(), false).await.unwrap();
+    let renamed = renames
+        .into_iter()
+        .map(|(old, new)| {
+            let content = std::fs::read_to_string(dir.join(&new)).unwrap();
+            (old, (new, content))
+        })
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    renamed
+}
+
+#[tokio::test]
+async fn test_names_follow_final_content() {
+    let chunk = format!("{}.js", CHUNK);
+    let runtime = format!(r#"var chunks={{12:"{}"}};"#, CHUNK);
+    let before = rename_files("final-a", &[("web.js", &runtime), (&chunk, "Welcome to Discord")]).await;
+    let again = rename_files("final-b", &[("web.js", &runtime), (&chunk, "Welcome to Discord")]).await;
+    assert_eq!(before, again);
+
+    // only the chunk changed, but the runtime now points at its new name
+    let after = rename_files("final-c", &[("web.js", &runtime), (&chunk, "Welcome to Underground")]).await;
+    assert_ne!(before[&chunk].0, after[&chunk].0);
+    assert_ne!(before["web.js"].1, after["web.js"].1);
+    assert_ne!(before["web.js"].0, after["web.js"].0);
+}
+
+#[tokio::test]
+async fn test_files_referencing_each_other() {
+    let other = "bbbbbbbbbbbbbbbbbbbb";
+    let first = format!(r#"{{1:"{}"}}"#, other);
+    let second = format!(r#"{{2:"{}"}}"#, CHUNK);
+    let files = [(format!("{}.js", CHUNK), first), (format!("{}.js", other), second)];
+    let files: Vec<(&str, &str)> = files.iter().map(|(n, c)| (n.as_str(), c.as_str())).collect();
+    let renamed = rename_files("cycle", &files).await;
+
+    let (new_first, first_content) = &renamed[files[0].0];
+    let (new_second, second_content) = &renamed[files[1].0];
+    assert_eq!(first_content, &format!(r#"{{1:"{}"}}"#, new_second.trim_end_matches(".js")));
+    assert_eq!(second_content, &format!(r#"{{2:"{}"}}"#, new_first.trim_end_matches(".js")));
+}
@@ -0,0 +1,140 @@
+use ug2_client::asset_downloader::AssetDownloader;
+use ug2_client::cache::FsCache;
+use ug2_client::config::PatchConfig;
+use ug2_client::patcher::PatchPipeline;
+
+fn pipeline(instance_name: &str) -> PatchPipeline {
+    let config: PatchConfig = toml::from_str(&format!(r#"
+patches_dir = "does-not-exist"
+
+[patches]
+nitro_rebranding = false
+discord_rebranding = true
+title_rebranding = false
+server_to_guild = false
+sentry_redirect = false
+status_page_redirect = false
+prevent_localstorage_deletion = false
+fast_identify = false
+gateway_reconnect = false
+remove_qr_login = false
+enable_dev_experiments = false
+remove_modals = false
+no_xss_warning = false
+vencord = false
+api_proxy = false
+
+[branding]
+instance_name = "{}"
+instance_url = "http://localhost:5002"
+sentry_url = "https://sentry.example.com"
+status_url = "status.example.com"
+"#, instance_name)).unwrap();
+    PatchPipeline::new(&config).unwrap()
+}
+
+fn temp_cache(name: &str) -> (std::path::PathBuf, FsCache) {
+    let base = std::env::temp_dir().join(format!("ug2-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(&base).unwrap();
+    (base.clone(), FsCache::new(base))
+}
+
+#[tokio::test]
+async fn test_repatch_starts_from_originals() {
+    let (base, cache) = temp_cache("repatch");
+    cache.put_original("abc", "web.js", b"Welcome to Discord").await.unwrap();
+    cache.put_original("abc", "logo.png", b"Discord").await.unwrap();
+
+    pipeline("Underground").patch_cached_build(&cache, "abc", None).await.unwrap();
+    assert_eq!(cache.get_asset("abc", "web.js").await.unwrap().unwrap(), b"Welcome to Underground");
+
+    // a second run with a different name must not see the first run's output
+    let report = pipeline("Overground").patch_cached_build(&cache, "abc", None).await.unwrap();
+    assert_eq!(report.files_changed, 1);
+    assert_eq!(cache.get_asset("abc", "web.js").await.unwrap().unwrap(), b"Welcome to Overground");
+    assert_eq!(cache.get_asset("abc", "logo.png").await.unwrap().unwrap(), b"Discord");
+    assert_eq!(std::fs::read(cache.original_dir("abc").join("web.js")).unwrap(), b"Welcome to Discord");
+
+    // the previous generation is cleaned up once the new one is published
+    let generations = std::fs::read_dir(cache.build_dir("abc"))
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with("patched-"))
+        .count();
+    assert_eq!(generations, 1);
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[tokio::test]
+async fn test_repatch_is_idempotent() {
+    let (base, cache) = temp_cache("repatch-idempotent");
+    cache.put_original("abc", "web.js", b"Discord Discord").await.unwrap();
+
+    let pipeline = pipeline("Underground");
+    let first = pipeline.patch_cached_build(&cache, "abc", None).await.unwrap();
+    let second = pipeline.patch_cached_build(&cache, "abc", None).await.unwrap();
+    assert_eq!(
+        first.patches["discord_rebranding"].replacements,
+        second.patches["discord_rebranding"].replacements,
+    );
+    assert_eq!(cache.get_asset("abc", "web.js").await.unwrap().unwrap(), b"Underground Underground");
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[tokio::test]
+async fn test_legacy_build_cannot_be_repatched() {
+    let (base, cache) = temp_cache("repatch-legacy");
+    std::fs::create_dir_all(cache.build_dir("old")).unwrap();
+    std::fs::write(cache.build_dir("old").join("web.js"), "Welcome to Underground").unwrap();
+
+    assert!(!cache.has_originals("old"));
+    assert_eq!(cache.patched_dir("old"), cache.build_dir("old"));
+    assert!(pipeline("Underground").patch_cached_build(&cache, "old", None).await.is_err());
+    assert_eq!(cache.get_asset("old", "web.js").await.unwrap().unwrap(), b"Welcome to Underground");
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[tokio::test]
+async fn test_legacy_build_gets_originals_backfilled() {
+    let (base, cache) = temp_cache("repatch-backfill");
+    // another build already has the original in the store, nothing is fetched
+    let newer = cache.original_dir("new").join("web.js");
+    std::fs::create_dir_all(newer.parent().unwrap()).unwrap();
+    std::fs::write(&newer, "Welcome to Discord").unwrap();
+    let sha = ug2_client::asset_downloader::manifest::sha256_hex(b"Welcome to Discord");
+    cache.blob_store().adopt_original("web.js", &newer, &sha).await.unwrap();
+
+    std::fs::create_dir_all(cache.build_dir("old")).unwrap();
+    std::fs::write(cache.build_dir("old").join("web.js"), "Welcome to Underground").unwrap();
+    std::fs::write(cache.build_dir("old").join("web.js.br"), "br").unwrap();
+
+    let downloader = AssetDownloader::new(base.clone(), "http://127.0.0.1:9");
+    assert_eq!(downloader.backfill_originals("old").await.unwrap(), 1);
+    assert!(cache.has_originals("old"));
+    assert!(!cache.build_dir("old").join("web.js").exists());
+    // the old patched files are still served until the repatch
+    assert_eq!(cache.get_asset("old", "web.js").await.unwrap().unwrap(), b"Welcome to Underground");
+    assert!(cache.patched_dir("old").join("web.js.br").exists());
+
+    pipeline("Overground").patch_cached_build(&cache, "old", None).await.unwrap();
+    assert_eq!(cache.get_asset("old", "web.js").await.unwrap().unwrap(), b"Welcome to Overground");
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[tokio::test]
+async fn test_backfill_keeps_serving_when_originals_are_missing() {
+    let (base, cache) = temp_cache("repatch-backfill-missing");
+    std::fs::create_dir_all(cache.build_dir("old")).unwrap();
+    std::fs::write(cache.build_dir("old").join("gone.js"), "Welcome to Underground").unwrap();
+
+    let downloader = AssetDownloader::new(base.clone(), "http://127.0.0.1:9");
+    assert!(downloader.backfill_originals("old").await.is_err());
+    assert!(!cache.has_originals("old"));
+    assert_eq!(cache.get_asset("old", "gone.js").await.unwrap().unwrap(), b"Welcome to Underground");
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
@@ -0,0 +1,152 @@
+use ug2_client::config::PatchConfig;
+use ug2_client::patcher::sourcemap::{self, Mapping};
+use ug2_client::patcher::{PatchContext, PatchPipeline};
+
+fn pipeline(source_maps: &str) -> PatchPipeline {
+    renaming_pipeline(source_maps, false)
+}
+
+fn renaming_pipeline(source_maps: &str, content_hashed_names: bool) -> PatchPipeline {
+    let config: PatchConfig = toml::from_str(&format!(r#"
+patches_dir = "does-not-exist"
+
+[patches]
+nitro_rebranding = false
+discord_rebranding = true
+title_rebranding = false
+server_to_guild = false
+sentry_redirect = false
+status_page_redirect = false
+prevent_localstorage_deletion = false
+fast_identify = false
+gateway_reconnect = false
+remove_qr_login = false
+enable_dev_experiments = false
+remove_modals = false
+no_xss_warning = false
+vencord = false
+api_proxy = false
+source_maps = "{}"
+content_hashed_names = {}
+
+[branding]
+instance_name = "Underground"
+instance_url = "http://localhost:5002"
+sentry_url = "https://sentry.example.com"
+status_url = "status.example.com"
+"#, source_maps, content_hashed_names)).unwrap();
+    PatchPipeline::new(&config).unwrap()
+}
+
+fn mapping(line: usize, column: usize, source_column: i64) -> Mapping {
+    Mapping { line, column, source: Some((0, 0, source_column, None)) }
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("ug2-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_vlq_round_trip() {
+    assert_eq!(sourcemap::decode_vlq("AAgBC").unwrap(), vec![0, 0, 16, 1]);
+    assert_eq!(sourcemap::decode_vlq("D").unwrap(), vec![-1]);
+    assert!(sourcemap::decode_vlq("g").is_err());
+
+    let mappings = vec![
+        mapping(0, 0, 0),
+        Mapping { line: 0, column: 4, source: Some((1, 3, 7, Some(2))) },
+        Mapping { line: 0, column: 9, source: None },
+        mapping(2, 1, 5),
+    ];
+    let encoded = sourcemap::encode_mappings(&mappings);
+    assert_eq!(sourcemap::decode_mappings(&encoded).unwrap(), mappings);
+}
+
+#[test]
+fn test_rewrite_mappings_follows_replacements() {
+    let original = "var a=\"Discord\";\nb()";
+    let mut ctx = PatchContext::default();
+    let patched = ctx.replace(original.to_string(), "Discord", "Underground");
+    let passes = ctx.into_passes();
+
+    // `;` after the string, a column inside it, and the next line
+    let mappings = sourcemap::encode_mappings(&[mapping(0, 0, 0), mapping(0, 9, 9), mapping(0, 15, 15), mapping(1, 0, 0)]);
+    let rewritten = sourcemap::rewrite_mappings(original, &patched, &passes, &mappings).unwrap();
+    assert_eq!(
+        sourcemap::decode_mappings(&rewritten).unwrap(),
+        vec![mapping(0, 0, 0), mapping(0, 19, 15), mapping(1, 0, 0)],
+    );
+}
+
+#[tokio::test]
+async fn test_source_map_modes() {
+    let dir = temp_dir("sourcemaps-in");
+    let script = "var a=\"Hi Discord\";x();\n//# sourceMappingURL=web.js.map\n";
+    let mappings = sourcemap::encode_mappings(&[mapping(0, 0, 0), mapping(0, 19, 19)]);
+    std::fs::write(dir.join("web.js"), script).unwrap();
+    std::fs::write(
+        dir.join("web.js.map"),
+        format!(r#"{{"version":3,"sources":["web.ts"],"names":[],"mappings":"{}"}}"#, mappings),
+    )
+    .unwrap();
+
+    let rewritten = temp_dir("sourcemaps-rewrite");
+    pipeline("rewrite").patch_build(&dir, &rewritten, None).await.unwrap();
+    let map: serde_json::Value = serde_json::from_slice(&std::fs::read(rewritten.join("web.js.map")).unwrap()).unwrap();
+    assert_eq!(
+        sourcemap::decode_mappings(map["mappings"].as_str().unwrap()).unwrap(),
+        vec![mapping(0, 0, 0), mapping(0, 23, 19)],
+    );
+    assert_eq!(map["sources"][0], "web.ts");
+    // the original is untouched
+    assert!(std::fs::read_to_string(dir.join("web.js.map")).unwrap().contains(&mappings));
+
+    let stripped = temp_dir("sourcemaps-strip");
+    let report = pipeline("strip").patch_build(&dir, &stripped, None).await.unwrap();
+    assert_eq!(std::fs::read_to_string(stripped.join("web.js")).unwrap(), "var a=\"Hi Underground\";x();\n\n");
+    assert_eq!(report.patches["strip_source_maps"].replacements, 1);
+
+    for dir in [dir, rewritten, stripped] {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_source_maps_follow_renamed_references() {
+    let dir = temp_dir("sourcemaps-renamed-in");
+    let chunk = "aaaaaaaaaaaaaaaaaaaa";
+    let script = format!("var c={{12:\"{}\"}};var a=\"Hi Discord\";x();\n//# sourceMappingURL=web.js.map\n", chunk);
+    let call = script.find("x();").unwrap();
+    let mappings = sourcemap::encode_mappings(&[mapping(0, 0, 0), mapping(0, call, call as i64)]);
+    std::fs::write(dir.join("web.js"), &script).unwrap();
+    std::fs::write(dir.join(format!("{}.js", chunk)), "Welcome to Discord").unwrap();
+    std::fs::write(
+        dir.join("web.js.map"),
+        format!(r#"{{"version":3,"sources":["web.ts"],"names":[],"mappings":"{}"}}"#, mappings),
+    )
+    .unwrap();
+
+    let out = temp_dir("sourcemaps-renamed-out");
+    renaming_pipeline("rewrite", true).patch_build(&dir, &out, None).await.unwrap();
+    let web = std::fs::read_dir(&out)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .find(|n| n.starts_with("web.") && n.ends_with(".js") && n != "web.js")
+        .unwrap();
+    let served = std::fs::read_to_string(out.join(&web)).unwrap();
+    assert!(!served.contains(&format!("\"{}\"", chunk)));
+
+    // both the patch and the renamed chunk reference moved `x();`
+    let map: serde_json::Value = serde_json::from_slice(&std::fs::read(out.join("web.js.map")).unwrap()).unwrap();
+    assert_eq!(
+        sourcemap::decode_mappings(map["mappings"].as_str().unwrap()).unwrap(),
+        vec![mapping(0, 0, 0), mapping(0, served.find("x();").unwrap(), call as i64)],
+    );
+
+    for dir in [dir, out] {
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+use sea_orm::sea_query::OnConflict;
+use sea_orm::*;
+use ug2_client::cache::asset_index::{self, AssetRecord};
+use ug2_client::db::{self, models::{discord_build, download_job}};
+use ug2_client::server::auth::{self, Scope};
+use ug2_client::server::jobs;
+
+async fn memory_db() -> DatabaseConnection {
+    let db = db::connect("sqlite::memory:").await.unwrap();
+    db::run_migrations(&db).await.unwrap();
+    db
+}
+
+async fn insert_build(db: &DatabaseConnection, hash: &str) {
+    let build = discord_build::ActiveModel {
+        build_hash: Set(hash.to_string()),
+        channel: Set("canary".into()),
+        build_date: Set(chrono::Utc::now().fixed_offset()),
+        global_env: Set(Some(serde_json::json!({ "RELEASE_CHANNEL": "canary" }))),
+        scripts: Set(serde_json::json!(["a.js", "b.js"])),
+        index_scripts: Set(serde_json::json!([])),
+        ..Default::default()
+    };
+    discord_build::Entity::insert(build)
+        .on_conflict(OnConflict::column(discord_build::Column::BuildHash).do_nothing().to_owned())
+        .do_nothing()
+        .exec_without_returning(db)
+        .await
+        .unwrap();
+}
+
+#[test]
+fn test_sqlite_url() {
+    assert_eq!(db::sqlite_url("sqlite://ug2.db"), "sqlite://ug2.db?mode=rwc");
+    assert_eq!(db::sqlite_url("sqlite://ug2.db?cache=shared"), "sqlite://ug2.db?cache=shared&mode=rwc");
+    assert_eq!(db::sqlite_url("sqlite://ug2.db?mode=ro"), "sqlite://ug2.db?mode=ro");
+    assert_eq!(db::sqlite_url("sqlite::memory:"), "sqlite::memory:");
+    assert_eq!(db::sqlite_url("postgres://localhost/ug2"), "postgres://localhost/ug2");
+}
+
+#[tokio::test]
+async fn test_migrations_run_once() {
+    let db = memory_db().await;
+    db::run_migrations(&db).await.unwrap();
+    insert_build(&db, "abc").await;
+    insert_build(&db, "abc").await;
+
+    let build = discord_build::Entity::find().one(&db).await.unwrap().unwrap();
+    assert_eq!(build.scripts, serde_json::json!(["a.js", "b.js"]));
+    assert_eq!(build.global_env.unwrap()["RELEASE_CHANNEL"], "canary");
+    assert!(!build.is_active);
+    assert_eq!(build.missing_assets, 0);
+}
+
+#[tokio::test]
+async fn test_asset_usage() {
+    let db = memory_db().await;
+    insert_build(&db, "abc").await;
+    let assets = vec![
+        AssetRecord { asset_name: "a.js".into(), file_size: 100, is_patched: true },
+        AssetRecord { asset_name: "b.css".into(), file_size: 50, is_patched: false },
+    ];
+    asset_index::record_assets(&db, "abc", assets).await.unwrap();
+
+    let usage = asset_index::usage_by_build(&db).await.unwrap();
+    assert_eq!(usage.len(), 1);
+    assert_eq!((usage[0].assets, usage[0].patched_assets, usage[0].total_size), (2, 1, 150));
+    assert!(usage[0].last_accessed.is_some());
+    assert_eq!(asset_index::least_recently_used(&db, 10).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_fail_interrupted_jobs() {
+    let db = memory_db().await;
+    for (state, errors) in [("downloading", serde_json::json!(["x.js: 404"])), ("done", serde_json::json!([]))] {
+        download_job::ActiveModel {
+            build_hash: Set("abc".into()),
+            kind: Set("download".into()),
+            state: Set(state.into()),
+            errors: Set(errors),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+    }
+    jobs::fail_interrupted(&db).await.unwrap();
+
+    let jobs = download_job::Entity::find().order_by_asc(download_job::Column::Id).all(&db).await.unwrap();
+    assert_eq!(jobs[0].state, "failed");
+    assert_eq!(jobs[0].errors, serde_json::json!(["x.js: 404", "interrupted by a server restart"]));
+    assert!(jobs[0].finished_at.is_some());
+    assert_eq!(jobs[1].state, "done");
+}
+
+#[tokio::test]
+async fn test_api_keys() {
+    let db = memory_db().await;
+    let key = auth::create_key(&db, "ci", Scope::Read).await.unwrap();
+    assert_eq!(auth::resolve_scope(&db, None, &key).await.unwrap(), Some(Scope::Read));
+    assert!(auth::revoke_key(&db, "ci").await.unwrap());
+    assert_eq!(auth::resolve_scope(&db, None, &key).await.unwrap(), None);
+}
@@ -0,0 +1,119 @@
+use ug2_client::asset_downloader::manifest::sha256_hex;
+use ug2_client::cache::FsCache;
+use ug2_client::config::{PatchConfig, VencordConfig};
+use ug2_client::vencord::*;
+
+fn temp_cache(name: &str) -> (std::path::PathBuf, FsCache) {
+    let base = std::env::temp_dir().join(format!("ug2-vencord-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    std::fs::create_dir_all(&base).unwrap();
+    (base.clone(), FsCache::new(base.join("cache")))
+}
+
+fn patch_config(vencord: VencordConfig) -> PatchConfig {
+    let mut config: PatchConfig = toml::from_str(include_str!("../patch_config.toml")).unwrap();
+    config.vencord = vencord;
+    config
+}
+
+async fn bundle_server() -> String {
+    let app = axum::Router::new()
+        .route("/browser.js", axum::routing::get(|| async { "console.log('vencord');" }))
+        .route("/browser.css", axum::routing::get(|| async { ".vc{}" }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", addr)
+}
+
+#[test]
+fn test_injection_loads_bundle() {
+    let config = VencordConfig::default();
+    let html = generate_injection(&config, false, "/assets/");
+    assert!(html.contains(r#"<script src="/assets/vencord.js"></script>"#));
+    assert!(!html.contains("vencord.css"));
+    assert!(!html.contains("localStorage"));
+}
+
+#[test]
+fn test_injection_seeds_default_plugins() {
+    let config = VencordConfig {
+        settings_key: "EquicordSettings".into(),
+        plugins: vec!["NoTrack".into(), "MessageLogger".into()],
+        ..Default::default()
+    };
+    let html = generate_injection(&config, true, "/assets/");
+    assert!(html.contains(r#"var key = "EquicordSettings";"#));
+    assert!(html.contains(r#"["NoTrack","MessageLogger"]"#));
+    assert!(html.contains(r#"<link rel="stylesheet" href="/assets/vencord.css">"#));
+
+    // settings must be seeded before the bundle reads them
+    let seed = html.find("localStorage.setItem").unwrap();
+    let bundle = html.find("/assets/vencord.js").unwrap();
+    assert!(seed < bundle);
+}
+
+#[test]
+fn test_injection_follows_pinned_build_prefix() {
+    let config = VencordConfig::default();
+    let html = generate_injection(&config, true, "/build/abc/assets/");
+    assert!(html.contains(r#"<script src="/build/abc/assets/vencord.js"></script>"#));
+    assert!(html.contains(r#"href="/build/abc/assets/vencord.css""#));
+}
+
+#[test]
+fn test_bundle_files_are_recognized() {
+    assert!(is_bundle_file("vencord.js"));
+    assert!(is_bundle_file("vencord.css"));
+    assert!(!is_bundle_file("web.65877e3d81a538c8.js"));
+}
+
+#[tokio::test]
+async fn test_remote_bundle_needs_checksum() {
+    let (base, cache) = temp_cache("checksum");
+    let server = bundle_server().await;
+    let client = reqwest::Client::new();
+    let js_hash = sha256_hex(b"console.log('vencord');");
+
+    let mut vencord = VencordConfig {
+        bundle: format!("{}/browser.js", server),
+        stylesheet: None,
+        ..Default::default()
+    };
+    let err = install_bundle(&client, &patch_config(vencord.clone()), &cache, "abc").await.unwrap_err();
+    // the error shows the hash to pin
+    assert!(format!("{:#}", err).contains(&js_hash));
+    assert!(!cache.original_dir("abc").join(BUNDLE_JS).exists());
+
+    vencord.bundle_sha256 = Some("0".repeat(64));
+    assert!(install_bundle(&client, &patch_config(vencord.clone()), &cache, "abc").await.is_err());
+    assert!(!cache.original_dir("abc").join(BUNDLE_JS).exists());
+
+    vencord.bundle_sha256 = Some(js_hash.to_uppercase());
+    install_bundle(&client, &patch_config(vencord), &cache, "abc").await.unwrap();
+    assert_eq!(std::fs::read(cache.original_dir("abc").join(BUNDLE_JS)).unwrap(), b"console.log('vencord');");
+
+    std::fs::remove_dir_all(base).unwrap();
+}
+
+#[tokio::test]
+async fn test_failed_stylesheet_removes_old_one() {
+    let (base, cache) = temp_cache("stylesheet");
+    let client = reqwest::Client::new();
+    cache.put_original("abc", BUNDLE_JS, b"old bundle").await.unwrap();
+    cache.put_original("abc", BUNDLE_CSS, b".old{}").await.unwrap();
+
+    let bundle = base.join("browser.js");
+    std::fs::write(&bundle, "new bundle").unwrap();
+    let vencord = VencordConfig {
+        bundle: bundle.to_string_lossy().into_owned(),
+        stylesheet: Some(base.join("missing.css").to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+    install_bundle(&client, &patch_config(vencord), &cache, "abc").await.unwrap();
+
+    assert_eq!(std::fs::read(cache.original_dir("abc").join(BUNDLE_JS)).unwrap(), b"new bundle");
+    assert!(!cache.original_dir("abc").join(BUNDLE_CSS).exists());
+
+    std::fs::remove_dir_all(base).unwrap();
+}
@@ -0,0 +1,72 @@
+use ug2_client::asset_downloader::manifest::sha256_hex;
+use ug2_client::asset_downloader::verify::verify_build;
+use ug2_client::asset_downloader::{AssetStatus, BuildManifest};
+use ug2_client::cache::FsCache;
+use ug2_client::config::PatchConfig;
+use ug2_client::patcher::PatchPipeline;
+
+fn pipeline() -> PatchPipeline {
+    let mut config: PatchConfig = toml::from_str(include_str!("../patch_config.toml")).unwrap();
+    config.patches_dir = "does-not-exist".into();
+    PatchPipeline::new(&config).unwrap()
+}
+
+async fn cached_build(name: &str) -> (std::path::PathBuf, FsCache) {
+    let base = std::env::temp_dir().join(format!("ug2-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&base);
+    let cache = FsCache::new(base.clone());
+
+    let mut manifest = BuildManifest::default();
+    for (asset, data) in [("web.js", &b"Welcome to Discord"[..]), ("logo.png", b"png")] {
+        cache.put_original("abc", asset, data).await.unwrap();
+        manifest.track(asset, None);
+        manifest.record_download(asset, data.len() as u64, sha256_hex(data));
+    }
+    manifest.track("chunk.js", Some("web.js"));
+    manifest.set("chunk.js", AssetStatus::Failed);
+    manifest.save(&cache.build_dir("abc")).await.unwrap();
+
+    pipeline().patch_cached_build(&cache, "abc", None).await.unwrap();
+    (base, cache)
+}
+
+#[tokio::test]
+async fn test_patching_records_patched_hashes() {
+    let (base, cache) = cached_build("verify-hashes").await;
+
+    let manifest = BuildManifest::load(&cache.build_dir("abc")).await.unwrap().unwrap();
+    let web = &manifest.assets["web.js"];
+    assert_ne!(web.patched_sha256, web.sha256);
+    let served = cache.get_asset("abc", "web.js").await.unwrap().unwrap();
+    assert_eq!(web.patched_sha256.as_deref(), Some(sha256_hex(&served).as_str()));
+    // unchanged files are served as downloaded
+    let logo = &manifest.assets["logo.png"];
+    assert_eq!(logo.patched_sha256, logo.sha256);
+    assert_eq!(manifest.assets["chunk.js"].referenced_by.as_deref(), Some("web.js"));
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[tokio::test]
+async fn test_verify_reports_problems() {
+    let (base, cache) = cached_build("verify-problems").await;
+
+    let report = verify_build(&cache, "abc").await.unwrap();
+    assert_eq!(report.assets, 3);
+    assert_eq!(report.missing, vec!["chunk.js".to_string()]);
+    assert!(report.corrupted.is_empty() && report.corrupted_patched.is_empty());
+    assert!(!report.is_ok());
+
+    std::fs::write(cache.original_dir("abc").join("extra.js"), b"").unwrap();
+    // replaced rather than written through, the served hard link keeps the old content
+    std::fs::remove_file(cache.original_dir("abc").join("logo.png")).unwrap();
+    std::fs::write(cache.original_dir("abc").join("logo.png"), b"gif").unwrap();
+    cache.put_asset("abc", "web.js", b"tampered").await.unwrap();
+
+    let report = verify_build(&cache, "abc").await.unwrap();
+    assert_eq!(report.corrupted, vec!["logo.png".to_string()]);
+    assert_eq!(report.corrupted_patched, vec!["web.js".to_string()]);
+    assert_eq!(report.unreferenced, vec!["extra.js".to_string()]);
+
+    std::fs::remove_dir_all(&base).unwrap();
+}